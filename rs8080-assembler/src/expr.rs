@@ -0,0 +1,333 @@
+use crate::ErrorKind;
+
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) enum Token {
+    Number(i64),
+    Ident(String),
+    /// Quoted with `'` or `"`
+    Str(String),
+    /// Current address
+    Dollar,
+    Colon,
+    Comma,
+    /// One of `+ - * / ( )`
+    Op(char),
+}
+
+/// Splits a source line into tokens, stops at a `;` comment
+pub(crate) fn tokenize(line: &str) -> Result<Vec<Token>, ErrorKind> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            ';' => break,
+            _ if ch.is_whitespace() => {
+                chars.next();
+            }
+            ':' | ',' | '+' | '-' | '*' | '/' | '(' | ')' => {
+                chars.next();
+                tokens.push(match ch {
+                    ':' => Token::Colon,
+                    ',' => Token::Comma,
+                    _ => Token::Op(ch),
+                });
+            }
+            '\'' | '"' => {
+                chars.next();
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        // doubled quote stands for the quote itself
+                        Some(c) if c == ch && chars.peek() == Some(&ch) => {
+                            chars.next();
+                            s.push(c);
+                        }
+                        Some(c) if c == ch => break,
+                        Some(c) => s.push(c),
+                        None => return Err(ErrorKind::Syntax("unterminated string".into())),
+                    }
+                }
+                tokens.push(Token::Str(s));
+            }
+            '$' => {
+                chars.next();
+                let mut digits = String::new();
+                while let Some(c) = chars.peek().filter(|c| c.is_ascii_hexdigit()) {
+                    digits.push(*c);
+                    chars.next();
+                }
+                if digits.is_empty() {
+                    tokens.push(Token::Dollar);
+                } else {
+                    tokens.push(Token::Number(parse_radix(&digits, 16)?));
+                }
+            }
+            _ if ch.is_ascii_digit() => {
+                let word = take_word(&mut chars);
+                tokens.push(Token::Number(parse_number(&word)?));
+            }
+            _ if is_ident_start(ch) => {
+                tokens.push(Token::Ident(take_word(&mut chars)));
+            }
+            _ => return Err(ErrorKind::Syntax(format!("unexpected character '{}'", ch))),
+        }
+    }
+    Ok(tokens)
+}
+
+fn is_ident_start(ch: char) -> bool {
+    ch.is_ascii_alphabetic() || matches!(ch, '_' | '?' | '@' | '.')
+}
+
+fn take_word(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars
+        .peek()
+        .filter(|c| c.is_ascii_alphanumeric() || is_ident_start(**c))
+    {
+        word.push(*c);
+        chars.next();
+    }
+    word
+}
+
+/// `0FFH`, `0x10`, `1010B`, `17O`, `17Q`, `10D` or `10`
+fn parse_number(word: &str) -> Result<i64, ErrorKind> {
+    let upper = word.to_ascii_uppercase();
+    if let Some(hex) = upper.strip_prefix("0X") {
+        return parse_radix(hex, 16);
+    }
+    let (digits, radix) = match upper.as_bytes()[upper.len() - 1] {
+        b'H' => (&upper[..upper.len() - 1], 16),
+        b'B' => (&upper[..upper.len() - 1], 2),
+        b'O' | b'Q' => (&upper[..upper.len() - 1], 8),
+        b'D' => (&upper[..upper.len() - 1], 10),
+        _ => (upper.as_str(), 10),
+    };
+    parse_radix(digits, radix)
+}
+
+fn parse_radix(digits: &str, radix: u32) -> Result<i64, ErrorKind> {
+    i64::from_str_radix(digits, radix)
+        .ok()
+        .filter(|value| *value <= 0xFFFF_FFFF)
+        .ok_or_else(|| ErrorKind::Syntax(format!("invalid number '{}'", digits)))
+}
+
+/// Recursive descent evaluator, from the lowest precedence:
+/// `OR XOR`, `AND`, `NOT`, `+ -`, `* / MOD SHL SHR`, unary `- + HIGH LOW`
+pub(crate) struct Evaluator<'a, F: Fn(&str) -> Option<i64>> {
+    tokens: &'a [Token],
+    pos: usize,
+    /// Value of `$`
+    dollar: i64,
+    symbol: F,
+}
+
+impl<'a, F: Fn(&str) -> Option<i64>> Evaluator<'a, F> {
+    /// Evaluates whole `tokens` as one expression
+    pub fn eval(tokens: &'a [Token], dollar: i64, symbol: F) -> Result<i64, ErrorKind> {
+        let mut evaluator = Evaluator {
+            tokens,
+            pos: 0,
+            dollar,
+            symbol,
+        };
+        if tokens.is_empty() {
+            return Err(ErrorKind::Syntax("expected expression".into()));
+        }
+        let value = evaluator.or_expr()?;
+        match evaluator.tokens.get(evaluator.pos) {
+            None => Ok(value),
+            Some(token) => Err(ErrorKind::Syntax(format!("unexpected {:?}", token))),
+        }
+    }
+
+    fn next_keyword(&self, keywords: &[&str]) -> Option<usize> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Ident(word)) => keywords.iter().position(|k| word.eq_ignore_ascii_case(k)),
+            _ => None,
+        }
+    }
+
+    fn next_op(&self, ops: &[char]) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => Some(*op),
+            _ => None,
+        }
+    }
+
+    fn or_expr(&mut self) -> Result<i64, ErrorKind> {
+        let mut value = self.and_expr()?;
+        while let Some(k) = self.next_keyword(&["OR", "XOR"]) {
+            self.pos += 1;
+            let rhs = self.and_expr()?;
+            value = if k == 0 { value | rhs } else { value ^ rhs };
+        }
+        Ok(value)
+    }
+
+    fn and_expr(&mut self) -> Result<i64, ErrorKind> {
+        let mut value = self.not_expr()?;
+        while self.next_keyword(&["AND"]).is_some() {
+            self.pos += 1;
+            value &= self.not_expr()?;
+        }
+        Ok(value)
+    }
+
+    fn not_expr(&mut self) -> Result<i64, ErrorKind> {
+        if self.next_keyword(&["NOT"]).is_some() {
+            self.pos += 1;
+            return Ok(!self.not_expr()? & 0xFFFF);
+        }
+        self.add_expr()
+    }
+
+    fn add_expr(&mut self) -> Result<i64, ErrorKind> {
+        let mut value = self.mul_expr()?;
+        while let Some(op) = self.next_op(&['+', '-']) {
+            self.pos += 1;
+            let rhs = self.mul_expr()?;
+            value = if op == '+' {
+                value.wrapping_add(rhs)
+            } else {
+                value.wrapping_sub(rhs)
+            };
+        }
+        Ok(value)
+    }
+
+    fn mul_expr(&mut self) -> Result<i64, ErrorKind> {
+        let mut value = self.unary()?;
+        loop {
+            let op = match (
+                self.next_op(&['*', '/']),
+                self.next_keyword(&["MOD", "SHL", "SHR"]),
+            ) {
+                (Some(op), _) => op,
+                (_, Some(0)) => '%',
+                (_, Some(1)) => '<',
+                (_, Some(_)) => '>',
+                _ => return Ok(value),
+            };
+            self.pos += 1;
+            let rhs = self.unary()?;
+            value = match op {
+                '*' => value.wrapping_mul(rhs),
+                '/' | '%' if rhs == 0 => return Err(ErrorKind::DivisionByZero),
+                '/' => value.wrapping_div(rhs),
+                '%' => value.wrapping_rem(rhs),
+                '<' => value.wrapping_shl(rhs as u32),
+                _ => value.wrapping_shr(rhs as u32),
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<i64, ErrorKind> {
+        if let Some(op) = self.next_op(&['+', '-']) {
+            self.pos += 1;
+            let value = self.unary()?;
+            return Ok(if op == '-' {
+                value.wrapping_neg()
+            } else {
+                value
+            });
+        }
+        if let Some(k) = self.next_keyword(&["HIGH", "LOW"]) {
+            self.pos += 1;
+            let value = self.unary()?;
+            return Ok(if k == 0 {
+                (value >> 8) & 0xFF
+            } else {
+                value & 0xFF
+            });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<i64, ErrorKind> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| ErrorKind::Syntax("unexpected end of expression".into()))?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(*n),
+            Token::Dollar => Ok(self.dollar),
+            Token::Ident(name) => {
+                (self.symbol)(name).ok_or_else(|| ErrorKind::UndefinedSymbol(name.clone()))
+            }
+            Token::Str(s) => match s.as_bytes() {
+                [ch] => Ok(*ch as i64),
+                [hi, lo] => Ok((*hi as i64) << 8 | *lo as i64),
+                _ => Err(ErrorKind::Syntax(format!(
+                    "string '{}' cannot be used as a value",
+                    s
+                ))),
+            },
+            Token::Op('(') => {
+                let value = self.or_expr()?;
+                if self.next_op(&[')']).is_none() {
+                    return Err(ErrorKind::Syntax("expected ')'".into()));
+                }
+                self.pos += 1;
+                Ok(value)
+            }
+            _ => Err(ErrorKind::Syntax(format!("unexpected {:?}", token))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<i64, ErrorKind> {
+        let tokens = tokenize(expr).unwrap();
+        Evaluator::eval(&tokens, 0x100, |name| (name == "START").then_some(0x1234))
+    }
+
+    #[test]
+    fn expr_numbers() {
+        assert_eq!(Ok(0xFF), eval("0FFH"));
+        assert_eq!(Ok(0xFF), eval("0ffh"));
+        assert_eq!(Ok(0x1A), eval("$1A"));
+        assert_eq!(Ok(0x1A), eval("0x1A"));
+        assert_eq!(Ok(5), eval("101B"));
+        assert_eq!(Ok(15), eval("17Q"));
+        assert_eq!(Ok(15), eval("17o"));
+        assert_eq!(Ok(42), eval("42"));
+        assert_eq!(Ok(0x41), eval("'A'"));
+        assert_eq!(Ok(0x4142), eval("'AB'"));
+        assert_eq!(Ok(0x27), eval("''''"));
+        assert!(tokenize("0FFX").is_err());
+    }
+
+    #[test]
+    fn expr_operators() {
+        assert_eq!(Ok(7), eval("1 + 2 * 3"));
+        assert_eq!(Ok(9), eval("(1 + 2) * 3"));
+        assert_eq!(Ok(0x105), eval("$ + 5"));
+        assert_eq!(Ok(0x12), eval("HIGH START"));
+        assert_eq!(Ok(0x34), eval("LOW START"));
+        assert_eq!(Ok(0x13), eval("HIGH START + 1"));
+        assert_eq!(Ok(-2), eval("-2"));
+        assert_eq!(Ok(1), eval("7 MOD 3"));
+        assert_eq!(Ok(0x10), eval("1 SHL 4"));
+        assert_eq!(Ok(0xFFFE), eval("NOT 1"));
+        assert_eq!(Ok(0x0F), eval("0FFH AND 0FH"));
+        assert_eq!(Ok(0xF0), eval("0FFH XOR 0FH"));
+        assert_eq!(Ok(0x1F), eval("10H OR 0FH"));
+        assert_eq!(Err(ErrorKind::DivisionByZero), eval("1 / 0"));
+        // i64 overflow wraps like `*` and `SHL`, the range check reports it
+        assert_eq!(Ok(i64::MIN), eval("(1 SHL 62) + (1 SHL 62)"));
+        assert_eq!(Ok(i64::MIN), eval("-(1 SHL 63)"));
+        assert_eq!(Ok(i64::MAX), eval("-(1 SHL 63) - 1"));
+        assert_eq!(Ok(i64::MIN), eval("(1 SHL 63) / -1"));
+        assert_eq!(Ok(0), eval("(1 SHL 63) MOD -1"));
+        assert_eq!(Err(ErrorKind::UndefinedSymbol("END".into())), eval("END"));
+        assert!(eval("(1").is_err());
+        assert!(eval("1 2").is_err());
+    }
+}
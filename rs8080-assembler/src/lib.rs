@@ -0,0 +1,544 @@
+//! Two-pass assembler for Intel 8080 mnemonics.
+//!
+//! Source is line based: `[label:] [mnemonic|directive [operands]] [; comment]`.
+//! Supported directives are `ORG`, `EQU`, `DB`, `DW`, `DS` and `END`.
+//! Operands are expressions of numbers (`10`, `0FFH`, `$FF`, `0x10`, `1010B`, `17Q`),
+//! symbols, `$` (address of the current line), character constants and
+//! `+ - * / MOD SHL SHR NOT AND OR XOR HIGH LOW` with parentheses.
+//! Mnemonics, registers and keywords are case-insensitive, symbols are not.
+//! `EQU` can only refer to symbols defined above it.
+use rs8080_disassembler::Cmd;
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+mod expr;
+mod opcode;
+
+use expr::{tokenize, Evaluator, Token};
+use opcode::{OpcodeTable, Operand};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Syntax(String),
+    UnknownMnemonic(String),
+    InvalidOperands(String),
+    UndefinedSymbol(String),
+    DuplicateSymbol(String),
+    ValueOutOfRange(i64),
+    DivisionByZero,
+    /// Code goes past 0xFFFF
+    AddressOverflow,
+    /// Address is written by two lines
+    Overlap(u16),
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Syntax(msg) => write!(f, "syntax error: {}", msg),
+            ErrorKind::UnknownMnemonic(name) => write!(f, "unknown mnemonic '{}'", name),
+            ErrorKind::InvalidOperands(name) => write!(f, "invalid operands for '{}'", name),
+            ErrorKind::UndefinedSymbol(name) => write!(f, "undefined symbol '{}'", name),
+            ErrorKind::DuplicateSymbol(name) => write!(f, "symbol '{}' is already defined", name),
+            ErrorKind::ValueOutOfRange(value) => write!(f, "value {} is out of range", value),
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::AddressOverflow => write!(f, "address is past 0FFFFH"),
+            ErrorKind::Overlap(adr) => write!(f, "address {:04X}H is already used", adr),
+        }
+    }
+}
+
+/// Error with the 1-based source line number
+#[derive(Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// One source line with the bytes it produced
+pub struct ListingLine {
+    pub line: usize,
+    /// Address of the line, value for `EQU`
+    pub address: Option<u16>,
+    pub bytes: Vec<u8>,
+    pub source: String,
+}
+
+/// Bytes shown on one row of the listing
+const LISTING_BYTES: usize = 4;
+
+impl Display for ListingLine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let address = match self.address {
+            Some(adr) => format!("{:04X}", adr),
+            None => String::new(),
+        };
+        let mut rows = self.bytes.chunks(LISTING_BYTES);
+        let first = rows.next().map(hex_bytes).unwrap_or_default();
+        write!(
+            f,
+            "{:>5}  {:4}  {:12} {}",
+            self.line, address, first, self.source
+        )?;
+        let mut adr = self.address.unwrap_or(0);
+        for row in rows {
+            adr = adr.wrapping_add(LISTING_BYTES as u16);
+            write!(f, "\n{:>5}  {:04X}  {}", "", adr, hex_bytes(row))?;
+        }
+        Ok(())
+    }
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Result of assembling
+pub struct Assembly {
+    /// Address of the first byte of `image`
+    pub origin: u16,
+    /// Assembled bytes, gaps between `ORG` blocks and `DS` are filled with zeroes
+    pub image: Vec<u8>,
+    pub symbols: BTreeMap<String, u16>,
+    pub listing: Vec<ListingLine>,
+}
+
+/// Parsed source line
+struct Line {
+    label: Option<String>,
+    /// Upper-cased mnemonic or directive
+    op: Option<String>,
+    operands: Vec<Vec<Token>>,
+}
+
+fn parse_line(tokens: Vec<Token>) -> Result<Line, ErrorKind> {
+    let mut rest = tokens.as_slice();
+    let mut label = None;
+    match rest {
+        [Token::Ident(name), Token::Colon, ..] => {
+            label = Some(name.clone());
+            rest = &rest[2..];
+        }
+        [Token::Ident(name), Token::Ident(equ), ..] if equ.eq_ignore_ascii_case("EQU") => {
+            label = Some(name.clone());
+            rest = &rest[1..];
+        }
+        _ => {}
+    }
+    let op = match rest.first() {
+        None => None,
+        Some(Token::Ident(op)) => Some(op.to_ascii_uppercase()),
+        Some(token) => return Err(ErrorKind::Syntax(format!("unexpected {:?}", token))),
+    };
+    let mut operands = Vec::new();
+    if rest.len() > 1 {
+        for operand in rest[1..].split(|t| *t == Token::Comma) {
+            if operand.is_empty() {
+                return Err(ErrorKind::Syntax("empty operand".into()));
+            }
+            operands.push(operand.to_vec());
+        }
+    }
+    Ok(Line {
+        label,
+        op,
+        operands,
+    })
+}
+
+struct Assembler {
+    table: OpcodeTable,
+    symbols: BTreeMap<String, u16>,
+    /// Address of the current line
+    pc: u32,
+}
+
+impl Assembler {
+    fn eval(&self, tokens: &[Token]) -> Result<i64, ErrorKind> {
+        Evaluator::eval(tokens, self.pc as i64, |name| {
+            self.symbols.get(name).map(|v| *v as i64)
+        })
+    }
+
+    fn eval8(&self, tokens: &[Token]) -> Result<u8, ErrorKind> {
+        match self.eval(tokens)? {
+            value @ -128..=255 => Ok(value as u8),
+            value => Err(ErrorKind::ValueOutOfRange(value)),
+        }
+    }
+
+    fn eval16(&self, tokens: &[Token]) -> Result<u16, ErrorKind> {
+        match self.eval(tokens)? {
+            value @ -32768..=65535 => Ok(value as u16),
+            value => Err(ErrorKind::ValueOutOfRange(value)),
+        }
+    }
+
+    fn define(&mut self, name: &str, value: u16) -> Result<(), ErrorKind> {
+        if Operand::register(name).is_some() {
+            return Err(ErrorKind::Syntax(format!(
+                "register '{}' cannot be a symbol",
+                name
+            )));
+        }
+        if self.symbols.contains_key(name) {
+            return Err(ErrorKind::DuplicateSymbol(name.to_owned()));
+        }
+        self.symbols.insert(name.to_owned(), value);
+        Ok(())
+    }
+
+    /// Handles one line, returns its bytes. The first pass only counts them,
+    /// so operands that may refer to labels below are not evaluated
+    fn line(&mut self, line: &Line, first_pass: bool) -> Result<Vec<u8>, ErrorKind> {
+        let op = match &line.op {
+            Some(op) => op.as_str(),
+            None => {
+                if let (Some(label), true) = (&line.label, first_pass) {
+                    self.define(label, self.pc as u16)?;
+                }
+                return Ok(Vec::new());
+            }
+        };
+        if op == "EQU" {
+            let name = line
+                .label
+                .as_ref()
+                .ok_or_else(|| ErrorKind::Syntax("EQU without a name".into()))?;
+            let value = self.eval16(self.single_operand(line, op)?)?;
+            if first_pass {
+                self.define(name, value)?;
+            }
+            return Ok(Vec::new());
+        }
+        if let (Some(label), true) = (&line.label, first_pass) {
+            self.define(label, self.pc as u16)?;
+        }
+
+        match op {
+            "ORG" => {
+                self.pc = self.eval16(self.single_operand(line, op)?)? as u32;
+                Ok(Vec::new())
+            }
+            "DS" => {
+                let size = self.eval16(self.single_operand(line, op)?)?;
+                self.pc += size as u32;
+                Ok(Vec::new())
+            }
+            "END" => Ok(Vec::new()),
+            "DB" => {
+                let mut bytes = Vec::new();
+                for operand in &line.operands {
+                    match operand.as_slice() {
+                        [Token::Str(s)] if s.len() != 1 => bytes.extend_from_slice(s.as_bytes()),
+                        _ if first_pass => bytes.push(0),
+                        _ => bytes.push(self.eval8(operand)?),
+                    }
+                }
+                Ok(bytes)
+            }
+            "DW" => {
+                let mut bytes = Vec::new();
+                for operand in &line.operands {
+                    let word = if first_pass { 0 } else { self.eval16(operand)? };
+                    bytes.extend_from_slice(&word.to_le_bytes());
+                }
+                Ok(bytes)
+            }
+            _ => self.instruction(line, op, first_pass),
+        }
+    }
+
+    fn single_operand<'a>(&self, line: &'a Line, op: &str) -> Result<&'a [Token], ErrorKind> {
+        match line.operands.as_slice() {
+            [operand] => Ok(operand),
+            _ => Err(ErrorKind::InvalidOperands(op.to_owned())),
+        }
+    }
+
+    fn instruction(&self, line: &Line, op: &str, first_pass: bool) -> Result<Vec<u8>, ErrorKind> {
+        let cmd = Cmd::from_str(op)
+            .ok()
+            .filter(|cmd| *cmd != Cmd::NOPU)
+            .ok_or_else(|| ErrorKind::UnknownMnemonic(op.to_owned()))?;
+        if cmd == Cmd::RST {
+            let n = if first_pass {
+                0
+            } else {
+                self.eval8(self.single_operand(line, op)?)?
+            };
+            if n > 7 {
+                return Err(ErrorKind::ValueOutOfRange(n as i64));
+            }
+            return Ok(vec![0xC7 | n << 3]);
+        }
+
+        let operands: Vec<Operand> = line
+            .operands
+            .iter()
+            .map(|operand| match operand.as_slice() {
+                [Token::Ident(name)] => Operand::register(name)
+                    .map(Operand::Register)
+                    .unwrap_or(Operand::Immediate),
+                _ => Operand::Immediate,
+            })
+            .collect();
+        let (opcode, size) = self
+            .table
+            .get(cmd, &operands)
+            .ok_or_else(|| ErrorKind::InvalidOperands(op.to_owned()))?;
+        let mut bytes = vec![opcode];
+        let value = operands.iter().position(|o| *o == Operand::Immediate);
+        match (size, value) {
+            (2, Some(_)) if first_pass => bytes.push(0),
+            (2, Some(i)) => bytes.push(self.eval8(&line.operands[i])?),
+            (3, Some(_)) if first_pass => bytes.extend_from_slice(&[0, 0]),
+            (3, Some(i)) => bytes.extend_from_slice(&self.eval16(&line.operands[i])?.to_le_bytes()),
+            _ => {}
+        }
+        Ok(bytes)
+    }
+}
+
+/// Assembles `source`, stops at the first error
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let text: Vec<&str> = source.lines().collect();
+    let lines = text
+        .iter()
+        .enumerate()
+        .map(|(i, text)| {
+            tokenize(text)
+                .and_then(parse_line)
+                .map_err(|kind| AsmError { line: i + 1, kind })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut asm = Assembler {
+        table: OpcodeTable::new(),
+        symbols: BTreeMap::new(),
+        pc: 0,
+    };
+    let mut listing = Vec::new();
+    for first_pass in [true, false] {
+        asm.pc = 0;
+        for (i, line) in lines.iter().enumerate() {
+            let error = |kind| AsmError { line: i + 1, kind };
+            let mut address = asm.pc;
+            let bytes = asm.line(line, first_pass).map_err(error)?;
+            if line.op.as_deref() == Some("ORG") {
+                address = asm.pc;
+            }
+            asm.pc += bytes.len() as u32;
+            if asm.pc > 0x10000 {
+                return Err(error(ErrorKind::AddressOverflow));
+            }
+            if !first_pass {
+                let address = match (&line.op, &line.label) {
+                    (Some(op), Some(name)) if op == "EQU" => asm.symbols.get(name).copied(),
+                    (None, None) => None,
+                    _ => Some(address as u16),
+                };
+                listing.push(ListingLine {
+                    line: i + 1,
+                    address,
+                    bytes,
+                    source: text[i].to_owned(),
+                });
+            }
+            if line.op.as_deref() == Some("END") {
+                break;
+            }
+        }
+    }
+
+    let (origin, image) = link(&listing)?;
+    Ok(Assembly {
+        origin,
+        image,
+        symbols: asm.symbols,
+        listing,
+    })
+}
+
+/// Places bytes of every line at its address
+fn link(listing: &[ListingLine]) -> Result<(u16, Vec<u8>), AsmError> {
+    let mut used = vec![false; 0x10000];
+    let blocks = listing
+        .iter()
+        .filter(|l| !l.bytes.is_empty())
+        .map(|l| (l, l.address.unwrap_or(0) as usize));
+    let start = blocks.clone().map(|(_, adr)| adr).min().unwrap_or(0);
+    let end = blocks
+        .clone()
+        .map(|(l, adr)| adr + l.bytes.len())
+        .max()
+        .unwrap_or(0);
+    let mut image = vec![0; end.saturating_sub(start)];
+    for (l, adr) in blocks {
+        for (i, byte) in l.bytes.iter().enumerate() {
+            if used[adr + i] {
+                return Err(AsmError {
+                    line: l.line,
+                    kind: ErrorKind::Overlap((adr + i) as u16),
+                });
+            }
+            used[adr + i] = true;
+            image[adr + i - start] = *byte;
+        }
+    }
+    Ok((start as u16, image))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use rs8080_disassembler::{disassemble, Argument, Command};
+
+    fn image(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().image
+    }
+
+    fn error(source: &str) -> ErrorKind {
+        assemble(source).err().unwrap().kind
+    }
+
+    #[test]
+    fn asm_instructions() {
+        let source = "
+        ; cpudiag patch
+                ORG 100H
+        START:  LXI SP, STACK
+                mvi a, 'A'
+                MOV M, A
+                JMP START
+                RST 7
+                DAD SP
+                POP PSW
+        STACK   EQU 2400H
+        ";
+        let asm = assemble(source).unwrap();
+        assert_eq!(0x100, asm.origin);
+        assert_eq!(
+            vec![0x31, 0x00, 0x24, 0x3E, 0x41, 0x77, 0xC3, 0x00, 0x01, 0xFF, 0x39, 0xF1],
+            asm.image
+        );
+        assert_eq!(Some(&0x100), asm.symbols.get("START"));
+        assert_eq!(Some(&0x2400), asm.symbols.get("STACK"));
+    }
+
+    #[test]
+    fn asm_directives() {
+        assert_eq!(
+            vec![b'H', b'I', 0, 0x34, 0x12, 0x04, 0x00, 0, 0, 0x08],
+            image("DB 'HI', 0\nDW 1234H, $ + 1\nDS 2\nDB $ - 1")
+        );
+        assert_eq!(
+            vec![1, 0, 0, 0, 2],
+            image("ORG 10\nDB 1\nORG 14\nDB 2\nEND\nDB 3")
+        );
+        assert_eq!(
+            vec![0x12, 0x34, 0x3E, 0x02],
+            image("DB HIGH NEXT, LOW NEXT\nNEXT EQU 1234H\nMVI A, TWO\nTWO EQU 2")
+        );
+        assert_eq!(vec![b'I', b'\'', b'M'], image("db 'I''M'"));
+    }
+
+    #[test]
+    fn asm_errors() {
+        assert_eq!(ErrorKind::UnknownMnemonic("FOO".into()), error("FOO A"));
+        assert_eq!(ErrorKind::UnknownMnemonic("NOPU".into()), error("NOPU"));
+        assert_eq!(ErrorKind::InvalidOperands("MOV".into()), error("MOV A"));
+        assert_eq!(ErrorKind::InvalidOperands("MOV".into()), error("MOV M, M"));
+        assert_eq!(ErrorKind::UndefinedSymbol("X".into()), error("JMP X"));
+        assert_eq!(
+            ErrorKind::UndefinedSymbol("Y".into()),
+            error("X EQU Y\nY EQU 1")
+        );
+        assert_eq!(
+            ErrorKind::DuplicateSymbol("LOOP".into()),
+            error("LOOP: NOP\nLOOP: NOP")
+        );
+        assert_eq!(ErrorKind::ValueOutOfRange(256), error("MVI A, 256"));
+        assert_eq!(ErrorKind::ValueOutOfRange(8), error("RST 8"));
+        assert_eq!(
+            ErrorKind::ValueOutOfRange(i64::MIN),
+            error("DW (1 SHL 62) + (1 SHL 62)")
+        );
+        assert_eq!(
+            ErrorKind::ValueOutOfRange(i64::MIN),
+            error("DW -(1 SHL 63)")
+        );
+        assert_eq!(ErrorKind::Overlap(1), error("DB 1, 2\nORG 1\nDB 3"));
+        assert_eq!(ErrorKind::AddressOverflow, error("ORG 0FFFFH\nDW 0"));
+        assert!(matches!(error("B: NOP"), ErrorKind::Syntax(_)));
+        let err = assemble("NOP\nMVI A,").err().unwrap();
+        assert_eq!(2, err.line);
+    }
+
+    #[test]
+    fn asm_listing() {
+        let asm = assemble("ORG 10H\nDATA: DB 1, 2, 3, 4, 5\n; comment\nX EQU 0AH").unwrap();
+        let listing: Vec<String> = asm.listing.iter().map(|l| l.to_string()).collect();
+        assert_eq!("    1  0010               ORG 10H", listing[0]);
+        assert_eq!(
+            "    2  0010  01 02 03 04  DATA: DB 1, 2, 3, 4, 5\n       0014  05",
+            listing[1]
+        );
+        assert_eq!("    3                     ; comment", listing[2]);
+        assert_eq!("    4  000A               X EQU 0AH", listing[3]);
+    }
+
+    /// Writes a disassembled instruction back in Intel syntax
+    fn to_source(command: &Command) -> String {
+        let bytes = command.get_bytes();
+        match (command.cmd, command.args.as_slice()) {
+            (Cmd::NOPU, _) => format!("DB {}", bytes[0]),
+            (Cmd::RST, [Argument::D8(n)]) => format!("RST {}", n),
+            (cmd, args) => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Argument::D8(d8) => format!("0{:02X}H", d8),
+                        Argument::D16(lo, hi) | Argument::Addr(lo, hi) => {
+                            format!("0{:02X}{:02X}H", hi, lo)
+                        }
+                        reg => reg.to_string(),
+                    })
+                    .collect();
+                format!("{} {}", cmd, args.join(", "))
+            }
+        }
+    }
+
+    /// Instruction with its operand bytes
+    fn instruction() -> impl Strategy<Value = Vec<u8>> {
+        any::<[u8; 3]>().prop_map(|bytes| {
+            let size = disassemble(&bytes).size as usize;
+            bytes[..size].to_vec()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn asm_disassembly_roundtrip(program in prop::collection::vec(instruction(), 0..64)) {
+            let bytes = program.concat();
+            let source: Vec<String> = Command::iterator(&bytes).map(|c| to_source(&c)).collect();
+            let asm = assemble(&source.join("\n")).unwrap();
+            prop_assert_eq!(bytes, asm.image);
+        }
+    }
+}
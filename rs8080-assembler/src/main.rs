@@ -0,0 +1,63 @@
+use rs8080_assembler::assemble;
+use std::path::PathBuf;
+
+const USAGE: &str = "usage: rs8080-assembler <source.asm> [-o <output.bin>] [-l <listing.lst>]";
+
+struct Args {
+    source: PathBuf,
+    output: PathBuf,
+    listing: Option<PathBuf>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut source = None;
+    let mut output = None;
+    let mut listing = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(args.next().ok_or(USAGE)?.into()),
+            "-l" => listing = Some(args.next().ok_or(USAGE)?.into()),
+            _ if source.is_none() && !arg.starts_with('-') => source = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{}'\n{}", arg, USAGE)),
+        }
+    }
+    let source = source.ok_or(USAGE)?;
+    let output = output.unwrap_or_else(|| source.with_extension("bin"));
+    Ok(Args {
+        source,
+        output,
+        listing,
+    })
+}
+
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(&args.source)?;
+    let asm = assemble(&text).map_err(|err| format!("{}:{}", args.source.display(), err))?;
+    std::fs::write(&args.output, &asm.image)?;
+    if let Some(path) = &args.listing {
+        let mut listing: String = asm.listing.iter().map(|l| format!("{}\n", l)).collect();
+        listing.push('\n');
+        for (name, value) in &asm.symbols {
+            listing.push_str(&format!("{:04X}  {}\n", value, name));
+        }
+        std::fs::write(path, listing)?;
+    }
+    println!(
+        "{}: {} bytes at {:04X}H",
+        args.output.display(),
+        asm.image.len(),
+        asm.origin
+    );
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args()
+        .map_err(|err| err.into())
+        .and_then(|args| run(&args));
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,63 @@
+use rs8080_disassembler::{disassemble, Argument, Cmd};
+use std::collections::HashMap;
+
+/// Operand of an instruction as it is written in source
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub(crate) enum Operand {
+    Register(Argument),
+    /// 8 or 16 bit value, the width comes from the opcode
+    Immediate,
+}
+
+impl Operand {
+    /// Register names are reserved and cannot be used as symbols
+    pub fn register(name: &str) -> Option<Argument> {
+        use Argument::*;
+        Some(match name.to_ascii_uppercase().as_str() {
+            "A" => A,
+            "B" => B,
+            "C" => C,
+            "D" => D,
+            "E" => E,
+            "H" => H,
+            "L" => L,
+            "M" => M,
+            "SP" => SP,
+            "PSW" => PSW,
+            _ => return None,
+        })
+    }
+}
+
+/// Maps mnemonic and operands to an opcode and instruction size.
+/// Built from the disassembler, so both sides always agree on encoding
+pub(crate) struct OpcodeTable {
+    opcodes: HashMap<(Cmd, Vec<Operand>), (u8, u8)>,
+}
+
+impl OpcodeTable {
+    pub fn new() -> OpcodeTable {
+        let mut opcodes = HashMap::new();
+        for opcode in 0..=0xFF {
+            let command = disassemble(&[opcode, 0, 0]);
+            // undocumented opcodes can only be written with DB, RST is encoded by hand
+            if command.cmd == Cmd::NOPU || command.cmd == Cmd::RST {
+                continue;
+            }
+            let operands = command
+                .args
+                .iter()
+                .map(|arg| match arg {
+                    Argument::Addr(..) | Argument::D8(_) | Argument::D16(..) => Operand::Immediate,
+                    reg => Operand::Register(*reg),
+                })
+                .collect();
+            opcodes.insert((command.cmd, operands), (opcode, command.size));
+        }
+        OpcodeTable { opcodes }
+    }
+
+    pub fn get(&self, cmd: Cmd, operands: &[Operand]) -> Option<(u8, u8)> {
+        self.opcodes.get(&(cmd, operands.to_vec())).copied()
+    }
+}
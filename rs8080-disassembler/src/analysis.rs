@@ -0,0 +1,228 @@
+use crate::{Branch, Command, Coverage, MemoryImage, SymbolTable};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// Reset and `RST 1`..`RST 7` vectors
+pub const DEFAULT_ENTRIES: [u16; 8] = [0x00, 0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38];
+
+/// Where control goes after an instruction
+pub(crate) struct Flow {
+    /// Jump or call target
+    pub target: Option<u16>,
+    pub call: bool,
+    /// Next instruction may be executed
+    pub falls_through: bool,
+}
+
+pub(crate) fn flow(cmd: &Command) -> Flow {
+    let info = cmd.info();
+    Flow {
+        target: info.target,
+        call: matches!(info.branch, Some(Branch::Call | Branch::Rst)),
+        falls_through: info.falls_through(),
+    }
+}
+
+/// Code found by following control flow from entry points,
+/// loaded bytes that are never reached are data
+pub struct Analysis {
+    instructions: BTreeMap<u16, Command>,
+    /// Addresses covered by instructions
+    code: Vec<bool>,
+    /// Addresses declared as data
+    data: Vec<bool>,
+    labels: SymbolTable,
+}
+
+impl Analysis {
+    /// Follows jumps, calls, conditional branches and `RST` from `entries`,
+    /// entries that are not loaded are skipped
+    pub fn new(image: &MemoryImage, entries: &[u16]) -> Analysis {
+        Analysis::run(image, entries, BTreeSet::new(), &[])
+    }
+
+    /// Like [new](#method.new), but executed opcodes are decoded first
+    /// and targets of executed `PCHL`s are followed and labeled too
+    pub fn with_coverage(image: &MemoryImage, entries: &[u16], coverage: &Coverage) -> Analysis {
+        Analysis::with_symbols(image, entries, Some(coverage), &SymbolTable::default())
+    }
+
+    /// Like [new](#method.new) or [with_coverage](#method.with_coverage),
+    /// but data regions of `symbols` are never decoded
+    pub fn with_symbols(
+        image: &MemoryImage,
+        entries: &[u16],
+        coverage: Option<&Coverage>,
+        symbols: &SymbolTable,
+    ) -> Analysis {
+        let data: Vec<Range<u32>> = symbols.data().iter().map(|r| r.range(image)).collect();
+        match coverage {
+            Some(coverage) => {
+                let mut all: Vec<u16> = coverage.executed().collect();
+                all.extend(entries);
+                let jumps = coverage.indirect().map(|(_, target)| target).collect();
+                Analysis::run(image, &all, jumps, &data)
+            }
+            None => Analysis::run(image, entries, BTreeSet::new(), &data),
+        }
+    }
+
+    fn run(
+        image: &MemoryImage,
+        entries: &[u16],
+        mut jumps: BTreeSet<u16>,
+        data: &[Range<u32>],
+    ) -> Analysis {
+        let mut analysis = Analysis {
+            instructions: BTreeMap::new(),
+            code: vec![false; 0x1_0000],
+            data: vec![false; 0x1_0000],
+            labels: SymbolTable::default(),
+        };
+        for range in data {
+            analysis.data[range.start as usize..range.end as usize].fill(true);
+        }
+        let mut calls = BTreeSet::new();
+        let mut pending: Vec<u16> = entries.iter().chain(&jumps).rev().copied().collect();
+        while let Some(mut pc) = pending.pop() {
+            while let Some(cmd) = analysis.decode_new(image, pc) {
+                let next = pc as u32 + cmd.size as u32;
+                let flow = flow(&cmd);
+                if let Some(target) = flow.target {
+                    if flow.call {
+                        calls.insert(target);
+                    } else {
+                        jumps.insert(target);
+                    }
+                    pending.push(target);
+                }
+                analysis.insert(pc, cmd);
+                if !flow.falls_through || next > 0xFFFF {
+                    break;
+                }
+                pc = next as u16;
+            }
+        }
+        for target in jumps {
+            if analysis.instructions.contains_key(&target) {
+                analysis.labels.insert(target, &format!("L_{:04X}", target));
+            }
+        }
+        for target in calls {
+            if analysis.instructions.contains_key(&target) {
+                analysis
+                    .labels
+                    .insert(target, &format!("SUB_{:04X}", target));
+            }
+        }
+        analysis
+    }
+
+    /// Decodes an instruction that does not overlap known code
+    fn decode_new(&self, image: &MemoryImage, pc: u16) -> Option<Command> {
+        if self.code[pc as usize] {
+            return None;
+        }
+        let cmd = image.decode(pc)?;
+        let end = pc as usize + cmd.size as usize;
+        let taken = |c: &bool| *c;
+        if self.code[pc as usize..end].iter().any(taken)
+            || self.data[pc as usize..end].iter().any(taken)
+        {
+            return None;
+        }
+        Some(cmd)
+    }
+
+    fn insert(&mut self, pc: u16, cmd: Command) {
+        let end = pc as usize + cmd.size as usize;
+        self.code[pc as usize..end].fill(true);
+        self.instructions.insert(pc, cmd);
+    }
+
+    /// Instruction starting at `address`
+    pub fn instruction(&self, address: u16) -> Option<&Command> {
+        self.instructions.get(&address)
+    }
+
+    pub fn instructions(&self) -> impl Iterator<Item = (u16, &Command)> {
+        self.instructions.iter().map(|(a, cmd)| (*a, cmd))
+    }
+
+    /// Instructions starting at `address` or after it
+    pub fn instructions_from(&self, address: u16) -> impl Iterator<Item = (u16, &Command)> {
+        self.instructions.range(address..).map(|(a, cmd)| (*a, cmd))
+    }
+
+    /// Whether `address` belongs to an instruction
+    pub fn is_code(&self, address: u16) -> bool {
+        self.code[address as usize]
+    }
+
+    /// `SUB_XXXX` for call targets and `L_XXXX` for other branch targets
+    pub fn labels(&self) -> &SymbolTable {
+        &self.labels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analysis_follows_branches() {
+        let mut image = MemoryImage::default();
+        #[rustfmt::skip]
+        image.load(0, &[
+            0xCD, 0x0B, 0x00, // CALL $000B
+            0xC2, 0x0C, 0x00, // JNZ $000C
+            0xC3, 0x0D, 0x00, // JMP $000D
+            0x41, 0x42,       // data
+            0xC9,             // RET
+            0x76,             // HLT
+            0xE9,             // PCHL
+            0x01,             // data, LXI B cut off
+        ]).unwrap();
+        let analysis = Analysis::new(&image, &DEFAULT_ENTRIES);
+        let starts: Vec<u16> = analysis.instructions().map(|(a, _)| a).collect();
+        assert_eq!(vec![0, 3, 6, 0xB, 0xC, 0xD], starts);
+        assert!(!analysis.is_code(0x09));
+        assert!(analysis.is_code(0x08));
+        assert!(!analysis.is_code(0x0E));
+        assert_eq!(Some("SUB_000B"), analysis.labels().get(0xB));
+        assert_eq!(Some("L_000C"), analysis.labels().get(0xC));
+        assert_eq!(Some("L_000D"), analysis.labels().get(0xD));
+        assert_eq!(
+            "JNZ  L_000C",
+            analysis.instruction(3).unwrap().text(analysis.labels())
+        );
+
+        // jump into the middle of JMP is not decoded
+        let analysis = Analysis::new(&image, &[0, 7]);
+        assert!(analysis.instruction(7).is_none());
+
+        // PCHL target is labeled
+        let mut coverage = Coverage::default();
+        coverage.record(0xD, 0xE9, 0x9);
+        let analysis = Analysis::with_coverage(&image, &[], &coverage);
+        assert_eq!(Some("L_0009"), analysis.labels().get(9));
+        assert!(analysis.instruction(0).is_none());
+
+        // declared data stops the flow
+        let symbols = SymbolTable::parse("$0B..$0C bytes").unwrap();
+        let analysis = Analysis::with_symbols(&image, &DEFAULT_ENTRIES, None, &symbols);
+        assert!(analysis.instruction(0xB).is_none());
+        assert!(analysis.instruction(0xD).is_some());
+    }
+
+    #[test]
+    fn analysis_of_full_image() {
+        // every NOP decodes in constant time, the whole 64K once
+        let mut image = MemoryImage::default();
+        image.load(0, &[0; 0x1_0000]).unwrap();
+        let start = std::time::Instant::now();
+        let analysis = Analysis::new(&image, &DEFAULT_ENTRIES);
+        assert_eq!(0x1_0000, analysis.instructions().count());
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+}
@@ -1,7 +1,9 @@
-use std::fmt::{self, Display, Formatter};
+#[cfg(feature = "alloc")]
+use alloc::{vec, vec::Vec};
+use core::fmt::{self, Display, Formatter};
 use Argument::*;
 
-#[derive(IntoStaticStr)]
+#[derive(IntoStaticStr, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Argument {
     /// lo, hi
     Addr(u8, u8),
@@ -22,12 +24,12 @@ pub enum Argument {
     SP,
 }
 
+/// Numbers as `$0A5C` like the default [Syntax](struct.Syntax.html)
 impl Display for Argument {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         match *self {
-            Addr(lo, hi) => write!(f, "${:02X}{:02X}", hi, lo),
-            D8(d8) => write!(f, "#__{:<02X}", d8),
-            D16(lo, hi) => write!(f, "#{:02X}{:02X}", hi, lo),
+            Addr(lo, hi) | D16(lo, hi) => write!(f, "${:02X}{:02X}", hi, lo),
+            D8(d8) => write!(f, "${:02X}", d8),
             _ => {
                 let s: &'static str = self.into();
                 write!(f, "{}", s)
@@ -36,21 +38,9 @@ impl Display for Argument {
     }
 }
 
+#[cfg(feature = "alloc")]
 impl From<Argument> for Vec<Argument> {
     fn from(x: Argument) -> Self {
         vec![x]
     }
 }
-
-pub(crate) trait MyToString {
-    fn to_string(&self) -> String;
-}
-
-impl MyToString for Vec<Argument> {
-    fn to_string(&self) -> String {
-        self.iter()
-            .map(|x| x.to_string())
-            .collect::<Vec<_>>()
-            .join(", ")
-    }
-}
@@ -0,0 +1,317 @@
+use crate::project::{Edit, DEFAULT_PROJECT};
+use rs8080_disassembler::{parse_number, HexStyle, Pattern, Style};
+use std::path::PathBuf;
+
+pub(crate) const USAGE: &str = "\
+usage: disassembler [options] <file[@address]>...
+       disassembler search [options] <pattern> <file[@address]>...
+       disassembler search [options] --signatures <file> <file[@address]>...
+       disassembler project <command> [-p <file>] [-b <address>]
+    -s, --start <address>   first address to disassemble, the lowest loaded one by default
+    -e, --end <address>     address after the last one to disassemble
+    -b, --base <address>    load address of files given without one, 0 by default
+    --entry <address>       code entry point besides reset and RST vectors, can be repeated
+    --linear                decode everything instead of following control flow
+    -f, --format <format>   listing (default), plain, source: assembler source that
+                            reassembles to the same bytes, xref: instructions that
+                            jump, call, RST to or use every address, json: the same
+                            as a JSON array, cfg: control-flow graph as Graphviz DOT,
+                            calls: call graph as DOT, graph: both as JSON, or html:
+                            listing with linked references and labels, data as hex
+                            and ASCII and sprite regions as bitmaps
+    --xref                  comment lines by the instructions that refer to them
+    --hex <style>           hex numbers as $0A5C (dollar, default), 0x0A5C (prefix)
+                            or 0A5CH (suffix)
+    --z80                   Z80 mnemonics
+    --lowercase             lowercase mnemonics, registers and hex digits
+    --separator <text>      between operands and data values, ', ' by default
+    --no-bytes              listing without the column of instruction bytes
+    --symbols <file>        names, comments and data of addresses, lines of
+                            `name address ; comment`,
+                            `start..end bytes|words|string|sprite`
+                            or `address string`, overrides imported labels
+    --import-labels <file>  label list of hex addresses and names, e.g. `1A5C: DrawSprite`,
+                            other lines are skipped
+    --coverage <file>       opcodes executed at runtime are code, the listing marks them
+                            by X and code that was only inferred by -
+    --ports <map>           comment IN and OUT by port names and bits, `invaders` for
+                            Space Invaders or a file of `in|out port name bit=name...`
+    -p, --project <file>    files, entry points, names, comments, data regions and ports
+                            of a project file, disassembler.toml for project commands
+search lists runs of decoded instructions that match a pattern, e.g. 'MVI A,?; OUT 03',
+operands are ? for any, r for a register or M, rp for a register pair, register names
+or hex numbers with ? for any digit, e.g. $20??; --signatures <file> searches named
+patterns, lines of `name = pattern`
+project commands edit the project file, which lists addresses in order:
+    init <file[@address]>...                  new project of the files
+    label <address> <name>                    names an address
+    comment <address> <text>                  comments an address
+    data <start>[..<end>] <kind>              bytes, words, string or sprite, inclusive end
+    entry <address>                           code entry point
+    port in|out <port> <name> [<bit>=<name>]... names a port and its bits
+    ports <map>                               adds the ports of `invaders` or a map file
+    remove label|comment|data|entry <address>, remove port in|out <port>
+addresses are decimal, or hex with 0x, $ prefix or H suffix";
+
+/// File and the address it is loaded at, `None` for the base address
+#[derive(Debug, PartialEq)]
+pub(crate) struct Input {
+    pub path: PathBuf,
+    pub address: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) enum Format {
+    /// Address, bytes and instruction
+    #[default]
+    Listing,
+    /// Instructions only
+    Plain,
+    /// Assembler source with `ORG`, `EQU` and labels
+    Source,
+    /// Cross-reference report
+    Xref,
+    /// Cross-references as JSON
+    Json,
+    /// Control-flow graph as DOT
+    Cfg,
+    /// Call graph as DOT
+    Calls,
+    /// Both graphs as JSON
+    Graph,
+    /// Self-contained hyperlinked listing
+    Html,
+}
+
+/// Port map used to comment `IN` and `OUT`
+#[derive(Debug, PartialEq)]
+pub(crate) enum Ports {
+    /// Built-in Space Invaders map
+    Invaders,
+    File(PathBuf),
+}
+
+/// Patterns of the `search` subcommand
+#[derive(Debug, PartialEq)]
+pub(crate) enum Search {
+    Pattern(Pattern),
+    /// File of named patterns
+    Signatures(PathBuf),
+}
+
+/// Command line options
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Options {
+    pub inputs: Vec<Input>,
+    pub start: Option<u16>,
+    pub end: Option<u32>,
+    pub base: u16,
+    pub entries: Vec<u16>,
+    /// Linear sweep instead of recursive descent
+    pub linear: bool,
+    pub format: Format,
+    pub style: Style,
+    /// Inline cross-reference comments
+    pub xref: bool,
+    pub symbols: Option<PathBuf>,
+    pub import_labels: Option<PathBuf>,
+    pub coverage: Option<PathBuf>,
+    pub ports: Option<Ports>,
+    /// Lists matches of patterns instead of the disassembly
+    pub search: Option<Search>,
+    pub project: Option<PathBuf>,
+    /// Changes the project instead of disassembling
+    pub edit: Option<Edit>,
+}
+
+fn address(text: &str) -> Result<u16, String> {
+    parse_number(text)
+        .and_then(|a| u16::try_from(a).ok())
+        .ok_or_else(|| format!("invalid address '{}'", text))
+}
+
+pub(crate) fn input(arg: &str) -> Result<Input, String> {
+    match arg.rsplit_once('@') {
+        Some((path, at)) if !path.is_empty() => Ok(Input {
+            path: path.into(),
+            address: Some(address(at)?),
+        }),
+        _ => Ok(Input {
+            path: arg.into(),
+            address: None,
+        }),
+    }
+}
+
+pub(crate) fn parse<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut args = args.peekable();
+    let search = args.next_if(|arg| arg == "search").is_some();
+    let project = !search && args.next_if(|arg| arg == "project").is_some();
+    let mut signatures = None;
+    let mut positional = Vec::new();
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "-s" | "--start" => options.start = Some(address(&value()?)?),
+            "-e" | "--end" => {
+                let end = value()?;
+                options.end = Some(
+                    parse_number(&end)
+                        .filter(|end| *end <= 0x1_0000)
+                        .ok_or_else(|| format!("invalid address '{}'", end))?,
+                );
+            }
+            "-b" | "--base" => options.base = address(&value()?)?,
+            "--entry" => options.entries.push(address(&value()?)?),
+            "--linear" => options.linear = true,
+            "-f" | "--format" => {
+                options.format = match value()?.as_str() {
+                    "listing" => Format::Listing,
+                    "plain" => Format::Plain,
+                    "source" => Format::Source,
+                    "xref" => Format::Xref,
+                    "json" => Format::Json,
+                    "cfg" => Format::Cfg,
+                    "calls" => Format::Calls,
+                    "graph" => Format::Graph,
+                    "html" => Format::Html,
+                    other => return Err(format!("unknown format '{}'\n{}", other, USAGE)),
+                }
+            }
+            "--hex" => {
+                options.style.syntax.hex = match value()?.as_str() {
+                    "dollar" => HexStyle::Dollar,
+                    "prefix" => HexStyle::Prefix,
+                    "suffix" => HexStyle::Suffix,
+                    other => return Err(format!("unknown hex style '{}'\n{}", other, USAGE)),
+                }
+            }
+            "--z80" => options.style.syntax.z80 = true,
+            "--lowercase" => options.style.syntax.lowercase = true,
+            "--separator" => options.style.syntax.separator = value()?,
+            "--no-bytes" => options.style.bytes = false,
+            "--xref" => options.xref = true,
+            "--symbols" => options.symbols = Some(value()?.into()),
+            "--import-labels" => options.import_labels = Some(value()?.into()),
+            "--coverage" => options.coverage = Some(value()?.into()),
+            "--ports" => {
+                options.ports = Some(match value()?.as_str() {
+                    "invaders" => Ports::Invaders,
+                    path => Ports::File(path.into()),
+                })
+            }
+            "--signatures" if search => signatures = Some(value()?.into()),
+            "-p" | "--project" => options.project = Some(value()?.into()),
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option '{}'\n{}", arg, USAGE))
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if project {
+        options.edit = Some(Edit::parse(&positional).map_err(|err| format!("{}\n{}", err, USAGE))?);
+        options
+            .project
+            .get_or_insert_with(|| DEFAULT_PROJECT.into());
+        return Ok(options);
+    }
+    let mut positional = positional.into_iter();
+    if search {
+        options.search = Some(match signatures {
+            Some(path) => Search::Signatures(path),
+            None => {
+                let text = positional.next().ok_or_else(|| USAGE.to_owned())?;
+                let pattern = Pattern::parse(&text)
+                    .map_err(|err| format!("invalid pattern '{}': {}", text, err))?;
+                Search::Pattern(pattern)
+            }
+        });
+    }
+    options.inputs = positional
+        .map(|arg| input(&arg))
+        .collect::<Result<_, _>>()?;
+    if options.inputs.is_empty() && options.project.is_none() {
+        return Err(USAGE.to_owned());
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn cli_parse() {
+        let options = parse(args(
+            "invaders.h@0 invaders.g@$800 --start 0x18 -e 2000H --entry 8 --entry 16 extra.bin -b 100 -f plain --linear --import-labels labels.txt --hex suffix --z80 --xref --lowercase --separator , --no-bytes --ports invaders",
+        ))
+        .unwrap();
+        assert_eq!(3, options.inputs.len());
+        assert_eq!(Some(0x800), options.inputs[1].address);
+        assert_eq!(None, options.inputs[2].address);
+        assert_eq!(Some(0x18), options.start);
+        assert_eq!(Some(0x2000), options.end);
+        assert_eq!(100, options.base);
+        assert_eq!(vec![8, 16], options.entries);
+        assert_eq!(Format::Plain, options.format);
+        assert!(options.linear);
+        assert_eq!(Some(PathBuf::from("labels.txt")), options.import_labels);
+        assert_eq!(HexStyle::Suffix, options.style.syntax.hex);
+        assert!(options.style.syntax.z80);
+        assert!(options.style.syntax.lowercase);
+        assert_eq!(",", options.style.syntax.separator);
+        assert!(!options.style.bytes);
+        assert_eq!(Some(Ports::Invaders), options.ports);
+        assert!(options.xref);
+        let options = parse(args("a.bin --ports machine.ports")).unwrap();
+        assert_eq!(Some(Ports::File("machine.ports".into())), options.ports);
+
+        assert!(parse(args("")).is_err());
+        assert!(parse(args("a.bin@10000H")).is_err());
+        assert!(parse(args("a.bin -e 0x10001")).is_err());
+        assert!(parse(args("a.bin --start")).is_err());
+        assert!(parse(args("a.bin -f pdf")).is_err());
+        assert_eq!(Format::Html, parse(args("a.bin -f html")).unwrap().format);
+        assert!(parse(args("a.bin --hex octal")).is_err());
+        assert!(parse(args("a.bin --fast")).is_err());
+        assert!(parse(args("a.bin --signatures sounds.sig")).is_err());
+
+        let search = |line: &[&str]| parse(line.iter().map(|arg| arg.to_string()));
+        let options = search(&["search", "MVI A,?; OUT 03", "a.bin", "--linear"]).unwrap();
+        let pattern = Pattern::parse("MVI A,?; OUT 03").unwrap();
+        assert_eq!(Some(Search::Pattern(pattern)), options.search);
+        assert_eq!(1, options.inputs.len());
+        assert!(options.linear);
+        let options = search(&["search", "--signatures", "sounds.sig", "a.bin"]).unwrap();
+        assert_eq!(
+            Some(Search::Signatures("sounds.sig".into())),
+            options.search
+        );
+        assert_eq!(PathBuf::from("a.bin"), options.inputs[0].path);
+        assert!(search(&["search", "CALL ????"]).is_err());
+        assert!(search(&["search", "CALL $12345", "a.bin"]).is_err());
+        assert!(search(&["a.bin", "search"]).unwrap().search.is_none());
+
+        let options = search(&["project", "label", "0x01E4", "DrawSprite"]).unwrap();
+        assert_eq!(Some(Edit::Label(0x1E4, "DrawSprite".into())), options.edit);
+        assert_eq!(Some(PathBuf::from(DEFAULT_PROJECT)), options.project);
+        let options = search(&["project", "init", "a.h@0", "-p", "si.toml", "-b", "8"]).unwrap();
+        assert!(matches!(options.edit, Some(Edit::Init(_))));
+        assert_eq!(
+            (Some(PathBuf::from("si.toml")), 8),
+            (options.project, options.base)
+        );
+        assert!(search(&["project", "comment", "0x01E4", "draws", "a", "sprite"]).is_ok());
+        assert!(search(&["project", "label", "0x01E4"]).is_err());
+        let options = search(&["--project", "si.toml", "-f", "html"]).unwrap();
+        assert!(options.inputs.is_empty() && options.edit.is_none());
+    }
+}
@@ -0,0 +1,1354 @@
+use rs8080_disassembler::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::Display;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+mod cli;
+use cli::{Format, Options, Ports, Search};
+
+mod project;
+use project::{project_dir, Edit, Project};
+
+/// Decoded instruction or a data byte
+struct Line {
+    address: u16,
+    bytes: Vec<u8>,
+    text: String,
+    code: bool,
+}
+
+/// Data bytes in one `DB` line
+const DATA_PER_LINE: usize = 8;
+/// Values in one `DW` line
+const WORDS_PER_LINE: usize = 4;
+/// Bytes of a string in one `DB` line
+const STRING_PER_LINE: usize = 32;
+/// Columns of a sprite in one `DB` line, the width of an invader
+const SPRITE_PER_LINE: usize = 16;
+
+impl Line {
+    fn code(address: u16, cmd: &Command, symbols: &SymbolTable, options: &Options) -> Line {
+        Line {
+            address,
+            bytes: cmd.get_bytes().to_vec(),
+            text: options.style.syntax.instruction(cmd, symbols),
+            code: true,
+        }
+    }
+
+    fn data(address: u16, bytes: &[u8], syntax: &Syntax) -> Line {
+        let values: Vec<String> = bytes.iter().map(|b| syntax.byte(*b)).collect();
+        Line {
+            address,
+            bytes: bytes.to_vec(),
+            text: syntax.directive("DB", &values),
+            code: false,
+        }
+    }
+
+    fn words(address: u16, bytes: &[u8], syntax: &Syntax) -> Line {
+        let values: Vec<String> = bytes
+            .chunks(2)
+            .map(|w| syntax.word(u16::from_le_bytes([w[0], w[1]])))
+            .collect();
+        Line {
+            address,
+            bytes: bytes.to_vec(),
+            text: syntax.directive("DW", &values),
+            code: false,
+        }
+    }
+
+    /// Printable characters are quoted, other bytes are numbers
+    fn string(address: u16, bytes: &[u8], syntax: &Syntax) -> Line {
+        let printable = |b: &u8| (b.is_ascii_graphic() || *b == b' ') && *b != b'"';
+        let mut values = Vec::new();
+        let mut rest = bytes;
+        while let Some(first) = rest.first() {
+            let len = match printable(first) {
+                true => rest.iter().take_while(|b| printable(b)).count(),
+                false => 1,
+            };
+            let (value, tail) = rest.split_at(len);
+            values.push(match printable(first) {
+                true => format!("\"{}\"", String::from_utf8_lossy(value)),
+                false => syntax.byte(*first),
+            });
+            rest = tail;
+        }
+        Line {
+            address,
+            bytes: bytes.to_vec(),
+            text: syntax.directive("DB", &values),
+            code: false,
+        }
+    }
+}
+
+/// Data line at `pc`, typed by the data region of `symbols` that covers it.
+/// Lines end before `stop`, names, comments, code and other data regions
+fn data_line(
+    image: &MemoryImage,
+    symbols: &SymbolTable,
+    syntax: &Syntax,
+    pc: u32,
+    stop: u32,
+    is_code: impl Fn(u16) -> bool,
+) -> Line {
+    let region = symbols.data_at(image, pc as u16);
+    let (kind, stop) = match region {
+        Some(region) => (region.kind, stop.min(region.range(image).end)),
+        None => (DataKind::Bytes, stop),
+    };
+    let boundary = |a: u32| {
+        let a = a as u16;
+        symbols.get(a).is_some()
+            || symbols.comment(a).is_some()
+            || is_code(a)
+            || symbols.data_at(image, a) != region
+    };
+    let len = 1 + (pc + 1..stop).take_while(|a| !boundary(*a)).count();
+    let bytes = &image.bytes_from(pc as u16)[..len];
+    match kind {
+        DataKind::Words if len >= 2 => Line::words(
+            pc as u16,
+            &bytes[..(len / 2).min(WORDS_PER_LINE) * 2],
+            syntax,
+        ),
+        DataKind::String => Line::string(pc as u16, &bytes[..len.min(STRING_PER_LINE)], syntax),
+        DataKind::Sprite => Line::data(pc as u16, &bytes[..len.min(SPRITE_PER_LINE)], syntax),
+        _ => Line::data(pc as u16, &bytes[..len.min(DATA_PER_LINE)], syntax),
+    }
+}
+
+fn load(options: &Options) -> Result<MemoryImage, Box<dyn Error>> {
+    let mut image = MemoryImage::default();
+    for input in &options.inputs {
+        let path = input.path.display();
+        let data = std::fs::read(&input.path).map_err(|err| format!("{}: {}", path, err))?;
+        image
+            .load(input.address.unwrap_or(options.base), &data)
+            .map_err(|err| format!("{}: {}", path, err))?;
+    }
+    Ok(image)
+}
+
+/// Loaded ranges clipped to `--start` and `--end`
+fn ranges(image: &MemoryImage, options: &Options) -> Vec<(u32, u32)> {
+    let start = options.start.unwrap_or(0) as u32;
+    let end = options.end.unwrap_or(0x1_0000);
+    image
+        .regions()
+        .into_iter()
+        .map(|region| (region.start.max(start), region.end.min(end)))
+        .filter(|(start, stop)| start < stop)
+        .collect()
+}
+
+/// Linear sweep over loaded bytes, instructions cut off by the end of data,
+/// an entry point or a data region are shown as data bytes
+fn sweep(image: &MemoryImage, options: &Options, symbols: &SymbolTable) -> Vec<Line> {
+    let mut lines = Vec::new();
+    for (mut pc, stop) in ranges(image, options) {
+        while pc < stop {
+            if symbols.data_at(image, pc as u16).is_some() {
+                let line = data_line(image, symbols, &options.style.syntax, pc, stop, |_| false);
+                pc += line.bytes.len() as u32;
+                lines.push(line);
+                continue;
+            }
+            let limit = options
+                .entries
+                .iter()
+                .map(|e| *e as u32)
+                .chain(symbols.data().iter().map(|r| r.start as u32))
+                .filter(|e| *e > pc && *e < stop)
+                .min()
+                .unwrap_or(stop);
+            let bytes = &image.bytes_from(pc as u16)[..(limit - pc) as usize];
+            for (address, cmd) in Command::iterator_at(bytes, pc as u16) {
+                lines.push(Line::code(address, &cmd, symbols, options));
+                pc += cmd.size as u32;
+            }
+            // the iterator stops at an instruction cut off by `limit`
+            if pc < limit {
+                let bytes = &image.bytes_from(pc as u16)[..1];
+                lines.push(Line::data(pc as u16, bytes, &options.style.syntax));
+                pc += 1;
+            }
+        }
+    }
+    lines
+}
+
+/// Instructions found by `Analysis`,
+/// other bytes are shown as data
+fn recursive(
+    image: &MemoryImage,
+    options: &Options,
+    analysis: &Analysis,
+    symbols: &SymbolTable,
+) -> Vec<Line> {
+    let mut lines = Vec::new();
+    for (mut pc, stop) in ranges(image, options) {
+        while pc < stop {
+            let line = match analysis.instruction(pc as u16) {
+                Some(cmd) => Line::code(pc as u16, cmd, symbols, options),
+                None => data_line(image, symbols, &options.style.syntax, pc, stop, |a| {
+                    analysis.is_code(a)
+                }),
+            };
+            pc += line.bytes.len() as u32;
+            lines.push(line);
+        }
+    }
+    lines
+}
+
+/// With coverage the listing marks code executed at runtime by `X`
+/// and code that was only inferred by `-`, comments follow the text
+fn print(
+    out: &mut impl Write,
+    lines: &[Line],
+    comments: &Comments,
+    coverage: Option<&Coverage>,
+    format: Format,
+    style: &Style,
+) -> io::Result<()> {
+    for line in lines {
+        if let Some(name) = comments.symbols.get(line.address) {
+            writeln!(out, "{}:", name)?;
+        }
+        match format {
+            Format::Listing => {
+                write!(out, "{:04X}  ", line.address)?;
+                if let Some(coverage) = coverage {
+                    write!(out, "{} ", coverage_mark(coverage, line))?;
+                }
+                // data bytes are already in the text
+                let bytes = match line.code {
+                    true => style.byte_column(&line.bytes),
+                    false => style.byte_column(&[]),
+                };
+                write!(out, "{}{}", bytes, line.text)?
+            }
+            _ => write!(out, "    {}", line.text)?,
+        }
+        comments.write(out, line)?;
+    }
+    Ok(())
+}
+
+/// `X` for code executed at runtime, `-` for code that was only inferred
+fn coverage_mark(coverage: &Coverage, line: &Line) -> char {
+    match line.code {
+        true if coverage.is_executed(line.address) => 'X',
+        true => '-',
+        false => ' ',
+    }
+}
+
+/// Comments of the symbol file, instructions that refer to a line
+/// and ports used by `IN` and `OUT`
+struct Comments<'a> {
+    symbols: &'a SymbolTable,
+    xrefs: Option<&'a Xrefs>,
+    ports: Option<&'a PortMap>,
+}
+
+impl Comments<'_> {
+    /// Comment of the symbol file and port of `line`
+    fn parts(&self, line: &Line) -> Vec<String> {
+        let mut parts: Vec<String> = self
+            .symbols
+            .comment(line.address)
+            .map(String::from)
+            .into_iter()
+            .collect();
+        let port = match (self.ports, line.code) {
+            (Some(ports), true) => ports.port(&disassemble(&line.bytes)),
+            _ => None,
+        };
+        if let Some(port) = port {
+            parts.push(format!("port: {}", port.describe()));
+        }
+        parts
+    }
+
+    /// Instructions that refer to `address`, none without `--xref`
+    fn refs(&self, address: u16) -> &[Reference] {
+        self.xrefs
+            .map(|xrefs| xrefs.to(address))
+            .unwrap_or_default()
+    }
+
+    /// Ends `line` with its comments
+    fn write(&self, out: &mut impl Write, line: &Line) -> io::Result<()> {
+        let mut parts = self.parts(line);
+        let refs = self.refs(line.address);
+        if !refs.is_empty() {
+            let refs: Vec<String> = refs
+                .iter()
+                .map(|r| format!("{:04X} {}", r.from, r.cmd.as_ref()))
+                .collect();
+            parts.push(format!("xref: {}", refs.join(", ")));
+        }
+        match parts.is_empty() {
+            true => writeln!(out),
+            false => writeln!(out, "  ; {}", parts.join("; ")),
+        }
+    }
+}
+
+/// Every referenced address with the instructions that refer to it
+fn print_xrefs(
+    out: &mut impl Write,
+    lines: &[Line],
+    symbols: &SymbolTable,
+    xrefs: &Xrefs,
+) -> io::Result<()> {
+    let text: BTreeMap<u16, &str> = lines.iter().map(|l| (l.address, l.text.as_str())).collect();
+    for (address, refs) in xrefs.iter() {
+        writeln!(
+            out,
+            "{:04X}  {}",
+            address,
+            symbols.get(address).unwrap_or_default()
+        )?;
+        for r in refs {
+            let text = text.get(&r.from).copied().unwrap_or_default();
+            writeln!(out, "    {:04X}  {:<5} {}", r.from, r.kind.name(), text)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct JsonTarget<'a> {
+    address: u16,
+    name: Option<&'a str>,
+    refs: Vec<JsonRef<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonRef<'a> {
+    from: u16,
+    kind: &'static str,
+    instruction: &'a str,
+}
+
+/// Cross-references as a JSON array of `{address, name, refs: [{from, kind, instruction}]}`
+fn print_json(
+    out: &mut impl Write,
+    lines: &[Line],
+    symbols: &SymbolTable,
+    xrefs: &Xrefs,
+) -> io::Result<()> {
+    let text: BTreeMap<u16, &str> = lines.iter().map(|l| (l.address, l.text.as_str())).collect();
+    let targets: Vec<JsonTarget> = xrefs
+        .iter()
+        .map(|(address, refs)| JsonTarget {
+            address,
+            name: symbols.get(address),
+            refs: refs
+                .iter()
+                .map(|r| JsonRef {
+                    from: r.from,
+                    kind: r.kind.name(),
+                    instruction: text.get(&r.from).copied().unwrap_or_default(),
+                })
+                .collect(),
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *out, &targets)?;
+    writeln!(out)
+}
+
+/// Name of a block or a function, its address when it has no name
+fn node_name(symbols: &SymbolTable, address: u16) -> String {
+    match symbols.get(address) {
+        Some(name) => name.to_owned(),
+        None => format!("{:04X}", address),
+    }
+}
+
+/// Instructions of `block` as `address  text`
+fn block_text(lines: &BTreeMap<u16, &Line>, block: &Block) -> Vec<String> {
+    lines
+        .range(block.start..)
+        .take_while(|(address, _)| (**address as u32) < block.end)
+        .map(|(address, line)| format!("{:04X}  {}", address, line.text))
+        .collect()
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Control-flow graph in Graphviz DOT: taken edges are bold, calls dashed blue,
+/// returns dotted, unresolved blocks red with a dashed edge to `?`
+fn print_cfg(
+    out: &mut impl Write,
+    lines: &[Line],
+    symbols: &SymbolTable,
+    graph: &Graph,
+) -> io::Result<()> {
+    let lines: BTreeMap<u16, &Line> = lines.iter().map(|l| (l.address, l)).collect();
+    writeln!(out, "digraph cfg {{")?;
+    writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+    for block in graph.blocks() {
+        let mut label = String::new();
+        if let Some(name) = symbols.get(block.start) {
+            label.push_str(&format!("{}:\\l", dot_escape(name)));
+        }
+        for text in block_text(&lines, block) {
+            label.push_str(&format!("{}\\l", dot_escape(&text)));
+        }
+        let color = match block.unresolved {
+            Some(_) => ", color=red",
+            None => "",
+        };
+        writeln!(
+            out,
+            "    b{:04X} [label=\"{}\"{}];",
+            block.start, label, color
+        )?;
+        if let Some(unresolved) = block.unresolved {
+            writeln!(
+                out,
+                "    u{0:04X} [label=\"? {1}\", shape=plaintext, fontcolor=red];\n    b{0:04X} -> u{0:04X} [style=dashed, color=red];",
+                block.start,
+                unresolved.name()
+            )?;
+        }
+    }
+    for edge in graph.edges() {
+        let style = match edge.kind {
+            EdgeKind::Fallthrough => "",
+            EdgeKind::Taken => " [style=bold]",
+            EdgeKind::Call => " [style=dashed, color=blue]",
+            EdgeKind::Return => " [style=dotted]",
+        };
+        writeln!(out, "    b{:04X} -> b{:04X}{};", edge.from, edge.to, style)?;
+    }
+    writeln!(out, "}}")
+}
+
+/// Call graph in Graphviz DOT, functions with unresolved flow are red
+fn print_calls(out: &mut impl Write, symbols: &SymbolTable, graph: &Graph) -> io::Result<()> {
+    writeln!(out, "digraph calls {{")?;
+    writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+    for function in graph.functions() {
+        let unresolved = function
+            .blocks
+            .iter()
+            .any(|b| graph.block(*b).is_some_and(|b| b.unresolved.is_some()));
+        let color = if unresolved { ", color=red" } else { "" };
+        writeln!(
+            out,
+            "    f{:04X} [label=\"{}\"{}];",
+            function.entry,
+            dot_escape(&node_name(symbols, function.entry)),
+            color
+        )?;
+        for callee in &function.calls {
+            writeln!(out, "    f{:04X} -> f{:04X};", function.entry, callee)?;
+        }
+    }
+    writeln!(out, "}}")
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    blocks: Vec<JsonBlock<'a>>,
+    edges: Vec<JsonEdge>,
+    functions: Vec<JsonFunction<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonBlock<'a> {
+    start: u16,
+    end: u32,
+    name: Option<&'a str>,
+    unresolved: Option<&'static str>,
+    instructions: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct JsonEdge {
+    from: u16,
+    to: u16,
+    kind: &'static str,
+}
+
+#[derive(Serialize)]
+struct JsonFunction<'a> {
+    entry: u16,
+    name: Option<&'a str>,
+    blocks: &'a [u16],
+    calls: &'a BTreeSet<u16>,
+}
+
+/// Blocks, edges and functions as one JSON object
+fn print_graph_json(
+    out: &mut impl Write,
+    lines: &[Line],
+    symbols: &SymbolTable,
+    graph: &Graph,
+) -> io::Result<()> {
+    let lines: BTreeMap<u16, &Line> = lines.iter().map(|l| (l.address, l)).collect();
+    let json = JsonGraph {
+        blocks: graph
+            .blocks()
+            .map(|block| JsonBlock {
+                start: block.start,
+                end: block.end,
+                name: symbols.get(block.start),
+                unresolved: block.unresolved.map(|u| u.name()),
+                instructions: block_text(&lines, block),
+            })
+            .collect(),
+        edges: graph
+            .edges()
+            .iter()
+            .map(|edge| JsonEdge {
+                from: edge.from,
+                to: edge.to,
+                kind: edge.kind.name(),
+            })
+            .collect(),
+        functions: graph
+            .functions()
+            .iter()
+            .map(|function| JsonFunction {
+                entry: function.entry,
+                name: symbols.get(function.entry),
+                blocks: &function.blocks,
+                calls: &function.calls,
+            })
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut *out, &json)?;
+    writeln!(out)
+}
+
+/// Decoded instructions of `lines`
+fn commands(lines: &[Line]) -> Vec<(u16, Command)> {
+    lines
+        .iter()
+        .filter(|line| line.code)
+        .map(|line| (line.address, disassemble(&line.bytes)))
+        .collect()
+}
+
+/// References of decoded instructions in `lines`
+fn xrefs(lines: &[Line]) -> Xrefs {
+    let commands = commands(lines);
+    Xrefs::new(commands.iter().map(|(address, cmd)| (*address, cmd)))
+}
+
+/// Address, signature name and instructions of every match,
+/// unnamed signatures are printed without a name
+fn print_matches(out: &mut impl Write, lines: &[Line], signatures: &[Signature]) -> io::Result<()> {
+    let commands = commands(lines);
+    let texts: BTreeMap<u16, &str> = lines
+        .iter()
+        .filter(|line| line.code)
+        .map(|line| (line.address, line.text.as_str()))
+        .collect();
+    for signature in signatures {
+        let found = signature
+            .pattern
+            .search(commands.iter().map(|(address, cmd)| (*address, cmd)));
+        for found in found {
+            let text: Vec<&str> = found
+                .instructions
+                .iter()
+                .map(|(address, _)| texts[address])
+                .collect();
+            write!(out, "{:04X}  ", found.address)?;
+            if !signature.name.is_empty() {
+                write!(out, "{}  ", signature.name)?;
+            }
+            writeln!(out, "{}", text.join("; "))?;
+        }
+    }
+    Ok(())
+}
+
+/// Assembler source: names that do not start a line are defined by `EQU`,
+/// every gap in addresses starts a new `ORG` block
+fn print_source(
+    out: &mut impl Write,
+    lines: &[Line],
+    comments: &Comments,
+    syntax: &Syntax,
+) -> io::Result<()> {
+    let symbols = comments.symbols;
+    let starts: BTreeSet<u16> = lines.iter().map(|line| line.address).collect();
+    for (address, name) in symbols.iter().filter(|(a, _)| !starts.contains(a)) {
+        writeln!(
+            out,
+            "{:<15} {:4} {}",
+            name,
+            syntax.case("EQU"),
+            syntax.word(address)
+        )?;
+    }
+    let mut next = None;
+    for line in lines {
+        if next != Some(line.address as u32) {
+            let org = syntax.case("ORG");
+            writeln!(out, "\n{:<15} {:4} {}", "", org, syntax.word(line.address))?;
+        }
+        next = Some(line.address as u32 + line.bytes.len() as u32);
+        if let Some(name) = symbols.get(line.address) {
+            writeln!(out, "{}:", name)?;
+        }
+        write!(out, "{:<15} {}", "", line.text)?;
+        comments.write(out, line)?;
+    }
+    writeln!(out, "{:<15} {}", "", syntax.case("END"))
+}
+
+const HTML_STYLE: &str = "\
+body { margin: 1em; color: #222; background: #fff; font: 13px monospace; }
+a { color: #05a; text-decoration: none; }
+a:hover { text-decoration: underline; }
+:target { background: #ffe680; }
+.label { color: #a30; font-weight: bold; }
+.comment { color: #080; }
+.data { color: #666; }
+.sprite { vertical-align: middle; fill: currentColor; }";
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Link to the line at `address`
+fn html_link(address: u16, text: &str) -> String {
+    format!("<a href=\"#a{:04X}\">{}</a>", address, html_escape(text))
+}
+
+/// Instruction text with the operand that refers to `target` linked to its line
+fn html_instruction(
+    line: &Line,
+    target: Option<u16>,
+    symbols: &SymbolTable,
+    syntax: &Syntax,
+) -> String {
+    let text = line.text.as_str();
+    let Some(target) = target else {
+        return html_escape(text);
+    };
+    let operand = [
+        symbols.get(target).map(String::from),
+        Some(syntax.word(target)),
+    ]
+    .into_iter()
+    .flatten()
+    .find_map(|operand| {
+        text.rfind(&operand)
+            .map(|start| start..start + operand.len())
+    });
+    // `RST` shows the vector number, the whole operand is linked
+    let operand = operand.or_else(|| {
+        let end = text.find(' ')?;
+        Some(end + text[end..].len() - text[end..].trim_start().len()..text.len())
+    });
+    match operand {
+        Some(operand) => format!(
+            "{}{}{}",
+            html_escape(&text[..operand.start]),
+            html_link(target, &text[operand.clone()]),
+            html_escape(&text[operand.end..])
+        ),
+        None => html_escape(text),
+    }
+}
+
+/// Inline SVG of columns of 8 pixels, bit 0 at the bottom, at twice the size
+fn html_sprite(bytes: &[u8]) -> String {
+    let mut path = String::new();
+    for (x, byte) in bytes.iter().enumerate() {
+        for bit in (0..8).filter(|bit| byte & (1 << bit) != 0) {
+            path += &format!("M{} {}h1v1h-1z", x, 7 - bit);
+        }
+    }
+    format!(
+        "<svg class=\"sprite\" width=\"{}\" height=\"16\" viewBox=\"0 0 {} 8\" \
+         shape-rendering=\"crispEdges\"><path d=\"{}\"/></svg>",
+        bytes.len() * 2,
+        bytes.len(),
+        path
+    )
+}
+
+/// Self-contained listing where references link to the lines they refer to
+/// and labels list the instructions that refer to them, data is shown as
+/// hex and ASCII and sprite regions as bitmaps
+fn print_html(
+    out: &mut impl Write,
+    image: &MemoryImage,
+    lines: &[Line],
+    comments: &Comments,
+    coverage: Option<&Coverage>,
+    style: &Style,
+    title: &str,
+) -> io::Result<()> {
+    let symbols = comments.symbols;
+    let starts: BTreeSet<u16> = lines.iter().map(|line| line.address).collect();
+    let xrefs = |address: u16| {
+        let refs: Vec<String> = comments
+            .refs(address)
+            .iter()
+            .map(|r| html_link(r.from, &format!("{:04X} {}", r.from, r.cmd.as_ref())))
+            .collect();
+        format!("xref: {}", refs.join(", "))
+    };
+    writeln!(
+        out,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>\n{}\n</style>\n</head>\n<body>\n<pre>",
+        html_escape(title),
+        HTML_STYLE
+    )?;
+    for line in lines {
+        let address = line.address;
+        let referred = !comments.refs(address).is_empty();
+        let name = symbols.get(address);
+        match name {
+            Some(name) => {
+                write!(
+                    out,
+                    "<span class=\"label\" id=\"a{:04X}\">{}:</span>",
+                    address,
+                    html_escape(name)
+                )?;
+                if referred {
+                    write!(out, "  <span class=\"comment\">; {}</span>", xrefs(address))?;
+                }
+                write!(out, "\n{:04X}  ", address)?
+            }
+            None => write!(
+                out,
+                "<span id=\"a{:04X}\">{:04X}</span>  ",
+                address, address
+            )?,
+        }
+        if let Some(coverage) = coverage {
+            write!(out, "{} ", coverage_mark(coverage, line))?;
+        }
+        if line.code {
+            let target = reference(&disassemble(&line.bytes))
+                .map(|(target, _)| target)
+                .filter(|target| starts.contains(target));
+            write!(
+                out,
+                "{}{}",
+                style.byte_column(&line.bytes),
+                html_instruction(line, target, symbols, &style.syntax)
+            )?;
+        } else {
+            let hex: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let hex = style.syntax.case(&hex.join(" "));
+            let sprite = symbols
+                .data_at(image, address)
+                .is_some_and(|region| region.kind == DataKind::Sprite);
+            let (width, shown) = match sprite {
+                true => (SPRITE_PER_LINE * 3 - 1, html_sprite(&line.bytes)),
+                false => {
+                    let ascii: String = line
+                        .bytes
+                        .iter()
+                        .map(|b| match b.is_ascii_graphic() || *b == b' ' {
+                            true => *b as char,
+                            false => '.',
+                        })
+                        .collect();
+                    (DATA_PER_LINE * 3 - 1, html_escape(&ascii))
+                }
+            };
+            write!(
+                out,
+                "<span class=\"data\">{:<width$}</span>  {}",
+                hex,
+                shown,
+                width = width
+            )?;
+        }
+        let mut parts: Vec<String> = comments
+            .parts(line)
+            .iter()
+            .map(|p| html_escape(p))
+            .collect();
+        if referred && name.is_none() {
+            parts.push(xrefs(address));
+        }
+        match parts.is_empty() {
+            true => writeln!(out)?,
+            false => writeln!(
+                out,
+                "  <span class=\"comment\">; {}</span>",
+                parts.join("; ")
+            )?,
+        }
+    }
+    writeln!(out, "</pre>\n</body>\n</html>")
+}
+
+/// Reads and parses a text file, errors start with the file name
+fn read_with<T, E: Display>(
+    path: &Path,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> Result<T, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    parse(&text).map_err(|err| format!("{}:{}", path.display(), err))
+}
+
+/// Reset and `RST` vectors and `--entry` addresses
+fn entries(options: &Options) -> Vec<u16> {
+    let mut entries = DEFAULT_ENTRIES.to_vec();
+    entries.extend(&options.entries);
+    entries
+}
+
+/// Recursive descent, `None` for `--linear`
+fn analyse(
+    image: &MemoryImage,
+    options: &Options,
+    symbols: &SymbolTable,
+    coverage: Option<&Coverage>,
+) -> Option<Analysis> {
+    let entries = entries(options);
+    (!options.linear).then(|| Analysis::with_symbols(image, &entries, coverage, symbols))
+}
+
+/// Lines to print and names used by them
+fn lines(
+    image: &MemoryImage,
+    options: &Options,
+    symbols: SymbolTable,
+    analysis: Option<&Analysis>,
+) -> (Vec<Line>, SymbolTable) {
+    let Some(analysis) = analysis else {
+        return (sweep(image, options, &symbols), symbols);
+    };
+    // names from the symbol file replace generated labels
+    let mut labels = analysis.labels().clone();
+    labels.extend(&symbols);
+    (recursive(image, options, analysis, &labels), labels)
+}
+
+fn port_map(ports: &Ports) -> Result<PortMap, String> {
+    match ports {
+        Ports::Invaders => Ok(PortMap::space_invaders()),
+        Ports::File(path) => read_with(path, PortMap::parse),
+    }
+}
+
+/// Files and entry points of `--project` come before those of the command line
+fn open_project(mut options: Options) -> Result<(Options, Option<Project>), Box<dyn Error>> {
+    let Some(path) = &options.project else {
+        return Ok((options, None));
+    };
+    let project = read_with(path, Project::parse)?;
+    let mut inputs = project.inputs(project_dir(path));
+    inputs.append(&mut options.inputs);
+    if inputs.is_empty() {
+        return Err(format!(
+            "{}: no files, create the project by project init",
+            path.display()
+        )
+        .into());
+    }
+    options.inputs = inputs;
+    options.entries.extend(project.entries());
+    Ok((options, Some(project)))
+}
+
+/// Applies a `project` command and writes the project back
+fn edit_project(options: &Options, edit: &Edit) -> Result<(), Box<dyn Error>> {
+    let path = options
+        .project
+        .as_deref()
+        .expect("project commands have a project file");
+    let mut project = match edit {
+        Edit::Init(_) if path.exists() => {
+            return Err(format!("{} already exists", path.display()).into())
+        }
+        Edit::Init(inputs) => Project::init(inputs, options.base, project_dir(path)),
+        _ => read_with(path, Project::parse)?,
+    };
+    let map = match edit {
+        Edit::Ports(ports) => Some(port_map(ports)?),
+        _ => None,
+    };
+    project.apply(edit, map.as_ref());
+    std::fs::write(path, project.to_toml())
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(())
+}
+
+/// Names, comments and data of `project` override imported labels and are
+/// overridden by `--symbols`, `--ports` replaces its ports
+fn run(options: &Options, project: Option<&Project>) -> Result<(), Box<dyn Error>> {
+    let image = load(options)?;
+    let mut symbols = match &options.import_labels {
+        Some(path) => read_with(path, |text| {
+            Ok::<_, String>(SymbolTable::import_plain(text))
+        })?,
+        None => SymbolTable::default(),
+    };
+    if let Some(project) = project {
+        symbols.extend(&project.symbols()?);
+    }
+    if let Some(path) = &options.symbols {
+        symbols.extend(&read_with(path, SymbolTable::parse)?);
+    }
+    let coverage = match &options.coverage {
+        Some(path) => Some(read_with(path, Coverage::parse)?),
+        None => None,
+    };
+    let ports = match (&options.ports, project) {
+        (Some(ports), _) => Some(port_map(ports)?),
+        (None, Some(project)) => project.port_map()?,
+        (None, None) => None,
+    };
+    let signatures = match &options.search {
+        Some(Search::Pattern(pattern)) => Some(vec![Signature {
+            name: String::new(),
+            pattern: pattern.clone(),
+        }]),
+        Some(Search::Signatures(path)) => Some(read_with(path, Signature::parse_file)?),
+        None => None,
+    };
+    let analysis = analyse(&image, options, &symbols, coverage.as_ref());
+    let graph = match (options.format, &analysis) {
+        (Format::Cfg | Format::Calls | Format::Graph, None) => {
+            return Err("graphs need control flow analysis, remove --linear".into())
+        }
+        (_, Some(analysis)) => Graph::new(analysis, &entries(options), coverage.as_ref()),
+        (_, None) => Graph::new(&Analysis::new(&image, &[]), &[], None),
+    };
+    let (lines, symbols) = lines(&image, options, symbols, analysis.as_ref());
+    let mut out = BufWriter::new(io::stdout().lock());
+    let xrefs = xrefs(&lines);
+    let comments = Comments {
+        symbols: &symbols,
+        // labels of HTML listings always list their references
+        xrefs: (options.xref || options.format == Format::Html).then_some(&xrefs),
+        ports: ports.as_ref(),
+    };
+    let result = match (&signatures, options.format) {
+        (Some(signatures), _) => print_matches(&mut out, &lines, signatures),
+        (None, Format::Source) => print_source(&mut out, &lines, &comments, &options.style.syntax),
+        (None, Format::Xref) => print_xrefs(&mut out, &lines, &symbols, &xrefs),
+        (None, Format::Json) => print_json(&mut out, &lines, &symbols, &xrefs),
+        (None, Format::Cfg) => print_cfg(&mut out, &lines, &symbols, &graph),
+        (None, Format::Calls) => print_calls(&mut out, &symbols, &graph),
+        (None, Format::Graph) => print_graph_json(&mut out, &lines, &symbols, &graph),
+        (None, Format::Html) => {
+            let files: Vec<String> = options
+                .inputs
+                .iter()
+                .map(|input| input.path.display().to_string())
+                .collect();
+            print_html(
+                &mut out,
+                &image,
+                &lines,
+                &comments,
+                coverage.as_ref(),
+                &options.style,
+                &files.join(", "),
+            )
+        }
+        (None, format) => print(
+            &mut out,
+            &lines,
+            &comments,
+            coverage.as_ref(),
+            format,
+            &options.style,
+        ),
+    };
+    match result.and_then(|_| out.flush()) {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+fn main() {
+    let result = cli::parse(std::env::args().skip(1))
+        .map_err(|err| err.into())
+        .and_then(|options| match &options.edit {
+            Some(edit) => edit_project(&options, edit),
+            None => {
+                open_project(options).and_then(|(options, project)| run(&options, project.as_ref()))
+            }
+        });
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassembler_truncated_input() {
+        let mut image = MemoryImage::default();
+        // JMP $0100, then LXI H cut off by the end of data, its last byte is INR M
+        image.load(0, &[0xC3, 0x00, 0x01, 0x21, 0x34]).unwrap();
+        let mut symbols = SymbolTable::default();
+        symbols.insert(0x100, "start");
+        let options = Options {
+            linear: true,
+            ..Options::default()
+        };
+        let lines = sweep(&image, &options, &symbols);
+        let text: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(vec!["JMP  start", "DB   $21", "INR  M"], text);
+
+        // an entry point inside JMP splits it
+        let options = Options {
+            entries: vec![2],
+            linear: true,
+            ..Options::default()
+        };
+        let lines = sweep(&image, &options, &symbols);
+        assert_eq!(2, lines[2].address);
+        assert_eq!("LXI  B, $3421", lines[2].text);
+
+        // JMP leads out of loaded data, the rest is not reached
+        let analysis = Analysis::new(&image, &DEFAULT_ENTRIES);
+        let lines = recursive(&image, &Options::default(), &analysis, &symbols);
+        let text: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(vec!["JMP  start", "DB   $21, $34"], text);
+    }
+
+    #[test]
+    fn disassembler_data_regions() {
+        let mut image = MemoryImage::default();
+        image
+            .load(0, &[0x00, 0x1C, 0x26, 0x41, 0x42, 0x0D, 0x00, 0x01])
+            .unwrap();
+        let symbols = SymbolTable::parse("1..2 words\n3 string\n6 ; flags").unwrap();
+        let options = Options {
+            linear: true,
+            ..Options::default()
+        };
+        let lines = sweep(&image, &options, &symbols);
+        let text: Vec<&str> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(
+            vec![
+                "NOP",
+                "DW   $261C",
+                "DB   \"AB\"",
+                "DCR  C",
+                "NOP",
+                "DB   $01"
+            ],
+            text
+        );
+    }
+
+    /// Source of `image` assembled again
+    fn reassemble(image: &MemoryImage, options: &Options, symbols: SymbolTable) -> (u16, Vec<u8>) {
+        let analysis = analyse(image, options, &symbols, None);
+        let (lines, symbols) = lines(image, options, symbols, analysis.as_ref());
+        let comments = Comments {
+            symbols: &symbols,
+            xrefs: None,
+            ports: None,
+        };
+        let mut source = Vec::new();
+        print_source(&mut source, &lines, &comments, &options.style.syntax).unwrap();
+        let source = String::from_utf8(source).unwrap();
+        let asm = rs8080_assembler::assemble(&source).unwrap_or_else(|err| {
+            panic!("{}\n{}", err, source);
+        });
+        (asm.origin, asm.image)
+    }
+
+    #[test]
+    fn disassembler_source_roundtrip() {
+        // every opcode, with data and names in between
+        let mut image = MemoryImage::default();
+        let bytes: Vec<u8> = (0..=255).chain(b"SCORE<1>\x1C".iter().copied()).collect();
+        image.load(0x100, &bytes).unwrap();
+        let symbols = SymbolTable::parse(
+            "start $100 ; entry\nscore $20F8\nmiddle $102\n$200..$207 string\n$208 byte",
+        )
+        .unwrap();
+        let styles = [
+            (HexStyle::Dollar, false),
+            (HexStyle::Suffix, false),
+            (HexStyle::Prefix, true),
+        ];
+        for (hex, lowercase) in styles {
+            let mut options = Options {
+                entries: vec![0x100],
+                format: Format::Source,
+                ..Options::default()
+            };
+            options.style.syntax.hex = hex;
+            options.style.syntax.lowercase = lowercase;
+            assert_eq!(
+                (0x100, bytes.clone()),
+                reassemble(&image, &options, symbols.clone())
+            );
+        }
+
+        let cpudiag = include_bytes!("../../../../roms/cpudiag.bin");
+        let mut image = MemoryImage::default();
+        image.load(0x100, cpudiag).unwrap();
+        let options = Options {
+            entries: vec![0x100],
+            format: Format::Source,
+            ..Options::default()
+        };
+        let (origin, bytes) = reassemble(&image, &options, SymbolTable::default());
+        assert_eq!(0x100, origin);
+        // trailing bytes reassemble as DB, so lengths match too
+        assert_eq!(cpudiag.to_vec(), bytes);
+    }
+
+    #[test]
+    fn disassembler_xrefs() {
+        let mut image = MemoryImage::default();
+        // CALL $0106, STA $20F8, RET, JMP $0106
+        #[rustfmt::skip]
+        image.load(0x100, &[0xCD, 0x06, 0x01, 0x32, 0xF8, 0x20, 0xC9, 0xC3, 0x06, 0x01]).unwrap();
+        let options = Options {
+            entries: vec![0x100, 0x107],
+            ..Options::default()
+        };
+        let analysis = analyse(&image, &options, &SymbolTable::default(), None);
+        let (lines, symbols) = lines(&image, &options, SymbolTable::default(), analysis.as_ref());
+        let xrefs = xrefs(&lines);
+
+        let comments = Comments {
+            symbols: &symbols,
+            xrefs: Some(&xrefs),
+            ports: None,
+        };
+        let mut out = Vec::new();
+        print(
+            &mut out,
+            &lines,
+            &comments,
+            None,
+            Format::Plain,
+            &options.style,
+        )
+        .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("SUB_0106:\n    RET  ; xref: 0100 CALL, 0107 JMP\n"));
+
+        let mut out = Vec::new();
+        print_xrefs(&mut out, &lines, &symbols, &xrefs).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("0106  SUB_0106\n    0100  call  CALL SUB_0106\n"));
+        assert!(text.contains("20F8  \n    0103  data  STA  $20F8\n"));
+
+        let mut out = Vec::new();
+        print_json(&mut out, &lines, &symbols, &xrefs).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(0x106, json[0]["address"]);
+        assert_eq!("SUB_0106", json[0]["name"]);
+        assert_eq!("jump", json[0]["refs"][1]["kind"]);
+        assert_eq!(serde_json::Value::Null, json[1]["name"]);
+    }
+
+    #[test]
+    fn disassembler_ports() {
+        let mut image = MemoryImage::default();
+        // IN 1, OUT 3, OUT 7
+        image
+            .load(0, &[0xDB, 0x01, 0xD3, 0x03, 0xD3, 0x07])
+            .unwrap();
+        let options = Options {
+            linear: true,
+            ..Options::default()
+        };
+        let symbols = SymbolTable::parse("2 ; sounds").unwrap();
+        let lines = sweep(&image, &options, &symbols);
+        let ports = PortMap::parse(
+            "in 1 inputs 0=coin
+out 3 sound 0-1=ufo",
+        )
+        .unwrap();
+        let comments = Comments {
+            symbols: &symbols,
+            xrefs: None,
+            ports: Some(&ports),
+        };
+        let mut out = Vec::new();
+        print(
+            &mut out,
+            &lines,
+            &comments,
+            None,
+            Format::Plain,
+            &options.style,
+        )
+        .unwrap();
+        assert_eq!(
+            "    IN   $01  ; port: inputs (0 coin)
+    \
+             OUT  $03  ; sounds; port: sound (0-1 ufo)
+    \
+             OUT  $07\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn disassembler_search() {
+        let mut image = MemoryImage::default();
+        // CALL $0006, JMP $0000, MVI A,$01, OUT $03, RET, then MVI A,$02, OUT $03 as data
+        image
+            .load(
+                0,
+                &[
+                    0xCD, 0x06, 0x00, 0xC3, 0x00, 0x00, 0x3E, 0x01, 0xD3, 0x03, 0xC9, 0x3E, 0x02,
+                    0xD3, 0x03,
+                ],
+            )
+            .unwrap();
+        let options = Options::default();
+        let symbols = SymbolTable::parse("sound 6").unwrap();
+        let analysis = analyse(&image, &options, &symbols, None);
+        let (lines, _) = lines(&image, &options, symbols, analysis.as_ref());
+        let signatures = Signature::parse_file(
+            "play = MVI A,?; OUT 03
+calls = CALL ????",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        print_matches(&mut out, &lines, &signatures).unwrap();
+        assert_eq!(
+            "0006  play  MVI  A, $01; OUT  $03\n0000  calls  CALL sound\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let options = Options {
+            linear: true,
+            ..Options::default()
+        };
+        let lines = sweep(&image, &options, &SymbolTable::default());
+        let signatures = [Signature {
+            name: String::new(),
+            pattern: Pattern::parse("mvi a,?; out ?").unwrap(),
+        }];
+        let mut out = Vec::new();
+        print_matches(&mut out, &lines, &signatures).unwrap();
+        assert_eq!(
+            "0006  MVI  A, $01; OUT  $03\n000B  MVI  A, $02; OUT  $03\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn disassembler_html() {
+        let mut image = MemoryImage::default();
+        // CALL $0006, JMP $0000, LXI H,$000A, RET, then "A<" and a sprite
+        image
+            .load(
+                0,
+                &[
+                    0xCD, 0x06, 0x00, 0xC3, 0x00, 0x00, 0x21, 0x0A, 0x00, 0xC9, 0x41, 0x3C, 0x18,
+                    0x3C,
+                ],
+            )
+            .unwrap();
+        let options = Options {
+            format: Format::Html,
+            ..Options::default()
+        };
+        let symbols = SymbolTable::parse("sub 6\n$A..$B bytes\n$C..$D sprite ; ship").unwrap();
+        let analysis = analyse(&image, &options, &symbols, None);
+        let (lines, symbols) = lines(&image, &options, symbols, analysis.as_ref());
+        let xrefs = xrefs(&lines);
+        let comments = Comments {
+            symbols: &symbols,
+            xrefs: Some(&xrefs),
+            ports: None,
+        };
+        let mut out = Vec::new();
+        print_html(
+            &mut out,
+            &image,
+            &lines,
+            &comments,
+            None,
+            &options.style,
+            "a&b.bin",
+        )
+        .unwrap();
+        let html = String::from_utf8(out).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.ends_with("</html>\n"));
+        assert!(html.contains("<title>a&amp;b.bin</title>"));
+        for line in [
+            "0000  CD 06 00  CALL <a href=\"#a0006\">sub</a>\n",
+            "<span class=\"label\" id=\"a0006\">sub:</span>  \
+             <span class=\"comment\">; xref: <a href=\"#a0000\">0000 CALL</a></span>\n",
+            "0006  21 0A 00  LXI  H, <a href=\"#a000A\">$000A</a>\n",
+            "<span id=\"a0009\">0009</span>  C9        RET\n",
+            "<span class=\"data\">41 3C                  </span>  A&lt;  \
+             <span class=\"comment\">; xref: <a href=\"#a0006\">0006 LXI</a></span>\n",
+            "<path d=\"M0 4h1v1h-1zM0 3h1v1h-1zM1 5h1v1h-1zM1 4h1v1h-1zM1 3h1v1h-1zM1 2h1v1h-1z\"/>\
+             </svg>  <span class=\"comment\">; ship</span>\n",
+        ] {
+            assert!(html.contains(line), "{}", line);
+        }
+    }
+
+    #[test]
+    fn disassembler_graphs() {
+        let mut image = MemoryImage::default();
+        // CALL $0104, HLT, PCHL
+        image.load(0x100, &[0xCD, 0x04, 0x01, 0x76, 0xE9]).unwrap();
+        let options = Options {
+            entries: vec![0x100],
+            ..Options::default()
+        };
+        let analysis = analyse(&image, &options, &SymbolTable::default(), None).unwrap();
+        let (lines, symbols) = lines(&image, &options, SymbolTable::default(), Some(&analysis));
+        let graph = Graph::new(&analysis, &entries(&options), None);
+
+        let mut out = Vec::new();
+        print_cfg(&mut out, &lines, &symbols, &graph).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+        assert!(dot.contains("    b0100 [label=\"0100  CALL SUB_0104\\l\"];\n"));
+        assert!(dot.contains("    b0104 [label=\"SUB_0104:\\l0104  PCHL\\l\", color=red];\n"));
+        assert!(dot.contains("    b0104 -> u0104 [style=dashed, color=red];\n"));
+        assert!(dot.contains("    b0100 -> b0104 [style=dashed, color=blue];\n"));
+        assert!(dot.contains("    b0100 -> b0103 [style=dotted];\n"));
+
+        let mut out = Vec::new();
+        print_calls(&mut out, &symbols, &graph).unwrap();
+        let dot = String::from_utf8(out).unwrap();
+        assert!(dot.contains("    f0104 [label=\"SUB_0104\", color=red];\n"));
+        assert!(dot.contains("    f0100 -> f0104;\n"));
+
+        let mut out = Vec::new();
+        print_graph_json(&mut out, &lines, &symbols, &graph).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!("pchl", json["blocks"][2]["unresolved"]);
+        assert_eq!("call", json["edges"][1]["kind"]);
+        assert_eq!(0x104, json["functions"][0]["calls"][0]);
+    }
+}
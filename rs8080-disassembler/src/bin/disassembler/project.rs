@@ -0,0 +1,526 @@
+use crate::cli::{input, Input, Ports};
+use rs8080_disassembler::{
+    is_name, parse_number, DataKind, DataRegion, Port, PortMap, SymbolTable,
+};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+
+/// Project file of `project` commands without `--project`
+pub(crate) const DEFAULT_PROJECT: &str = "disassembler.toml";
+
+/// Written as `$01E4`, so keys sort by address and edits keep the file stable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Address(pub u16);
+
+/// Port number written as `$01`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct PortNumber(pub u8);
+
+fn number<'de, D: Deserializer<'de>>(deserializer: D, max: u32) -> Result<u32, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_number(&text)
+        .filter(|n| *n <= max)
+        .ok_or_else(|| de::Error::custom(format!("invalid number '{}'", text)))
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("${:04X}", self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        number(deserializer, 0xFFFF).map(|a| Address(a as u16))
+    }
+}
+
+impl Serialize for PortNumber {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("${:02X}", self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for PortNumber {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        number(deserializer, 0xFF).map(|p| PortNumber(p as u8))
+    }
+}
+
+/// ROM file and the address it is loaded at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct File {
+    pub path: PathBuf,
+    pub address: Address,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Data {
+    /// `bytes`, `words`, `string` or `sprite`
+    pub kind: String,
+    /// Last address, without it one value or a string
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<Address>,
+}
+
+/// Names and bits of ports as in port maps, e.g. `inputs1 0=coin 1=start2`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct PortNames {
+    #[serde(rename = "in")]
+    pub inputs: BTreeMap<PortNumber, String>,
+    #[serde(rename = "out")]
+    pub outputs: BTreeMap<PortNumber, String>,
+}
+
+impl PortNames {
+    fn direction(&mut self, output: bool) -> &mut BTreeMap<PortNumber, String> {
+        match output {
+            true => &mut self.outputs,
+            false => &mut self.inputs,
+        }
+    }
+}
+
+/// Everything assigned during analysis, kept in one TOML file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Project {
+    /// Code entry points besides reset and `RST` vectors
+    pub entries: BTreeSet<Address>,
+    pub files: Vec<File>,
+    pub labels: BTreeMap<Address, String>,
+    pub comments: BTreeMap<Address, String>,
+    /// Data regions by start address
+    pub data: BTreeMap<Address, Data>,
+    pub ports: PortNames,
+}
+
+/// Change made by a `project` command
+#[derive(Debug, PartialEq)]
+pub(crate) enum Edit {
+    /// New project of the files
+    Init(Vec<Input>),
+    Label(u16, String),
+    Comment(u16, String),
+    Data {
+        start: u16,
+        end: Option<u16>,
+        kind: DataKind,
+    },
+    Entry(u16),
+    Port {
+        output: bool,
+        port: u8,
+        /// Name and bits, e.g. `inputs1 0=coin`
+        names: String,
+    },
+    /// Adds every port of a map
+    Ports(Ports),
+    Remove(Removal),
+}
+
+/// What `project remove` removes
+#[derive(Debug, PartialEq)]
+pub(crate) enum Removal {
+    Label(u16),
+    Comment(u16),
+    Data(u16),
+    Entry(u16),
+    Port { output: bool, port: u8 },
+}
+
+fn address(text: &str) -> Result<u16, String> {
+    parse_number(text)
+        .and_then(|a| u16::try_from(a).ok())
+        .ok_or_else(|| format!("invalid address '{}'", text))
+}
+
+/// `in` or `out` and a port number
+fn port(direction: &str, port: &str) -> Result<(bool, u8), String> {
+    let output = match direction.to_ascii_lowercase().as_str() {
+        "in" => false,
+        "out" => true,
+        _ => return Err(format!("expected in or out, got '{}'", direction)),
+    };
+    let port = parse_number(port)
+        .and_then(|p| u8::try_from(p).ok())
+        .ok_or_else(|| format!("invalid port '{}'", port))?;
+    Ok((output, port))
+}
+
+/// Port map line of `port` without direction and number
+fn port_names(port: &Port) -> String {
+    let mut words = vec![port.name.clone()];
+    words.extend(port.bits.iter().map(|bits| match bits.low == bits.high {
+        true => format!("{}={}", bits.low, bits.name),
+        false => format!("{}-{}={}", bits.low, bits.high, bits.name),
+    }));
+    words.join(" ")
+}
+
+impl Edit {
+    /// Words after `project`
+    pub(crate) fn parse(words: &[String]) -> Result<Edit, String> {
+        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+        Ok(match words.as_slice() {
+            ["init", files @ ..] if !files.is_empty() => Edit::Init(
+                files
+                    .iter()
+                    .map(|file| input(file))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ["label", at, name] if is_name(name) => Edit::Label(address(at)?, name.to_string()),
+            ["label", _, name] => return Err(format!("invalid name '{}'", name)),
+            ["comment", at, text @ ..] if !text.is_empty() => {
+                Edit::Comment(address(at)?, text.join(" "))
+            }
+            ["data", range, kind] => {
+                let kind =
+                    DataKind::parse(kind).ok_or_else(|| format!("unknown data kind '{}'", kind))?;
+                let (start, end) = match range.split_once("..") {
+                    Some((start, end)) => (address(start)?, Some(address(end)?)),
+                    None => (address(range)?, None),
+                };
+                if end.is_some_and(|end| end < start) {
+                    return Err(format!("invalid range '{}'", range));
+                }
+                Edit::Data { start, end, kind }
+            }
+            ["entry", at] => Edit::Entry(address(at)?),
+            ["port", direction, number, names @ ..] if !names.is_empty() => {
+                let (output, port) = port(direction, number)?;
+                let names = names.join(" ");
+                PortMap::parse(&format!("in 0 {}", names)).map_err(|err| err.message)?;
+                Edit::Port {
+                    output,
+                    port,
+                    names,
+                }
+            }
+            ["ports", "invaders"] => Edit::Ports(Ports::Invaders),
+            ["ports", path] => Edit::Ports(Ports::File(path.into())),
+            ["remove", "label", at] => Edit::Remove(Removal::Label(address(at)?)),
+            ["remove", "comment", at] => Edit::Remove(Removal::Comment(address(at)?)),
+            ["remove", "data", at] => Edit::Remove(Removal::Data(address(at)?)),
+            ["remove", "entry", at] => Edit::Remove(Removal::Entry(address(at)?)),
+            ["remove", "port", direction, number] => {
+                let (output, port) = port(direction, number)?;
+                Edit::Remove(Removal::Port { output, port })
+            }
+            _ => return Err(format!("invalid project command '{}'", words.join(" "))),
+        })
+    }
+}
+
+impl Project {
+    pub(crate) fn parse(text: &str) -> Result<Project, String> {
+        let project: Project = toml::from_str(text).map_err(|err| err.to_string())?;
+        project.symbols()?;
+        project.port_map()?;
+        Ok(project)
+    }
+
+    /// Tables and keys are sorted, so the same project is always written the same way
+    pub(crate) fn to_toml(&self) -> String {
+        toml::to_string(self).expect("project is valid TOML")
+    }
+
+    /// Files with the base address for those given without one, their paths
+    /// are stored relative to `dir`, the directory of the project file
+    pub(crate) fn init(inputs: &[Input], base: u16, dir: &Path) -> Project {
+        let files = inputs
+            .iter()
+            .map(|input| File {
+                path: relative_to(&input.path, dir),
+                address: Address(input.address.unwrap_or(base)),
+            })
+            .collect();
+        Project {
+            files,
+            ..Project::default()
+        }
+    }
+
+    /// `map` is the port map of a `ports` command
+    pub(crate) fn apply(&mut self, edit: &Edit, map: Option<&PortMap>) {
+        match edit {
+            Edit::Init(_) => {}
+            Edit::Label(at, name) => {
+                self.labels.insert(Address(*at), name.clone());
+            }
+            Edit::Comment(at, text) => {
+                self.comments.insert(Address(*at), text.clone());
+            }
+            Edit::Data { start, end, kind } => {
+                let data = Data {
+                    kind: kind.name().to_owned(),
+                    end: end.map(Address),
+                };
+                self.data.insert(Address(*start), data);
+            }
+            Edit::Entry(at) => {
+                self.entries.insert(Address(*at));
+            }
+            Edit::Port {
+                output,
+                port,
+                names,
+            } => {
+                (self.ports.direction(*output)).insert(PortNumber(*port), names.clone());
+            }
+            Edit::Ports(_) => {
+                let Some(map) = map else { return };
+                for (port, names) in map.inputs() {
+                    let names = port_names(names);
+                    self.ports.inputs.insert(PortNumber(port), names);
+                }
+                for (port, names) in map.outputs() {
+                    let names = port_names(names);
+                    self.ports.outputs.insert(PortNumber(port), names);
+                }
+            }
+            Edit::Remove(Removal::Label(at)) => {
+                self.labels.remove(&Address(*at));
+            }
+            Edit::Remove(Removal::Comment(at)) => {
+                self.comments.remove(&Address(*at));
+            }
+            Edit::Remove(Removal::Data(at)) => {
+                self.data.remove(&Address(*at));
+            }
+            Edit::Remove(Removal::Entry(at)) => {
+                self.entries.remove(&Address(*at));
+            }
+            Edit::Remove(Removal::Port { output, port }) => {
+                self.ports.direction(*output).remove(&PortNumber(*port));
+            }
+        }
+    }
+
+    /// Files with paths resolved against `dir`, the directory of the project file
+    pub(crate) fn inputs(&self, dir: &Path) -> Vec<Input> {
+        self.files
+            .iter()
+            .map(|file| Input {
+                path: dir.join(&file.path),
+                address: Some(file.address.0),
+            })
+            .collect()
+    }
+
+    pub(crate) fn entries(&self) -> impl Iterator<Item = u16> + '_ {
+        self.entries.iter().map(|at| at.0)
+    }
+
+    pub(crate) fn symbols(&self) -> Result<SymbolTable, String> {
+        let mut symbols = SymbolTable::default();
+        for (at, name) in &self.labels {
+            if !is_name(name) {
+                return Err(format!("invalid name '{}' at ${:04X}", name, at.0));
+            }
+            symbols.insert(at.0, name);
+        }
+        for (at, comment) in &self.comments {
+            symbols.insert_comment(at.0, comment);
+        }
+        for (start, data) in &self.data {
+            let kind = DataKind::parse(&data.kind)
+                .ok_or_else(|| format!("unknown data kind '{}'", data.kind))?;
+            let end = match data.end {
+                Some(end) if end < *start => {
+                    return Err(format!("data at ${:04X} ends before it starts", start.0))
+                }
+                end => end.map(|end| end.0 as u32 + 1),
+            };
+            symbols.insert_data(DataRegion {
+                start: start.0,
+                end,
+                kind,
+            });
+        }
+        Ok(symbols)
+    }
+
+    /// `None` without ports
+    pub(crate) fn port_map(&self) -> Result<Option<PortMap>, String> {
+        let lines: Vec<String> = (self.ports.inputs.iter().map(|port| ("in", port)))
+            .chain(self.ports.outputs.iter().map(|port| ("out", port)))
+            .map(|(direction, (port, names))| format!("{} {} {}", direction, port.0, names))
+            .collect();
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        PortMap::parse(&lines.join("\n"))
+            .map(Some)
+            .map_err(|err| format!("port {}: {}", lines[err.line - 1], err.message))
+    }
+}
+
+/// Directory that the paths of the project file at `path` are relative to
+pub(crate) fn project_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Absolute path without `.` and `..`
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normal = PathBuf::new();
+    for component in std::path::absolute(path).ok()?.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normal.pop();
+            }
+            _ => normal.push(component),
+        }
+    }
+    Some(normal)
+}
+
+/// `path` relative to `dir`, both relative to the current directory;
+/// `path` is kept as it is if it is absolute or on another drive
+fn relative_to(path: &Path, dir: &Path) -> PathBuf {
+    let (Some(full), Some(dir)) = (normalize(path), normalize(dir)) else {
+        return path.to_owned();
+    };
+    if path.is_absolute() || full.components().next() != dir.components().next() {
+        return path.to_owned();
+    }
+    let common = full
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut relative: PathBuf = dir.components().skip(common).map(|_| "..").collect();
+    relative.extend(full.components().skip(common));
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(project: &mut Project, line: &str) {
+        let words: Vec<String> = line.split_whitespace().map(String::from).collect();
+        project.apply(&Edit::parse(&words).unwrap(), None);
+    }
+
+    #[test]
+    fn project_edits_are_stable() {
+        let inputs = vec![
+            Input {
+                path: "invaders.h".into(),
+                address: None,
+            },
+            Input {
+                path: "invaders.g".into(),
+                address: Some(0x800),
+            },
+        ];
+        let mut project = Project::init(&inputs, 0, Path::new("."));
+        for line in [
+            "label 0x01E4 DrawSprite",
+            "label $0008 ISR_1",
+            "comment 1E4H draws a sprite # at HL",
+            "data $1C00..$1C0F sprite",
+            "data 0x1A00 words",
+            "entry 0x1A5C",
+            "entry 0x0100",
+            "port out 3 sound1 0=ufo 1-2=shot",
+        ] {
+            edit(&mut project, line);
+        }
+        project.apply(
+            &Edit::Ports(Ports::Invaders),
+            Some(&PortMap::space_invaders()),
+        );
+        let text = project.to_toml();
+        let parsed = Project::parse(&text).unwrap();
+        assert_eq!(project, parsed);
+        assert_eq!(text, parsed.to_toml());
+        assert!(text.contains("\"$01E4\" = \"DrawSprite\""));
+        assert!(text.contains("entries = [\"$0100\", \"$1A5C\"]"));
+        assert!(text.find("\"$0008\"").unwrap() < text.find("\"$01E4\"").unwrap());
+
+        let symbols = project.symbols().unwrap();
+        assert_eq!(Some("DrawSprite"), symbols.get(0x1E4));
+        assert_eq!(Some("draws a sprite # at HL"), symbols.comment(0x1E4));
+        assert_eq!(Some(0x1C10), symbols.data()[1].end);
+        let ports = project.port_map().unwrap().unwrap();
+        assert_eq!("sound1", ports.output(3).unwrap().name);
+        assert_eq!("inputs1", ports.input(1).unwrap().name);
+        assert_eq!(vec![0x100, 0x1A5C], project.entries().collect::<Vec<_>>());
+        assert_eq!(Some(0), project.inputs(Path::new("."))[0].address);
+
+        edit(&mut project, "remove label 0x1E4");
+        edit(&mut project, "remove port out 3");
+        edit(&mut project, "remove entry $100");
+        assert_eq!(None, project.symbols().unwrap().get(0x1E4));
+        assert_eq!(None, project.port_map().unwrap().unwrap().output(3));
+        assert_eq!(1, project.entries.len());
+
+        let parse = |line: &str| {
+            let words: Vec<String> = line.split_whitespace().map(String::from).collect();
+            Edit::parse(&words)
+        };
+        assert_eq!(
+            Edit::Init(vec![Input {
+                path: "a.bin".into(),
+                address: Some(0x800)
+            }]),
+            parse("init a.bin@0x800").unwrap()
+        );
+        assert!(parse("label 0x10000 a").is_err());
+        assert!(parse("label 0 1a").is_err());
+        assert!(parse("data 2..1 bytes").is_err());
+        assert!(parse("data 1 floats").is_err());
+        assert!(parse("port in 1 a 8=b").is_err());
+        assert!(parse("init").is_err());
+        assert!(parse("rename 1 a").is_err());
+        assert!(Project::parse("[labels]\n\"$0001\" = \"1a\"").is_err());
+        assert!(Project::parse("[labels]\n\"$10000\" = \"a\"").is_err());
+        assert!(Project::parse("colors = 1").is_err());
+    }
+
+    #[test]
+    fn project_paths_are_relative_to_its_file() {
+        let input = |path: &str| Input {
+            path: path.into(),
+            address: None,
+        };
+        let inputs = [input("roms/invaders.h"), input("./b.bin")];
+        let dir = project_dir(Path::new("work/listing/disassembler.toml"));
+        let project = Project::init(&inputs, 0, dir);
+        assert_eq!(
+            vec![
+                PathBuf::from("../../roms/invaders.h"),
+                PathBuf::from("../../b.bin")
+            ],
+            project
+                .files
+                .iter()
+                .map(|f| f.path.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Path::new("work/listing/../../roms/invaders.h"),
+            project.inputs(dir)[0].path
+        );
+        let dir = project_dir(Path::new("roms/disassembler.toml"));
+        assert_eq!(
+            Path::new("invaders.h"),
+            Project::init(&inputs, 0, dir).files[0].path
+        );
+        assert_eq!(Path::new("."), project_dir(Path::new("disassembler.toml")));
+        assert_eq!(
+            Path::new("/roms/a.bin"),
+            relative_to(Path::new("/roms/a.bin"), Path::new("sub"))
+        );
+    }
+}
@@ -0,0 +1,236 @@
+use rs8080_disassembler::*;
+use std::error::Error;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+const USAGE: &str = "\
+usage: romdiff [options] <old> <new>
+    <old>, <new>            comma-separated files with load addresses,
+                            e.g. invaders.h@0,invaders.g@0x800
+    -b, --base <address>    load address of files given without one, 0 by default
+    --entry <address>       code entry point of both images besides reset and RST vectors,
+                            can be repeated
+    --all                   list routines that are the same or only moved too
+    --map                   list old and new addresses of every matched instruction
+addresses are decimal, or hex with 0x, $ prefix or H suffix";
+
+/// Files of one image and the addresses they are loaded at
+#[derive(Debug, PartialEq)]
+struct Side {
+    files: Vec<(PathBuf, Option<u16>)>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    sides: Vec<Side>,
+    base: u16,
+    entries: Vec<u16>,
+    all: bool,
+    map: bool,
+}
+
+fn address(text: &str) -> Result<u16, String> {
+    parse_number(text)
+        .and_then(|a| u16::try_from(a).ok())
+        .ok_or_else(|| format!("invalid address '{}'", text))
+}
+
+fn side(arg: &str) -> Result<Side, String> {
+    let files = arg
+        .split(',')
+        .map(|file| match file.rsplit_once('@') {
+            Some((path, at)) if !path.is_empty() => Ok((path.into(), Some(address(at)?))),
+            _ => Ok((file.into(), None)),
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Side { files })
+}
+
+fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "-b" | "--base" => options.base = address(&value()?)?,
+            "--entry" => options.entries.push(address(&value()?)?),
+            "--all" => options.all = true,
+            "--map" => options.map = true,
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option '{}'\n{}", arg, USAGE))
+            }
+            _ => options.sides.push(side(&arg)?),
+        }
+    }
+    if options.sides.len() != 2 {
+        return Err(USAGE.to_owned());
+    }
+    Ok(options)
+}
+
+fn load(side: &Side, base: u16) -> Result<MemoryImage, Box<dyn Error>> {
+    let mut image = MemoryImage::default();
+    for (path, address) in &side.files {
+        let name = path.display();
+        let data = std::fs::read(path).map_err(|err| format!("{}: {}", name, err))?;
+        image
+            .load(address.unwrap_or(base), &data)
+            .map_err(|err| format!("{}: {}", name, err))?;
+    }
+    Ok(image)
+}
+
+/// Address or dashes for the missing side
+fn at(address: Option<u16>) -> String {
+    match address {
+        Some(address) => format!("{:04X}", address),
+        None => "----".to_owned(),
+    }
+}
+
+/// Routines that differ with their changed instructions and a summary,
+/// with `--all` also same and moved routines, with `--map` every matched instruction
+fn report(
+    out: &mut impl Write,
+    old: &Program,
+    new: &Program,
+    diffs: &[RoutineDiff],
+    options: &Options,
+) -> io::Result<()> {
+    let symbols = SymbolTable::default();
+    let text = |program: &Program, address: u16| match program.analysis.instruction(address) {
+        Some(cmd) => cmd.text(&symbols),
+        None => String::new(),
+    };
+    let mut counts = [0; 5];
+    for diff in diffs {
+        counts[diff.status as usize] += 1;
+        let listed = match diff.status {
+            RoutineStatus::Same | RoutineStatus::Moved => options.all,
+            _ => true,
+        };
+        if !listed {
+            continue;
+        }
+        write!(
+            out,
+            "{:<9} {} -> {}",
+            diff.status.name(),
+            at(diff.old),
+            at(diff.new)
+        )?;
+        match diff.status {
+            RoutineStatus::Inserted | RoutineStatus::Deleted => {
+                writeln!(out, "  ({} instructions)", diff.changes.len())?;
+                continue;
+            }
+            _ => writeln!(out)?,
+        }
+        for change in &diff.changes {
+            match *change {
+                Change::Equal { old: a, new: b } if options.map => {
+                    writeln!(out, "    {:04X} -> {:04X}    {}", a, b, text(old, a))?
+                }
+                Change::Equal { .. } => {}
+                Change::Changed { old: a, new: b } => writeln!(
+                    out,
+                    "    {:04X} -> {:04X}  ~ {} => {}",
+                    a,
+                    b,
+                    text(old, a),
+                    text(new, b)
+                )?,
+                Change::Deleted { old: a } => {
+                    writeln!(out, "    {:04X} -> ----  - {}", a, text(old, a))?
+                }
+                Change::Inserted { new: b } => {
+                    writeln!(out, "    ---- -> {:04X}  + {}", b, text(new, b))?
+                }
+            }
+        }
+    }
+    let [same, moved, changed, inserted, deleted] = counts;
+    writeln!(
+        out,
+        "{} same, {} moved, {} changed, {} inserted, {} deleted",
+        same, moved, changed, inserted, deleted
+    )
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut entries = DEFAULT_ENTRIES.to_vec();
+    entries.extend(&options.entries);
+    let old_image = load(&options.sides[0], options.base)?;
+    let new_image = load(&options.sides[1], options.base)?;
+    let old_analysis = Analysis::new(&old_image, &entries);
+    let new_analysis = Analysis::new(&new_image, &entries);
+    let old = Program::new(&old_analysis, &Graph::new(&old_analysis, &entries, None));
+    let new = Program::new(&new_analysis, &Graph::new(&new_analysis, &entries, None));
+    let diffs = diff(&old, &new);
+    let mut out = BufWriter::new(io::stdout().lock());
+    match report(&mut out, &old, &new, &diffs, options).and_then(|_| out.flush()) {
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+fn main() {
+    let result = parse(std::env::args().skip(1))
+        .map_err(|err| err.into())
+        .and_then(|options| run(&options));
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn romdiff_parse_and_report() {
+        let options = parse(args("a.h@0,a.g@0x800 b.bin --entry 0x100 -b 8 --map")).unwrap();
+        assert_eq!(
+            vec![
+                (PathBuf::from("a.h"), Some(0)),
+                (PathBuf::from("a.g"), Some(0x800))
+            ],
+            options.sides[0].files
+        );
+        assert_eq!(vec![(PathBuf::from("b.bin"), None)], options.sides[1].files);
+        assert_eq!((8, vec![0x100]), (options.base, options.entries.clone()));
+        assert!(options.map && !options.all);
+        assert!(parse(args("a.bin")).is_err());
+        assert!(parse(args("a.bin b.bin c.bin")).is_err());
+        assert!(parse(args("a.bin@x b.bin")).is_err());
+
+        // CALL $0004, RET, then MVI A, $01 and RET moved by one NOP
+        let program = |bytes: &[u8]| {
+            let mut image = MemoryImage::default();
+            image.load(0, bytes).unwrap();
+            let analysis = Analysis::new(&image, &[0]);
+            let graph = Graph::new(&analysis, &[0], None);
+            (analysis, graph)
+        };
+        let (old_analysis, old_graph) = program(&[0xCD, 0x04, 0x00, 0xC9, 0x3E, 0x01, 0xC9]);
+        let (new_analysis, new_graph) = program(&[0xCD, 0x05, 0x00, 0xC9, 0x00, 0x3E, 0x02, 0xC9]);
+        let old = Program::new(&old_analysis, &old_graph);
+        let new = Program::new(&new_analysis, &new_graph);
+        let mut out = Vec::new();
+        report(&mut out, &old, &new, &diff(&old, &new), &options).unwrap();
+        assert_eq!(
+            "changed   0004 -> 0005\n    \
+             0004 -> 0005  ~ MVI  A, $01 => MVI  A, $02\n    \
+             0006 -> 0007    RET\n\
+             1 same, 0 moved, 1 changed, 0 inserted, 0 deleted\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,364 @@
+use rs8080_disassembler::*;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+mod png_file;
+
+const USAGE: &str = "\
+usage: sprites list|extract|insert [options] <file[@address]>...
+    -b, --base <address>    load address of files given without one, 0 by default
+    -d, --dir <dir>         directory of the images, . by default
+    -f, --format <format>   png (default) or pbm
+    --scan                  also sprites found in bytes that are not code
+    --entry <address>       code entry point for --scan besides reset and RST vectors,
+                            can be repeated
+    -o, --output <dir>      directory for the files patched by insert
+list prints the sprite sheets of Space Invaders and, with --scan, found ones
+extract writes every sheet to <dir>/<name>.png or .pbm with its sprites side by side
+insert reads the image of every sheet that has one, sprite_XXXX images are put at
+XXXX, and writes the patched files to --output
+addresses are decimal, or hex with 0x, $ prefix or H suffix";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Action {
+    #[default]
+    List,
+    Extract,
+    Insert,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum ImageFormat {
+    #[default]
+    Png,
+    Pbm,
+}
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Pbm => "pbm",
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    action: Action,
+    files: Vec<(PathBuf, Option<u16>)>,
+    base: u16,
+    dir: PathBuf,
+    format: ImageFormat,
+    scan: bool,
+    entries: Vec<u16>,
+    output: Option<PathBuf>,
+}
+
+/// ROM file with its load address and bytes
+struct RomFile {
+    path: PathBuf,
+    address: u16,
+    data: Vec<u8>,
+}
+
+fn address(text: &str) -> Result<u16, String> {
+    parse_number(text)
+        .and_then(|a| u16::try_from(a).ok())
+        .ok_or_else(|| format!("invalid address '{}'", text))
+}
+
+fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        action: match args.next().as_deref() {
+            Some("list") => Action::List,
+            Some("extract") => Action::Extract,
+            Some("insert") => Action::Insert,
+            _ => return Err(USAGE.to_owned()),
+        },
+        dir: ".".into(),
+        ..Options::default()
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "-b" | "--base" => options.base = address(&value()?)?,
+            "-d" | "--dir" => options.dir = value()?.into(),
+            "-f" | "--format" => {
+                options.format = match value()?.as_str() {
+                    "png" => ImageFormat::Png,
+                    "pbm" => ImageFormat::Pbm,
+                    other => return Err(format!("unknown format '{}'\n{}", other, USAGE)),
+                }
+            }
+            "--scan" => options.scan = true,
+            "--entry" => options.entries.push(address(&value()?)?),
+            "-o" | "--output" => options.output = Some(value()?.into()),
+            _ if arg.starts_with('-') => {
+                return Err(format!("unknown option '{}'\n{}", arg, USAGE))
+            }
+            _ => options.files.push(match arg.rsplit_once('@') {
+                Some((path, at)) if !path.is_empty() => (path.into(), Some(address(at)?)),
+                _ => (arg.into(), None),
+            }),
+        }
+    }
+    if options.files.is_empty() || (options.action == Action::Insert) != options.output.is_some() {
+        return Err(USAGE.to_owned());
+    }
+    Ok(options)
+}
+
+fn load(options: &Options) -> Result<(MemoryImage, Vec<RomFile>), Box<dyn Error>> {
+    let mut image = MemoryImage::default();
+    let mut files = Vec::new();
+    for (path, address) in &options.files {
+        let name = path.display();
+        let data = std::fs::read(path).map_err(|err| format!("{}: {}", name, err))?;
+        let address = address.unwrap_or(options.base);
+        image
+            .load(address, &data)
+            .map_err(|err| format!("{}: {}", name, err))?;
+        files.push(RomFile {
+            path: path.clone(),
+            address,
+            data,
+        });
+    }
+    Ok((image, files))
+}
+
+fn is_loaded(image: &MemoryImage, sheet: &SpriteSheet) -> bool {
+    image.bytes_from(sheet.address).len() >= sheet.len()
+}
+
+/// Known sheets that are loaded, with `--scan` followed by found sprites
+/// that are not part of a known sheet
+fn sheets(image: &MemoryImage, options: &Options) -> Vec<SpriteSheet> {
+    let mut sheets: Vec<SpriteSheet> = SpriteSheet::space_invaders()
+        .into_iter()
+        .filter(|sheet| is_loaded(image, sheet))
+        .collect();
+    if options.scan {
+        let mut entries = DEFAULT_ENTRIES.to_vec();
+        entries.extend(&options.entries);
+        let analysis = Analysis::new(image, &entries);
+        let known: Vec<_> = sheets.iter().map(SpriteSheet::range).collect();
+        let found = scan(image, |a| analysis.is_code(a))
+            .into_iter()
+            .filter(|sheet| {
+                let range = sheet.range();
+                known
+                    .iter()
+                    .all(|k| range.end <= k.start || k.end <= range.start)
+            });
+        sheets.extend(found);
+    }
+    sheets
+}
+
+/// Sheet of a `sprite_XXXX` image that is not a known sheet
+fn found_sheet(name: &str, bitmap: &Bitmap) -> Option<SpriteSheet> {
+    let address = u16::from_str_radix(name.strip_prefix("sprite_")?, 16).ok()?;
+    bitmap.height.is_multiple_of(8).then(|| SpriteSheet {
+        name: name.to_owned(),
+        address,
+        width: bitmap.width,
+        rows: bitmap.height / 8,
+        count: 1,
+    })
+}
+
+/// Writes `bytes` at `address` into the files that cover it
+fn patch(files: &mut [RomFile], address: u16, bytes: &[u8]) -> Result<(), String> {
+    for (i, byte) in bytes.iter().enumerate() {
+        let at = address as usize + i;
+        let file = files
+            .iter_mut()
+            .find(|f| (f.address as usize..f.address as usize + f.data.len()).contains(&at))
+            .ok_or_else(|| format!("${:04X} is not loaded", at))?;
+        file.data[at - file.address as usize] = *byte;
+    }
+    Ok(())
+}
+
+fn read_bitmap(path: &Path, format: ImageFormat) -> Result<Bitmap, String> {
+    let data = std::fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+    match format {
+        ImageFormat::Png => png_file::read(&data),
+        ImageFormat::Pbm => Bitmap::parse_pbm(&data),
+    }
+    .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Sheets that have an image in `--dir` with their bitmaps
+fn images(
+    image: &MemoryImage,
+    options: &Options,
+) -> Result<Vec<(SpriteSheet, Bitmap)>, Box<dyn Error>> {
+    let extension = options.format.extension();
+    let mut images = Vec::new();
+    for sheet in sheets(image, options) {
+        let path = options.dir.join(format!("{}.{}", sheet.name, extension));
+        if path.exists() {
+            let bitmap = read_bitmap(&path, options.format)?;
+            images.push((sheet, bitmap));
+        }
+    }
+    let dir = std::fs::read_dir(&options.dir)
+        .map_err(|err| format!("{}: {}", options.dir.display(), err))?;
+    let mut paths: Vec<PathBuf> = dir.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    paths.sort();
+    for path in paths {
+        let (Some(name), Some(ext)) = (path.file_stem(), path.extension()) else {
+            continue;
+        };
+        let name = name.to_string_lossy();
+        if ext != extension || images.iter().any(|(sheet, _)| sheet.name == name) {
+            continue;
+        }
+        if !name.starts_with("sprite_") {
+            continue;
+        }
+        let bitmap = read_bitmap(&path, options.format)?;
+        let sheet = found_sheet(&name, &bitmap).ok_or_else(|| {
+            format!(
+                "{}: expected sprite_XXXX with a height of a multiple of 8",
+                path.display()
+            )
+        })?;
+        images.push((sheet, bitmap));
+    }
+    Ok(images)
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let (image, mut files) = load(options)?;
+    match options.action {
+        Action::List => {
+            for sheet in sheets(&image, options) {
+                println!(
+                    "{:<18} ${:04X}  {} x {}x{}",
+                    sheet.name,
+                    sheet.address,
+                    sheet.count,
+                    sheet.width,
+                    sheet.rows * 8
+                );
+            }
+        }
+        Action::Extract => {
+            std::fs::create_dir_all(&options.dir)
+                .map_err(|err| format!("{}: {}", options.dir.display(), err))?;
+            for sheet in sheets(&image, options) {
+                let bitmap = sheet.extract(&image);
+                let data = match options.format {
+                    ImageFormat::Png => png_file::write(&bitmap),
+                    ImageFormat::Pbm => bitmap.to_pbm(),
+                };
+                let path =
+                    options
+                        .dir
+                        .join(format!("{}.{}", sheet.name, options.format.extension()));
+                std::fs::write(&path, data)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?;
+            }
+        }
+        Action::Insert => {
+            let images = images(&image, options)?;
+            if images.is_empty() {
+                return Err(format!("no images in {}", options.dir.display()).into());
+            }
+            for (sheet, bitmap) in images {
+                let bytes = sheet.encode(&bitmap)?;
+                patch(&mut files, sheet.address, &bytes)
+                    .map_err(|err| format!("{}: {}", sheet.name, err))?;
+                println!("{:<18} ${:04X}", sheet.name, sheet.address);
+            }
+            let output = options.output.as_ref().expect("insert has an output");
+            std::fs::create_dir_all(output)
+                .map_err(|err| format!("{}: {}", output.display(), err))?;
+            for file in &files {
+                let name = file.path.file_name().unwrap_or_default();
+                let path = output.join(name);
+                std::fs::write(&path, &file.data)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = parse(std::env::args().skip(1))
+        .map_err(|err| err.into())
+        .and_then(|options| run(&options));
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn sprites_parse_and_patch() {
+        let options = parse(args("insert a.h@0 a.g@0x800 -d art -f pbm -o out --scan")).unwrap();
+        assert_eq!(Action::Insert, options.action);
+        assert_eq!((PathBuf::from("a.g"), Some(0x800)), options.files[1]);
+        assert_eq!(ImageFormat::Pbm, options.format);
+        assert_eq!(Some(PathBuf::from("out")), options.output);
+        assert!(options.scan);
+        assert_eq!(PathBuf::from("."), parse(args("list a.h")).unwrap().dir);
+        assert!(parse(args("insert a.h")).is_err());
+        assert!(parse(args("extract a.h -o out")).is_err());
+        assert!(parse(args("extract")).is_err());
+        assert!(parse(args("draw a.h")).is_err());
+        assert!(parse(args("list a.h -f gif")).is_err());
+
+        // the saucer straddles two files and is patched in both
+        let mut files = vec![
+            RomFile {
+                path: "a".into(),
+                address: 0x1000,
+                data: vec![0; 0xD70],
+            },
+            RomFile {
+                path: "b".into(),
+                address: 0x1D70,
+                data: vec![0; 0x290],
+            },
+        ];
+        let mut image = MemoryImage::default();
+        for file in &files {
+            image.load(file.address, &file.data).unwrap();
+        }
+        let saucer = SpriteSheet::space_invaders()
+            .into_iter()
+            .find(|sheet| sheet.name == "saucer")
+            .unwrap();
+        assert!(is_loaded(&image, &saucer));
+        let mut bitmap = saucer.extract(&image);
+        bitmap.set(0, 7, true);
+        bitmap.set(23, 0, true);
+        let bytes = saucer.encode(&bitmap).unwrap();
+        patch(&mut files, saucer.address, &bytes).unwrap();
+        assert_eq!(0x01, files[0].data[0xD64]);
+        assert_eq!(0x80, files[1].data[0x1D7B - 0x1D70]);
+        assert!(patch(&mut files, 0xFFFF, &[1, 2]).is_err());
+
+        let found = found_sheet("sprite_1A00", &Bitmap::new(5, 16)).unwrap();
+        assert_eq!((0x1A00, 5, 2), (found.address, found.width, found.rows));
+        assert_eq!(None, found_sheet("sprite_1A00", &Bitmap::new(5, 12)));
+        assert_eq!(None, found_sheet("aliens", &Bitmap::new(5, 8)));
+    }
+}
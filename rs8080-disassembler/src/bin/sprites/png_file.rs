@@ -0,0 +1,64 @@
+use rs8080_disassembler::{Bitmap, BitmapError};
+
+fn error(message: impl Into<String>) -> BitmapError {
+    BitmapError {
+        message: message.into(),
+    }
+}
+
+/// 1-bit grayscale PNG, lit pixels are white as on screen
+pub(crate) fn write(bitmap: &Bitmap) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut encoder = png::Encoder::new(&mut data, bitmap.width as u32, bitmap.height as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::One);
+    let mut writer = encoder
+        .write_header()
+        .expect("writing to memory does not fail");
+    writer
+        .write_image_data(&bitmap.packed_rows())
+        .expect("rows match the size of the header");
+    drop(writer);
+    data
+}
+
+/// Reads a PNG of any color type, light opaque pixels are lit
+pub(crate) fn read(data: &[u8]) -> Result<Bitmap, BitmapError> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|err| error(err.to_string()))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|err| error(err.to_string()))?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let channels = info.color_type.samples();
+    let mut bitmap = Bitmap::new(width, height);
+    for y in 0..height {
+        let row = &buffer[y * info.line_size..];
+        for x in 0..width {
+            let pixel = &row[x * channels..(x + 1) * channels];
+            let (light, alpha) = match *pixel {
+                [gray] => (gray as u32, 255),
+                [gray, alpha] => (gray as u32, alpha),
+                [r, g, b] => ((r as u32 + g as u32 + b as u32) / 3, 255),
+                [r, g, b, alpha] => ((r as u32 + g as u32 + b as u32) / 3, alpha),
+                _ => return Err(error("unsupported PNG color type")),
+            };
+            bitmap.set(x, y, light >= 128 && alpha >= 128);
+        }
+    }
+    Ok(bitmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn png_roundtrip() {
+        let tall = Bitmap::from_columns(&[0x01, 0x80, 0xFF, 0x00, 0x0F, 0xF0], 2);
+        assert_eq!(tall, read(&write(&tall)).unwrap());
+        assert!(read(b"P1\n1 1\n1").is_err());
+    }
+}
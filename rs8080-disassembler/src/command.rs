@@ -1,5 +1,7 @@
-use crate::{disassemble, Argument, Cmd, MyToString};
-use std::fmt::{self, Formatter};
+use crate::{try_disassemble, Argument, Cmd, Style, SymbolTable, Syntax};
+use alloc::string::String;
+use alloc::{vec, vec::Vec};
+use core::fmt::{self, Formatter};
 
 pub struct Command {
     pub cmd: Cmd,
@@ -15,13 +17,47 @@ pub struct Iter<'a> {
 impl<'a> Iterator for Iter<'a> {
     type Item = Command;
 
+    /// Ends at the end of bytes or at an instruction cut off by it
     fn next(&mut self) -> Option<Self::Item> {
-        if self.bytes.is_empty() {
+        let instruction = try_disassemble(self.bytes).ok()?;
+        self.bytes = &self.bytes[instruction.bytes.len()..];
+        Some(instruction.into())
+    }
+}
+
+/// Instructions of bytes loaded at a base address together with their addresses
+pub struct AddressIter<'a> {
+    bytes: &'a [u8],
+    address: u32,
+    wrap: bool,
+}
+
+impl AddressIter<'_> {
+    /// Continues from 0 after 0xFFFF instead of ending there
+    pub fn wrapping(mut self) -> Self {
+        self.wrap = true;
+        self
+    }
+}
+
+impl<'a> Iterator for AddressIter<'a> {
+    type Item = (u16, Command);
+
+    /// Ends like [Iter](struct.Iter.html) or, without wrapping,
+    /// at an instruction that does not end by 0xFFFF
+    fn next(&mut self) -> Option<Self::Item> {
+        let instruction = try_disassemble(self.bytes).ok()?;
+        let next = self.address + instruction.bytes.len() as u32;
+        if next > 0x1_0000 && !self.wrap {
             return None;
         }
-        let cmd = disassemble(self.bytes);
-        self.bytes = self.bytes.split_at(cmd.size as usize).1;
-        Some(cmd)
+        let address = self.address as u16;
+        self.bytes = &self.bytes[instruction.bytes.len()..];
+        self.address = match self.wrap {
+            true => next & 0xFFFF,
+            false => next,
+        };
+        Some((address, instruction.into()))
     }
 }
 
@@ -35,12 +71,28 @@ impl Command {
         }
     }
 
-    pub fn iterator(bytes: &[u8]) -> Iter {
+    pub fn iterator(bytes: &[u8]) -> Iter<'_> {
         Iter { bytes }
     }
 
+    /// Like [iterator](#method.iterator) for `bytes` loaded at `base`, yields addresses
+    pub fn iterator_at(bytes: &[u8], base: u16) -> AddressIter<'_> {
+        AddressIter {
+            bytes,
+            address: base as u32,
+            wrap: false,
+        }
+    }
+
+    /// Opcode and operand bytes, empty for commands built without them
     pub fn get_bytes(&self) -> &[u8] {
-        self.bytes.as_ref().unwrap()
+        self.bytes.as_deref().unwrap_or_default()
+    }
+
+    /// Mnemonic and arguments in the default syntax,
+    /// addresses named in `symbols` are shown by name
+    pub fn text(&self, symbols: &SymbolTable) -> String {
+        Syntax::default().instruction(self, symbols)
     }
 }
 
@@ -68,19 +120,41 @@ impl From<(Cmd, u8)> for Command {
     }
 }
 
+/// Written in the default [Style](struct.Style.html)
 impl fmt::Display for Command {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        let bytes_str = format!("{:02X?}", self.bytes.as_ref().unwrap());
-        if !self.args.is_empty() {
-            write!(
-                f,
-                "{:>12} {:4} {:>}",
-                bytes_str,
-                self.cmd.as_ref(),
-                self.args.to_string()
-            )
-        } else {
-            write!(f, "{:>12} {:4}", bytes_str, self.cmd.as_ref())
-        }
+        f.pad(&Style::default().instruction(self, &SymbolTable::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_iterator_at() {
+        // NOP, JMP $0000, MVI A cut off
+        let bytes = [0x00, 0xC3, 0x00, 0x00, 0x3E];
+        let addresses = |iter: AddressIter| iter.map(|(a, cmd)| (a, cmd.cmd)).collect::<Vec<_>>();
+        assert_eq!(
+            vec![(0x100, Cmd::NOP), (0x101, Cmd::JMP)],
+            addresses(Command::iterator_at(&bytes, 0x100))
+        );
+        assert_eq!(
+            vec![(0xFFFE, Cmd::NOP)],
+            addresses(Command::iterator_at(&bytes, 0xFFFE))
+        );
+        assert_eq!(
+            vec![(0xFFFF, Cmd::NOP)],
+            addresses(Command::iterator_at(&bytes, 0xFFFF))
+        );
+        assert_eq!(
+            vec![(0xFFFE, Cmd::NOP), (0xFFFF, Cmd::JMP)],
+            addresses(Command::iterator_at(&bytes, 0xFFFE).wrapping())
+        );
+        assert_eq!(
+            vec![(0xFFFF, Cmd::NOP), (0, Cmd::JMP)],
+            addresses(Command::iterator_at(&bytes, 0xFFFF).wrapping())
+        );
     }
 }
@@ -0,0 +1,154 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter, Write};
+
+const HEADER: &str = "rs8080-coverage 1";
+const PCHL: u8 = 0xE9;
+
+/// Error in a coverage file, `line` is 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for CoverageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CoverageError {}
+
+/// Addresses of executed opcodes and targets of executed `PCHL`s,
+/// recorded while running a program
+///
+/// Text format: the `rs8080-coverage 1` header, then `X <address>` for
+/// every executed opcode and `J <PCHL address> <target>` for indirect jumps,
+/// addresses are 4 hex digits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    executed: Vec<bool>,
+    indirect: BTreeSet<(u16, u16)>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage {
+            executed: vec![false; 0x1_0000],
+            indirect: BTreeSet::new(),
+        }
+    }
+}
+
+impl Coverage {
+    /// Records that `opcode` at `pc` was executed and the next one is at `next_pc`
+    pub fn record(&mut self, pc: u16, opcode: u8, next_pc: u16) {
+        self.executed[pc as usize] = true;
+        if opcode == PCHL {
+            self.indirect.insert((pc, next_pc));
+        }
+    }
+
+    /// Adds everything recorded in `other`
+    pub fn merge(&mut self, other: &Coverage) {
+        for (executed, other) in self.executed.iter_mut().zip(&other.executed) {
+            *executed |= *other;
+        }
+        self.indirect.extend(&other.indirect);
+    }
+
+    pub fn is_executed(&self, address: u16) -> bool {
+        self.executed[address as usize]
+    }
+
+    /// Executed opcode addresses in ascending order
+    pub fn executed(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..=0xFFFF).filter(|a| self.executed[*a as usize])
+    }
+
+    /// `(PCHL address, target)` pairs
+    pub fn indirect(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.indirect.iter().copied()
+    }
+
+    pub fn parse(text: &str) -> Result<Coverage, CoverageError> {
+        let mut coverage = Coverage::default();
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, l)| l.trim()) != Some(HEADER) {
+            return Err(CoverageError {
+                line: 1,
+                message: format!("expected '{}' header", HEADER),
+            });
+        }
+        for (i, line) in lines {
+            let error = |message: &str| CoverageError {
+                line: i + 1,
+                message: format!("{} '{}'", message, line.trim()),
+            };
+            let address = |hex: &str| {
+                // exactly 4 digits, `from_str_radix` would also take "+1A5" or "1A5"
+                if hex.len() != 4 || !hex.bytes().all(|d| d.is_ascii_hexdigit()) {
+                    return Err(error("bad address in"));
+                }
+                Ok(u16::from_str_radix(hex, 16).expect("4 hex digits fit in u16"))
+            };
+            match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => {}
+                ["X", pc] => coverage.executed[address(pc)? as usize] = true,
+                ["J", pc, target] => {
+                    coverage.indirect.insert((address(pc)?, address(target)?));
+                }
+                _ => return Err(error("unknown record")),
+            }
+        }
+        Ok(coverage)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for pc in self.executed() {
+            let _ = writeln!(text, "X {:04X}", pc);
+        }
+        for (pc, target) in self.indirect() {
+            let _ = writeln!(text, "J {:04X} {:04X}", pc, target);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coverage_text_roundtrip() {
+        let mut coverage = Coverage::default();
+        coverage.record(0x100, 0x00, 0x101);
+        coverage.record(0x0A3C, PCHL, 0x0A50);
+        coverage.record(0x0A3C, PCHL, 0x0A60);
+        let text = coverage.to_text();
+        assert_eq!(
+            "rs8080-coverage 1\nX 0100\nX 0A3C\nJ 0A3C 0A50\nJ 0A3C 0A60\n",
+            text
+        );
+        assert_eq!(coverage, Coverage::parse(&text).unwrap());
+
+        let mut other = Coverage::default();
+        other.record(0x200, 0x00, 0x201);
+        other.merge(&coverage);
+        assert_eq!(
+            vec![0x100, 0x200, 0x0A3C],
+            other.executed().collect::<Vec<_>>()
+        );
+        assert_eq!(2, other.indirect().count());
+
+        assert_eq!(1, Coverage::parse("X 0000").unwrap_err().line);
+        let err = Coverage::parse("rs8080-coverage 1\nX 0000\nX 10000").unwrap_err();
+        assert_eq!("line 3: bad address in 'X 10000'", err.to_string());
+        assert!(Coverage::parse("rs8080-coverage 1\nY 0000").is_err());
+        for address in ["+1A5", "1A5", "01A5C", "1A5G"] {
+            let text = format!("rs8080-coverage 1\nJ 0A3C {}", address);
+            assert!(Coverage::parse(&text).is_err(), "{}", address);
+        }
+    }
+}
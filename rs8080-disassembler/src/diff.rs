@@ -0,0 +1,477 @@
+use crate::{Analysis, Argument, Cmd, Command, Graph};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Instructions of a function of a program in address order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Routine {
+    pub entry: u16,
+    pub addresses: Vec<u16>,
+}
+
+/// Functions of `graph` with their instructions found by `analysis`
+pub fn routines(analysis: &Analysis, graph: &Graph) -> Vec<Routine> {
+    graph
+        .functions()
+        .iter()
+        .map(|function| {
+            let mut addresses = BTreeSet::new();
+            for block in function.blocks.iter().filter_map(|b| graph.block(*b)) {
+                addresses.extend(
+                    analysis
+                        .instructions_from(block.start)
+                        .map(|(address, _)| address)
+                        .take_while(|a| (*a as u32) < block.end),
+                );
+            }
+            Routine {
+                entry: function.entry,
+                addresses: addresses.into_iter().collect(),
+            }
+        })
+        .collect()
+}
+
+/// Operand of an instruction with code addresses made independent of where a routine is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Operand {
+    Value(Argument),
+    /// Branch target inside the routine, relative to its entry
+    Internal(i32),
+    /// Branch target outside the routine
+    External,
+}
+
+/// Instruction compared without the addresses of branch targets outside its routine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    cmd: Cmd,
+    args: [Option<Operand>; 2],
+}
+
+fn key(cmd: &Command, routine: &Routine) -> Key {
+    let branch = cmd.info().branch.is_some();
+    let mut args = [None; 2];
+    for (slot, arg) in args.iter_mut().zip(&cmd.args) {
+        *slot = Some(match *arg {
+            Argument::Addr(lo, hi) if branch => {
+                let target = u16::from_le_bytes([lo, hi]);
+                match routine.addresses.binary_search(&target) {
+                    Ok(_) => Operand::Internal(target as i32 - routine.entry as i32),
+                    Err(_) => Operand::External,
+                }
+            }
+            arg => Operand::Value(arg),
+        });
+    }
+    Key { cmd: cmd.cmd, args }
+}
+
+/// Branch target outside the routine
+fn external(cmd: &Command, routine: &Routine) -> Option<u16> {
+    let target = cmd.info().target?;
+    routine
+        .addresses
+        .binary_search(&target)
+        .is_err()
+        .then_some(target)
+}
+
+/// Difference of an instruction of the old and the new program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// Same instruction, possibly moved
+    Equal {
+        old: u16,
+        new: u16,
+    },
+    /// Instruction at `old` was replaced by the one at `new`
+    Changed {
+        old: u16,
+        new: u16,
+    },
+    Inserted {
+        new: u16,
+    },
+    Deleted {
+        old: u16,
+    },
+}
+
+/// How a routine differs between the programs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoutineStatus {
+    Same,
+    /// Identical at another address
+    Moved,
+    Changed,
+    /// Only in the new program
+    Inserted,
+    /// Only in the old program
+    Deleted,
+}
+
+impl RoutineStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RoutineStatus::Same => "same",
+            RoutineStatus::Moved => "moved",
+            RoutineStatus::Changed => "changed",
+            RoutineStatus::Inserted => "inserted",
+            RoutineStatus::Deleted => "deleted",
+        }
+    }
+}
+
+/// Routine of the old program matched to one of the new program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutineDiff {
+    /// Entry in the old program
+    pub old: Option<u16>,
+    /// Entry in the new program
+    pub new: Option<u16>,
+    pub status: RoutineStatus,
+    pub changes: Vec<Change>,
+}
+
+/// One side of a comparison
+pub struct Program<'a> {
+    pub analysis: &'a Analysis,
+    pub routines: Vec<Routine>,
+}
+
+impl<'a> Program<'a> {
+    pub fn new(analysis: &'a Analysis, graph: &Graph) -> Self {
+        Program {
+            analysis,
+            routines: routines(analysis, graph),
+        }
+    }
+
+    fn keys(&self, routine: &Routine) -> Vec<Key> {
+        routine
+            .addresses
+            .iter()
+            .filter_map(|a| self.analysis.instruction(*a))
+            .map(|cmd| key(cmd, routine))
+            .collect()
+    }
+}
+
+/// Operations aligning `old` with `new`, indices of equal items or `None` for the other side
+fn align<T: PartialEq>(old: &[T], new: &[T]) -> Vec<(Option<usize>, Option<usize>)> {
+    // longest common subsequence lengths of suffixes
+    let mut lengths = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lengths[i][j] = match old[i] == new[j] {
+                true => lengths[i + 1][j + 1] + 1,
+                false => lengths[i + 1][j].max(lengths[i][j + 1]),
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut ops = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if j == new.len() || (i < old.len() && lengths[i + 1][j] >= lengths[i][j + 1]) {
+            ops.push((Some(i), None));
+            i += 1;
+        } else {
+            ops.push((None, Some(j)));
+            j += 1;
+        }
+    }
+    ops
+}
+
+/// Share of instructions in common, from 0 to 1
+fn similarity(old: &[Key], new: &[Key]) -> f64 {
+    let common = align(old, new)
+        .iter()
+        .filter(|(i, j)| i.is_some() && j.is_some())
+        .count();
+    2.0 * common as f64 / (old.len() + new.len()).max(1) as f64
+}
+
+/// Least similarity of routines that are matched although they differ
+const MIN_SIMILARITY: f64 = 0.5;
+
+/// Matches routines of `old` and `new`, first identical ones wherever they are,
+/// then the most similar ones, and compares matched routines instruction by instruction.
+/// Branches out of a routine are equal when they lead to matched routines
+pub fn diff(old: &Program, new: &Program) -> Vec<RoutineDiff> {
+    let old_keys: Vec<Vec<Key>> = old.routines.iter().map(|r| old.keys(r)).collect();
+    let new_keys: Vec<Vec<Key>> = new.routines.iter().map(|r| new.keys(r)).collect();
+
+    // identical routines, pairs of duplicates in address order
+    let mut by_keys: HashMap<&[Key], Vec<usize>> = HashMap::new();
+    for (j, keys) in new_keys.iter().enumerate().rev() {
+        by_keys.entry(keys.as_slice()).or_default().push(j);
+    }
+    let mut pairs: BTreeMap<usize, usize> = BTreeMap::new();
+    for (i, keys) in old_keys.iter().enumerate() {
+        if let Some(j) = by_keys.get_mut(keys.as_slice()).and_then(Vec::pop) {
+            pairs.insert(i, j);
+        }
+    }
+
+    // the most similar of the rest
+    let matched: BTreeSet<usize> = pairs.values().copied().collect();
+    let mut candidates = Vec::new();
+    for (i, keys) in old_keys
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !pairs.contains_key(i))
+    {
+        for (j, other) in new_keys
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| !matched.contains(j))
+        {
+            let (short, long) = (keys.len().min(other.len()), keys.len().max(other.len()));
+            if short * 2 < long {
+                continue;
+            }
+            let score = similarity(keys, other);
+            if score >= MIN_SIMILARITY {
+                candidates.push((score, i, j));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+    let mut taken: BTreeSet<usize> = matched;
+    for (_, i, j) in candidates {
+        if !pairs.contains_key(&i) && taken.insert(j) {
+            pairs.insert(i, j);
+        }
+    }
+
+    let entries: HashMap<u16, u16> = pairs
+        .iter()
+        .map(|(i, j)| (old.routines[*i].entry, new.routines[*j].entry))
+        .collect();
+    let mut diffs: Vec<RoutineDiff> = pairs
+        .iter()
+        .map(|(i, j)| {
+            let (old_routine, new_routine) = (&old.routines[*i], &new.routines[*j]);
+            let target = |program: &Program, routine: &Routine, index: usize| {
+                let cmd = program.analysis.instruction(routine.addresses[index])?;
+                external(cmd, routine)
+            };
+            let changes = changes(
+                align(&old_keys[*i], &new_keys[*j]),
+                |i, j| {
+                    let old_target = target(old, old_routine, i);
+                    let new_target = target(new, new_routine, j);
+                    old_target.map(|t| entries.get(&t).copied().unwrap_or(t)) == new_target
+                },
+                &old_routine.addresses,
+                &new_routine.addresses,
+            );
+            let equal = changes.iter().all(|c| matches!(c, Change::Equal { .. }));
+            let status = match (equal, old_routine.entry == new_routine.entry) {
+                (true, true) => RoutineStatus::Same,
+                (true, false) => RoutineStatus::Moved,
+                (false, _) => RoutineStatus::Changed,
+            };
+            RoutineDiff {
+                old: Some(old_routine.entry),
+                new: Some(new_routine.entry),
+                status,
+                changes,
+            }
+        })
+        .collect();
+    let new_matched: BTreeSet<usize> = pairs.values().copied().collect();
+    for (i, routine) in old.routines.iter().enumerate() {
+        if !pairs.contains_key(&i) {
+            diffs.push(RoutineDiff {
+                old: Some(routine.entry),
+                new: None,
+                status: RoutineStatus::Deleted,
+                changes: routine
+                    .addresses
+                    .iter()
+                    .map(|old| Change::Deleted { old: *old })
+                    .collect(),
+            });
+        }
+    }
+    for (j, routine) in new.routines.iter().enumerate() {
+        if !new_matched.contains(&j) {
+            diffs.push(RoutineDiff {
+                old: None,
+                new: Some(routine.entry),
+                status: RoutineStatus::Inserted,
+                changes: routine
+                    .addresses
+                    .iter()
+                    .map(|new| Change::Inserted { new: *new })
+                    .collect(),
+            });
+        }
+    }
+    diffs.sort_by_key(|d| (d.old.unwrap_or(u16::MAX), d.new));
+    diffs
+}
+
+/// Changes from aligned instructions, runs of deleted and inserted ones
+/// become changed pairs, `same` compares targets of aligned ones
+fn changes(
+    ops: Vec<(Option<usize>, Option<usize>)>,
+    same: impl Fn(usize, usize) -> bool,
+    old: &[u16],
+    new: &[u16],
+) -> Vec<Change> {
+    let mut changes = Vec::new();
+    let mut deleted = Vec::new();
+    let mut inserted = Vec::new();
+    let flush =
+        |changes: &mut Vec<Change>, deleted: &mut Vec<usize>, inserted: &mut Vec<usize>| {
+            let paired = deleted.len().min(inserted.len());
+            for (i, j) in deleted.iter().zip(inserted.iter()) {
+                changes.push(Change::Changed {
+                    old: old[*i],
+                    new: new[*j],
+                });
+            }
+            changes.extend(
+                deleted[paired..]
+                    .iter()
+                    .map(|i| Change::Deleted { old: old[*i] }),
+            );
+            changes.extend(
+                inserted[paired..]
+                    .iter()
+                    .map(|j| Change::Inserted { new: new[*j] }),
+            );
+            deleted.clear();
+            inserted.clear();
+        };
+    for op in ops {
+        match op {
+            (Some(i), Some(j)) => {
+                flush(&mut changes, &mut deleted, &mut inserted);
+                changes.push(match same(i, j) {
+                    true => Change::Equal {
+                        old: old[i],
+                        new: new[j],
+                    },
+                    false => Change::Changed {
+                        old: old[i],
+                        new: new[j],
+                    },
+                });
+            }
+            (Some(i), None) => deleted.push(i),
+            (None, Some(j)) => inserted.push(j),
+            (None, None) => {}
+        }
+    }
+    flush(&mut changes, &mut deleted, &mut inserted);
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryImage;
+
+    fn program(bytes: &[u8]) -> (Analysis, Graph) {
+        let mut image = MemoryImage::default();
+        image.load(0, bytes).unwrap();
+        let analysis = Analysis::new(&image, &[0]);
+        let graph = Graph::new(&analysis, &[0], None);
+        (analysis, graph)
+    }
+
+    #[test]
+    fn diff_routines() {
+        #[rustfmt::skip]
+        let (old_analysis, old_graph) = program(&[
+            0xCD, 0x07, 0x00, // 0000 CALL $0007
+            0xCD, 0x0B, 0x00, // 0003 CALL $000B
+            0xC9,             // 0006 RET
+            0x3E, 0x01,       // 0007 MVI A, $01
+            0xD3, 0x03,       // 0009 OUT 3
+            0x3E, 0x02,       // 000B MVI A, $02
+            0xC9,             // 000D RET
+        ]);
+        #[rustfmt::skip]
+        let (new_analysis, new_graph) = program(&[
+            0xCD, 0x0A, 0x00, // 0000 CALL $000A
+            0xCD, 0x07, 0x00, // 0003 CALL $0007
+            0xC9,             // 0006 RET
+            0x3E, 0x02,       // 0007 MVI A, $02, moved from 000B
+            0xC9,             // 0009 RET
+            0x3E, 0x05,       // 000A MVI A, $05, was $01
+            0xD3, 0x03,       // 000C OUT 3
+            0xAF,             // 000E XRA A, the old one fell through to MVI A, $02
+            0xC9,             // 000F RET
+        ]);
+        let old = Program::new(&old_analysis, &old_graph);
+        let new = Program::new(&new_analysis, &new_graph);
+        let diffs = diff(&old, &new);
+        let entries: Vec<_> = diffs.iter().map(|d| (d.old, d.new, d.status)).collect();
+        assert_eq!(
+            vec![
+                (Some(0), Some(0), RoutineStatus::Same),
+                (Some(7), Some(0xA), RoutineStatus::Changed),
+                (Some(0xB), Some(7), RoutineStatus::Moved),
+            ],
+            entries
+        );
+        assert_eq!(
+            vec![
+                Change::Changed { old: 7, new: 0xA },
+                Change::Equal { old: 9, new: 0xC },
+                Change::Changed { old: 0xB, new: 0xE },
+                Change::Equal { old: 0xD, new: 0xF },
+            ],
+            diffs[1].changes
+        );
+    }
+
+    #[test]
+    fn diff_inserted_and_deleted() {
+        #[rustfmt::skip]
+        let (old_analysis, old_graph) = program(&[
+            0x3E, 0x01,       // 0000 MVI A, $01
+            0xD3, 0x03,       // 0002 OUT 3
+            0xC9,             // 0004 RET
+        ]);
+        #[rustfmt::skip]
+        let (new_analysis, new_graph) = program(&[
+            0x3E, 0x01,       // 0000 MVI A, $01
+            0x07,             // 0002 RLC, inserted
+            0xD3, 0x03,       // 0003 OUT 3
+            0xC9,             // 0005 RET
+        ]);
+        let old = Program::new(&old_analysis, &old_graph);
+        let new = Program::new(&new_analysis, &new_graph);
+        let diffs = diff(&old, &new);
+        assert_eq!(RoutineStatus::Changed, diffs[0].status);
+        assert_eq!(
+            vec![
+                Change::Equal { old: 0, new: 0 },
+                Change::Inserted { new: 2 },
+                Change::Equal { old: 2, new: 3 },
+                Change::Equal { old: 4, new: 5 },
+            ],
+            diffs[0].changes
+        );
+        // the other way around the instruction is deleted
+        assert_eq!(
+            vec![
+                Change::Equal { old: 0, new: 0 },
+                Change::Deleted { old: 2 },
+                Change::Equal { old: 3, new: 2 },
+                Change::Equal { old: 5, new: 4 },
+            ],
+            diff(&new, &old)[0].changes
+        );
+    }
+}
@@ -0,0 +1,286 @@
+use crate::analysis::flow;
+use crate::{Analysis, Cmd, Command, Coverage};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Why the successors of a block are not known
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unresolved {
+    /// `PCHL` without recorded targets
+    Pchl,
+    /// `RET` after more `PUSH`es than `POP`s or after `XTHL` in the same block,
+    /// so it returns to a computed address rather than to the caller
+    RetTrick,
+}
+
+impl Unresolved {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Unresolved::Pchl => "pchl",
+            Unresolved::RetTrick => "ret-trick",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    /// To the next instruction
+    Fallthrough,
+    /// Jump, taken conditional jump or recorded `PCHL` target
+    Taken,
+    /// `CALL`, conditional call or `RST` to the called function
+    Call,
+    /// From a calling block to the instruction after the call,
+    /// where the callee returns to
+    Return,
+}
+
+impl EdgeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Taken => "taken",
+            EdgeKind::Call => "call",
+            EdgeKind::Return => "return",
+        }
+    }
+}
+
+/// Instructions that run one after another, only the first one is a branch target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+    /// Address after the last instruction
+    pub end: u32,
+    /// Address of the last instruction
+    pub last: u16,
+    pub unresolved: Option<Unresolved>,
+}
+
+/// Edge between starts of blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub from: u16,
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+/// Blocks reachable from `entry` without following calls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub entry: u16,
+    /// Starts of the blocks
+    pub blocks: Vec<u16>,
+    /// Entries of called functions
+    pub calls: BTreeSet<u16>,
+}
+
+/// Control-flow graph of the code found by [Analysis](struct.Analysis.html)
+/// and the call graph of its functions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Graph {
+    blocks: BTreeMap<u16, Block>,
+    edges: Vec<Edge>,
+    functions: Vec<Function>,
+}
+
+/// Instruction that ends a block
+fn ends_block(cmd: &Command) -> bool {
+    cmd.info().branch.is_some()
+}
+
+impl Graph {
+    /// Functions start at `entries` and at call targets, targets of `PCHL`s
+    /// recorded in `coverage` are followed
+    pub fn new(analysis: &Analysis, entries: &[u16], coverage: Option<&Coverage>) -> Graph {
+        let mut indirect: BTreeMap<u16, BTreeSet<u16>> = BTreeMap::new();
+        for (pc, target) in coverage.into_iter().flat_map(|c| c.indirect()) {
+            indirect.entry(pc).or_default().insert(target);
+        }
+        let mut leaders: BTreeSet<u16> = entries.iter().copied().collect();
+        for (_, cmd) in analysis.instructions() {
+            leaders.extend(flow(cmd).target);
+        }
+        leaders.extend(indirect.values().flatten());
+
+        // split instructions into blocks
+        let mut blocks: BTreeMap<u16, Block> = BTreeMap::new();
+        let mut current: Option<Block> = None;
+        let mut pushes = 0i32;
+        for (pc, cmd) in analysis.instructions() {
+            let continues =
+                matches!(&current, Some(block) if block.end == pc as u32) && !leaders.contains(&pc);
+            if !continues {
+                blocks.extend(current.take().map(|b| (b.start, b)));
+                pushes = 0;
+            }
+            let block = current.get_or_insert(Block {
+                start: pc,
+                end: pc as u32,
+                last: pc,
+                unresolved: None,
+            });
+            block.end = pc as u32 + cmd.size as u32;
+            block.last = pc;
+            match cmd.cmd {
+                Cmd::PUSH => pushes += 1,
+                Cmd::POP => pushes -= 1,
+                Cmd::XTHL => pushes = i32::MAX / 2,
+                _ => {}
+            }
+            if ends_block(cmd) {
+                block.unresolved = match cmd.cmd {
+                    Cmd::PCHL if !indirect.contains_key(&pc) => Some(Unresolved::Pchl),
+                    Cmd::RET if pushes > 0 => Some(Unresolved::RetTrick),
+                    _ => None,
+                };
+                blocks.extend(current.take().map(|b| (b.start, b)));
+            }
+        }
+        blocks.extend(current.map(|b| (b.start, b)));
+
+        let mut edges = Vec::new();
+        for block in blocks.values() {
+            let Some(cmd) = analysis.instruction(block.last) else {
+                continue;
+            };
+            let flow = flow(cmd);
+            let next = (block.end <= 0xFFFF).then_some(block.end as u16);
+            let mut add = |to: Option<u16>, kind| {
+                if let Some(to) = to.filter(|to| blocks.contains_key(to)) {
+                    edges.push(Edge {
+                        from: block.start,
+                        to,
+                        kind,
+                    });
+                }
+            };
+            match (flow.target, flow.call) {
+                (Some(target), true) => {
+                    add(Some(target), EdgeKind::Call);
+                    add(next, EdgeKind::Return);
+                }
+                (target, false) => {
+                    add(target, EdgeKind::Taken);
+                    if flow.falls_through {
+                        add(next, EdgeKind::Fallthrough);
+                    }
+                }
+                (None, true) => {}
+            }
+            for target in indirect.get(&block.last).into_iter().flatten() {
+                add(Some(*target), EdgeKind::Taken);
+            }
+        }
+        edges.sort();
+        edges.dedup();
+
+        let mut roots: BTreeSet<u16> = entries.iter().copied().collect();
+        roots.extend(
+            edges
+                .iter()
+                .filter(|e| e.kind == EdgeKind::Call)
+                .map(|e| e.to),
+        );
+        let functions = roots
+            .into_iter()
+            .filter(|entry| blocks.contains_key(entry))
+            .map(|entry| Graph::function(entry, &edges))
+            .collect();
+        Graph {
+            blocks,
+            edges,
+            functions,
+        }
+    }
+
+    fn function(entry: u16, edges: &[Edge]) -> Function {
+        let mut seen = BTreeSet::from([entry]);
+        let mut calls = BTreeSet::new();
+        let mut pending = vec![entry];
+        while let Some(start) = pending.pop() {
+            for edge in edges.iter().filter(|e| e.from == start) {
+                if edge.kind == EdgeKind::Call {
+                    calls.insert(edge.to);
+                } else if seen.insert(edge.to) {
+                    pending.push(edge.to);
+                }
+            }
+        }
+        Function {
+            entry,
+            blocks: seen.into_iter().collect(),
+            calls,
+        }
+    }
+
+    /// Blocks in address order
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    /// Block starting at `address`
+    pub fn block(&self, address: u16) -> Option<&Block> {
+        self.blocks.get(&address)
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Functions in the order of their entries
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryImage;
+
+    #[test]
+    fn graph_blocks_and_calls() {
+        let mut image = MemoryImage::default();
+        #[rustfmt::skip]
+        image.load(0, &[
+            0xCD, 0x08, 0x00, // 0000 CALL $0008
+            0xC2, 0x00, 0x00, // 0003 JNZ $0000
+            0x76,             // 0006 HLT
+            0xC9,             // 0007 RET
+            0xE5,             // 0008 PUSH H
+            0xC9,             // 0009 RET, returns to HL
+            0xE9,             // 000A PCHL
+        ]).unwrap();
+        let analysis = Analysis::new(&image, &[0, 0xA]);
+        let graph = Graph::new(&analysis, &[0, 0xA], None);
+        let starts: Vec<u16> = graph.blocks().map(|b| b.start).collect();
+        assert_eq!(vec![0, 3, 6, 8, 0xA], starts);
+        assert_eq!(
+            Some(Unresolved::RetTrick),
+            graph.block(8).unwrap().unresolved
+        );
+        assert_eq!(Some(Unresolved::Pchl), graph.block(0xA).unwrap().unresolved);
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(
+            &[
+                edge(0, 3, EdgeKind::Return),
+                edge(0, 8, EdgeKind::Call),
+                edge(3, 0, EdgeKind::Taken),
+                edge(3, 6, EdgeKind::Fallthrough),
+            ],
+            &graph.edges()[..4]
+        );
+        let entries: Vec<u16> = graph.functions().iter().map(|f| f.entry).collect();
+        assert_eq!(vec![0, 8, 0xA], entries);
+        assert_eq!(vec![0, 3, 6], graph.functions()[0].blocks);
+        assert_eq!(BTreeSet::from([8]), graph.functions()[0].calls);
+
+        // a recorded target resolves PCHL
+        let mut coverage = Coverage::default();
+        coverage.record(0xA, 0xE9, 0x7);
+        let analysis = Analysis::with_coverage(&image, &[0, 0xA], &coverage);
+        let graph = Graph::new(&analysis, &[0, 0xA], Some(&coverage));
+        assert_eq!(None, graph.block(0xA).unwrap().unresolved);
+        assert!(graph.edges().contains(&edge(0xA, 7, EdgeKind::Taken)));
+    }
+}
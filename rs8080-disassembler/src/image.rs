@@ -0,0 +1,151 @@
+use crate::{try_disassemble, Command};
+use alloc::{vec, vec::Vec};
+use core::fmt::{self, Display, Formatter};
+use core::ops::Range;
+
+const MEMORY_SIZE: usize = 0x1_0000;
+
+/// Error returned when data does not fit in the address space or is loaded twice
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageError {
+    /// `address` is the first one outside of memory
+    Overflow {
+        address: u32,
+    },
+    Overlap {
+        address: u16,
+    },
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Overflow { address } => {
+                write!(f, "address ${:04X} is out of memory", address)
+            }
+            ImageError::Overlap { address } => write!(f, "${:04X} is loaded twice", address),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ImageError {}
+
+/// 64K address space which remembers what was loaded
+#[derive(Debug, Clone)]
+pub struct MemoryImage {
+    bytes: Vec<u8>,
+    /// End of the loaded run an address belongs to,
+    /// the address itself if it is not loaded
+    run_end: Vec<u32>,
+}
+
+impl Default for MemoryImage {
+    fn default() -> Self {
+        MemoryImage {
+            bytes: vec![0; MEMORY_SIZE],
+            run_end: (0..MEMORY_SIZE as u32).collect(),
+        }
+    }
+}
+
+impl MemoryImage {
+    /// Places `data` at `address`
+    pub fn load(&mut self, address: u16, data: &[u8]) -> Result<(), ImageError> {
+        let start = address as usize;
+        let end = start + data.len();
+        if end > MEMORY_SIZE {
+            return Err(ImageError::Overflow {
+                address: MEMORY_SIZE as u32,
+            });
+        }
+        if let Some(i) = (start..end).position(|a| self.is_loaded(a as u16)) {
+            return Err(ImageError::Overlap {
+                address: (start + i) as u16,
+            });
+        }
+        self.bytes[start..end].copy_from_slice(data);
+        // the run now ends where the one right after `end` ends,
+        // the run right before `start` is extended too
+        let run_end = if end < MEMORY_SIZE && self.is_loaded(end as u16) {
+            self.run_end[end]
+        } else {
+            end as u32
+        };
+        for address in (0..end).rev() {
+            if address < start && !self.is_loaded(address as u16) {
+                break;
+            }
+            self.run_end[address] = run_end;
+        }
+        Ok(())
+    }
+
+    pub fn is_loaded(&self, address: u16) -> bool {
+        self.run_end[address as usize] > address as u32
+    }
+
+    /// Loaded bytes from `address` up to the first one that was not loaded
+    pub fn bytes_from(&self, address: u16) -> &[u8] {
+        &self.bytes[address as usize..self.run_end[address as usize] as usize]
+    }
+
+    /// Instruction at `address`, `None` if it is cut off by the end of loaded data
+    pub fn decode(&self, address: u16) -> Option<Command> {
+        try_disassemble(self.bytes_from(address))
+            .ok()
+            .map(Command::from)
+    }
+
+    /// Ranges of loaded addresses in ascending order
+    pub fn regions(&self) -> Vec<Range<u32>> {
+        let mut regions = Vec::new();
+        let mut address = 0;
+        while address < MEMORY_SIZE as u32 {
+            let end = self.run_end[address as usize];
+            if end > address {
+                regions.push(address..end);
+                address = end;
+            } else {
+                address += 1;
+            }
+        }
+        regions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn image_load() {
+        let mut image = MemoryImage::default();
+        image.load(0x10, &[1, 2]).unwrap();
+        image.load(0x12, &[3]).unwrap();
+        image.load(0x20, &[0xC3, 0x00]).unwrap();
+        image.load(0xFFFF, &[4]).unwrap();
+        assert_eq!(
+            vec![0x10..0x13, 0x20..0x22, 0xFFFF..0x1_0000],
+            image.regions()
+        );
+        assert_eq!(&[2, 3], image.bytes_from(0x11));
+        assert!(image.bytes_from(0x13).is_empty());
+        assert!(image.decode(0x13).is_none());
+        assert_eq!(3, image.decode(0x10).unwrap().size);
+        // JMP cut off by the end of data
+        assert!(image.decode(0x20).is_none());
+        assert_eq!(
+            Err(ImageError::Overlap { address: 0x10 }),
+            image.load(0x0F, &[0; 4])
+        );
+        assert_eq!(
+            Err(ImageError::Overflow { address: 0x1_0000 }),
+            image.load(0xFFFE, &[0; 3])
+        );
+        // joins the run after it
+        image.load(0x0E, &[0; 2]).unwrap();
+        assert_eq!(5, image.bytes_from(0x0E).len());
+        assert_eq!(0x0E..0x13, image.regions()[0]);
+    }
+}
@@ -0,0 +1,580 @@
+use crate::{Argument, Cmd, Command};
+
+/// Condition flags, bits are laid out as `PUSH PSW` stores them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Flags(pub u8);
+
+impl Flags {
+    pub const NONE: Flags = Flags(0);
+    pub const S: Flags = Flags(0b1000_0000);
+    pub const Z: Flags = Flags(0b0100_0000);
+    pub const AC: Flags = Flags(0b0001_0000);
+    pub const P: Flags = Flags(0b0000_0100);
+    pub const CY: Flags = Flags(0b0000_0001);
+    pub const ALL: Flags = Flags(0b1101_0101);
+    /// Flags set by `INR` and `DCR`, all but carry
+    pub const ZSPAC: Flags = Flags(0b1101_0100);
+
+    pub fn contains(&self, other: Flags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// 8-bit registers and the stack pointer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    SP,
+}
+
+const REGISTERS: [Register; 8] = [
+    Register::A,
+    Register::B,
+    Register::C,
+    Register::D,
+    Register::E,
+    Register::H,
+    Register::L,
+    Register::SP,
+];
+
+/// Set of registers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Registers(u8);
+
+impl Registers {
+    fn bit(register: Register) -> u8 {
+        1 << REGISTERS.iter().position(|r| *r == register).unwrap_or(0)
+    }
+
+    pub fn insert(&mut self, register: Register) {
+        self.0 |= Registers::bit(register);
+    }
+
+    pub fn contains(&self, register: Register) -> bool {
+        self.0 & Registers::bit(register) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Register> + '_ {
+        REGISTERS.iter().copied().filter(|r| self.contains(*r))
+    }
+}
+
+impl<const N: usize> From<[Register; N]> for Registers {
+    fn from(registers: [Register; N]) -> Self {
+        let mut set = Registers::default();
+        for register in registers {
+            set.insert(register);
+        }
+        set
+    }
+}
+
+impl Extend<Register> for Registers {
+    fn extend<T: IntoIterator<Item = Register>>(&mut self, iter: T) {
+        for register in iter {
+            self.insert(register);
+        }
+    }
+}
+
+/// Where an instruction accesses memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// Address in the instruction
+    Address(u16),
+    /// Address in a register pair
+    BC,
+    DE,
+    HL,
+    /// Top of the stack
+    Stack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub location: Location,
+    pub bytes: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Branch {
+    /// `JMP`, conditional jumps and `PCHL`
+    Jump,
+    /// `CALL` and conditional calls
+    Call,
+    /// `RET` and conditional returns
+    Return,
+    Rst,
+}
+
+/// Clock cycles, `taken` is spent when a conditional branch is taken
+/// and equals `not_taken` for other instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycles {
+    pub taken: u8,
+    pub not_taken: u8,
+}
+
+impl Cycles {
+    fn fixed(n: u8) -> Cycles {
+        Cycles {
+            taken: n,
+            not_taken: n,
+        }
+    }
+}
+
+/// What an instruction does besides its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Info {
+    pub cycles: Cycles,
+    pub flags_read: Flags,
+    pub flags_written: Flags,
+    pub reads: Registers,
+    pub writes: Registers,
+    pub memory_read: Option<Access>,
+    pub memory_written: Option<Access>,
+    /// Port of `IN` and `OUT`
+    pub port: Option<u8>,
+    pub branch: Option<Branch>,
+    /// Branch depends on a flag
+    pub conditional: bool,
+    /// Branch target known without running the code, `None` for `RET` and `PCHL`
+    pub target: Option<u16>,
+}
+
+impl Info {
+    /// Whether the next instruction can be executed after this one,
+    /// calls and `RST` count as returning
+    pub fn falls_through(&self) -> bool {
+        self.conditional || !matches!(self.branch, Some(Branch::Jump | Branch::Return))
+    }
+}
+
+fn pair(arg: &Argument) -> Registers {
+    use Register::*;
+    match arg {
+        Argument::B => [B, C].into(),
+        Argument::D => [D, E].into(),
+        Argument::H => [H, L].into(),
+        Argument::SP => [SP].into(),
+        Argument::PSW => [A].into(),
+        _ => Registers::default(),
+    }
+}
+
+fn register(arg: &Argument) -> Option<Register> {
+    Some(match arg {
+        Argument::A => Register::A,
+        Argument::B => Register::B,
+        Argument::C => Register::C,
+        Argument::D => Register::D,
+        Argument::E => Register::E,
+        Argument::H => Register::H,
+        Argument::L => Register::L,
+        Argument::SP => Register::SP,
+        _ => return None,
+    })
+}
+
+/// Flag tested by a conditional jump, call or return
+fn condition(cmd: Cmd) -> Flags {
+    match cmd {
+        Cmd::JNZ | Cmd::JZ | Cmd::CNZ | Cmd::CZ | Cmd::RNZ | Cmd::RZ => Flags::Z,
+        Cmd::JNC | Cmd::JC | Cmd::CNC | Cmd::CC | Cmd::RNC | Cmd::RC => Flags::CY,
+        Cmd::JPO | Cmd::JPE | Cmd::CPO | Cmd::CPE | Cmd::RPO | Cmd::RPE => Flags::P,
+        Cmd::JP | Cmd::JM | Cmd::CP | Cmd::CM | Cmd::RP | Cmd::RM => Flags::S,
+        _ => Flags::NONE,
+    }
+}
+
+impl Command {
+    /// Timing, flags, registers, memory and control flow of the instruction,
+    /// cycles are those of the Intel 8080
+    pub fn info(&self) -> Info {
+        use Register::*;
+        let mut info = Info {
+            cycles: Cycles::fixed(4),
+            flags_read: Flags::NONE,
+            flags_written: Flags::NONE,
+            reads: Registers::default(),
+            writes: Registers::default(),
+            memory_read: None,
+            memory_written: None,
+            port: None,
+            branch: None,
+            conditional: false,
+            target: None,
+        };
+        let address = self.args.iter().find_map(|arg| match *arg {
+            Argument::Addr(lo, hi) | Argument::D16(lo, hi) => Some(u16::from_le_bytes([lo, hi])),
+            _ => None,
+        });
+        let at = |location, bytes| Some(Access { location, bytes });
+        let hl: Registers = [H, L].into();
+        let stack: Registers = [SP].into();
+        // `M` operand reads HL and accesses memory
+        let memory = self.args.contains(&Argument::M);
+        match self.cmd {
+            Cmd::NOP | Cmd::NOPU | Cmd::RIM | Cmd::SIM | Cmd::EI | Cmd::DI => {}
+            Cmd::HLT => info.cycles = Cycles::fixed(7),
+            Cmd::MOV => {
+                info.cycles = Cycles::fixed(if memory { 7 } else { 5 });
+                let (dst, src) = (&self.args[0], &self.args[1]);
+                if *src == Argument::M {
+                    info.reads = hl;
+                    info.memory_read = at(Location::HL, 1);
+                }
+                if *dst == Argument::M {
+                    info.reads = hl;
+                    info.memory_written = at(Location::HL, 1);
+                }
+                info.reads.extend(register(src));
+                info.writes.extend(register(dst));
+            }
+            Cmd::MVI => {
+                info.cycles = Cycles::fixed(if memory { 10 } else { 7 });
+                match register(&self.args[0]) {
+                    Some(dst) => info.writes.insert(dst),
+                    None => {
+                        info.reads = hl;
+                        info.memory_written = at(Location::HL, 1);
+                    }
+                }
+            }
+            Cmd::LXI => {
+                info.cycles = Cycles::fixed(10);
+                info.writes = pair(&self.args[0]);
+            }
+            Cmd::LDA | Cmd::STA => {
+                info.cycles = Cycles::fixed(13);
+                let access = at(Location::Address(address.unwrap_or_default()), 1);
+                if self.cmd == Cmd::LDA {
+                    info.writes.insert(A);
+                    info.memory_read = access;
+                } else {
+                    info.reads.insert(A);
+                    info.memory_written = access;
+                }
+            }
+            Cmd::LHLD | Cmd::SHLD => {
+                info.cycles = Cycles::fixed(16);
+                let access = at(Location::Address(address.unwrap_or_default()), 2);
+                if self.cmd == Cmd::LHLD {
+                    info.writes = hl;
+                    info.memory_read = access;
+                } else {
+                    info.reads = hl;
+                    info.memory_written = access;
+                }
+            }
+            Cmd::LDAX | Cmd::STAX => {
+                info.cycles = Cycles::fixed(7);
+                info.reads = pair(&self.args[0]);
+                let location = match self.args[0] {
+                    Argument::B => Location::BC,
+                    _ => Location::DE,
+                };
+                if self.cmd == Cmd::LDAX {
+                    info.writes.insert(A);
+                    info.memory_read = at(location, 1);
+                } else {
+                    info.reads.insert(A);
+                    info.memory_written = at(location, 1);
+                }
+            }
+            Cmd::XCHG => {
+                info.reads = [D, E, H, L].into();
+                info.writes = info.reads;
+            }
+            Cmd::ADD
+            | Cmd::ADC
+            | Cmd::SUB
+            | Cmd::SBB
+            | Cmd::ANA
+            | Cmd::XRA
+            | Cmd::ORA
+            | Cmd::CMP
+            | Cmd::ADI
+            | Cmd::ACI
+            | Cmd::SUI
+            | Cmd::SBI
+            | Cmd::ANI
+            | Cmd::XRI
+            | Cmd::ORI
+            | Cmd::CPI => {
+                let immediate = matches!(self.args.first(), Some(Argument::D8(_)));
+                info.cycles = Cycles::fixed(if memory || immediate { 7 } else { 4 });
+                info.reads.insert(A);
+                if memory {
+                    info.reads.extend(hl.iter());
+                    info.memory_read = at(Location::HL, 1);
+                }
+                info.reads.extend(self.args.first().and_then(register));
+                if !matches!(self.cmd, Cmd::CMP | Cmd::CPI) {
+                    info.writes.insert(A);
+                }
+                info.flags_written = Flags::ALL;
+                if matches!(self.cmd, Cmd::ADC | Cmd::SBB | Cmd::ACI | Cmd::SBI) {
+                    info.flags_read = Flags::CY;
+                }
+            }
+            Cmd::INR | Cmd::DCR => {
+                info.cycles = Cycles::fixed(if memory { 10 } else { 5 });
+                match register(&self.args[0]) {
+                    Some(r) => {
+                        info.reads.insert(r);
+                        info.writes.insert(r);
+                    }
+                    None => {
+                        info.reads = hl;
+                        info.memory_read = at(Location::HL, 1);
+                        info.memory_written = at(Location::HL, 1);
+                    }
+                }
+                info.flags_written = Flags::ZSPAC;
+            }
+            Cmd::INX | Cmd::DCX => {
+                info.cycles = Cycles::fixed(5);
+                info.reads = pair(&self.args[0]);
+                info.writes = info.reads;
+            }
+            Cmd::DAD => {
+                info.cycles = Cycles::fixed(10);
+                info.reads = pair(&self.args[0]);
+                info.reads.extend(hl.iter());
+                info.writes = hl;
+                info.flags_written = Flags::CY;
+            }
+            Cmd::RLC | Cmd::RRC | Cmd::RAL | Cmd::RAR => {
+                info.reads.insert(A);
+                info.writes.insert(A);
+                info.flags_written = Flags::CY;
+                if matches!(self.cmd, Cmd::RAL | Cmd::RAR) {
+                    info.flags_read = Flags::CY;
+                }
+            }
+            Cmd::DAA => {
+                info.reads.insert(A);
+                info.writes.insert(A);
+                info.flags_read = Flags(Flags::CY.0 | Flags::AC.0);
+                info.flags_written = Flags::ALL;
+            }
+            Cmd::CMA => {
+                info.reads.insert(A);
+                info.writes.insert(A);
+            }
+            Cmd::STC => info.flags_written = Flags::CY,
+            Cmd::CMC => {
+                info.flags_read = Flags::CY;
+                info.flags_written = Flags::CY;
+            }
+            Cmd::PUSH | Cmd::POP => {
+                info.cycles = Cycles::fixed(if self.cmd == Cmd::PUSH { 11 } else { 10 });
+                let (registers, flags) = (pair(&self.args[0]), self.args[0] == Argument::PSW);
+                info.reads = stack;
+                info.writes = stack;
+                if self.cmd == Cmd::PUSH {
+                    info.reads.extend(registers.iter());
+                    info.memory_written = at(Location::Stack, 2);
+                    info.flags_read = if flags { Flags::ALL } else { Flags::NONE };
+                } else {
+                    info.writes.extend(registers.iter());
+                    info.memory_read = at(Location::Stack, 2);
+                    info.flags_written = if flags { Flags::ALL } else { Flags::NONE };
+                }
+            }
+            Cmd::XTHL => {
+                info.cycles = Cycles::fixed(18);
+                info.reads = [H, L, SP].into();
+                info.writes = hl;
+                info.memory_read = at(Location::Stack, 2);
+                info.memory_written = at(Location::Stack, 2);
+            }
+            Cmd::SPHL => {
+                info.cycles = Cycles::fixed(5);
+                info.reads = hl;
+                info.writes = stack;
+            }
+            Cmd::PCHL => {
+                info.cycles = Cycles::fixed(5);
+                info.reads = hl;
+                info.branch = Some(Branch::Jump);
+            }
+            Cmd::IN | Cmd::OUT => {
+                info.cycles = Cycles::fixed(10);
+                if let Some(Argument::D8(port)) = self.args.first() {
+                    info.port = Some(*port);
+                }
+                if self.cmd == Cmd::IN {
+                    info.writes.insert(A);
+                } else {
+                    info.reads.insert(A);
+                }
+            }
+            Cmd::JMP
+            | Cmd::JNZ
+            | Cmd::JZ
+            | Cmd::JNC
+            | Cmd::JC
+            | Cmd::JPO
+            | Cmd::JPE
+            | Cmd::JP
+            | Cmd::JM => {
+                info.cycles = Cycles::fixed(10);
+                info.branch = Some(Branch::Jump);
+                info.target = address;
+            }
+            Cmd::CALL
+            | Cmd::CNZ
+            | Cmd::CZ
+            | Cmd::CNC
+            | Cmd::CC
+            | Cmd::CPO
+            | Cmd::CPE
+            | Cmd::CP
+            | Cmd::CM => {
+                info.cycles = Cycles {
+                    taken: 17,
+                    not_taken: if self.cmd == Cmd::CALL { 17 } else { 11 },
+                };
+                info.branch = Some(Branch::Call);
+                info.target = address;
+                info.reads = stack;
+                info.writes = stack;
+                info.memory_written = at(Location::Stack, 2);
+            }
+            Cmd::RST => {
+                info.cycles = Cycles::fixed(11);
+                info.branch = Some(Branch::Rst);
+                if let Some(Argument::D8(n)) = self.args.first() {
+                    info.target = Some(*n as u16 * 8);
+                }
+                info.reads = stack;
+                info.writes = stack;
+                info.memory_written = at(Location::Stack, 2);
+            }
+            Cmd::RET
+            | Cmd::RNZ
+            | Cmd::RZ
+            | Cmd::RNC
+            | Cmd::RC
+            | Cmd::RPO
+            | Cmd::RPE
+            | Cmd::RP
+            | Cmd::RM => {
+                info.cycles = Cycles {
+                    taken: if self.cmd == Cmd::RET { 10 } else { 11 },
+                    not_taken: if self.cmd == Cmd::RET { 10 } else { 5 },
+                };
+                info.branch = Some(Branch::Return);
+                info.reads = stack;
+                info.writes = stack;
+                info.memory_read = at(Location::Stack, 2);
+            }
+        }
+        info.flags_read = Flags(info.flags_read.0 | condition(self.cmd).0);
+        info.conditional = !condition(self.cmd).is_empty();
+        info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassemble;
+
+    #[test]
+    fn info_of_instructions() {
+        let info = |bytes: &[u8]| disassemble(bytes).info();
+
+        // CNZ $1234
+        let cnz = info(&[0xC4, 0x34, 0x12]);
+        assert_eq!(
+            Cycles {
+                taken: 17,
+                not_taken: 11
+            },
+            cnz.cycles
+        );
+        assert_eq!((Some(Branch::Call), true), (cnz.branch, cnz.conditional));
+        assert_eq!(Some(0x1234), cnz.target);
+        assert_eq!(Flags::Z, cnz.flags_read);
+        assert!(cnz.falls_through());
+
+        // RZ, RET
+        assert_eq!(
+            Cycles {
+                taken: 11,
+                not_taken: 5
+            },
+            info(&[0xC8]).cycles
+        );
+        assert!(!info(&[0xC9]).falls_through());
+
+        // MOV M, A
+        let mov = info(&[0x77]);
+        assert_eq!(7, mov.cycles.taken);
+        assert_eq!(
+            vec![Register::A, Register::H, Register::L],
+            mov.reads.iter().collect::<Vec<_>>()
+        );
+        assert!(mov.writes.is_empty());
+        assert_eq!(
+            Some(Access {
+                location: Location::HL,
+                bytes: 1
+            }),
+            mov.memory_written
+        );
+
+        // ADC B
+        let adc = info(&[0x88]);
+        assert_eq!(Flags::CY, adc.flags_read);
+        assert_eq!(Flags::ALL, adc.flags_written);
+        assert!(adc.writes.contains(Register::A));
+
+        // INR A leaves carry
+        assert!(!info(&[0x3C]).flags_written.contains(Flags::CY));
+
+        // LHLD $20F8
+        let lhld = info(&[0x2A, 0xF8, 0x20]);
+        assert_eq!(
+            Some(Access {
+                location: Location::Address(0x20F8),
+                bytes: 2
+            }),
+            lhld.memory_read
+        );
+        assert_eq!(16, lhld.cycles.not_taken);
+
+        // RST 1, PCHL, OUT 3
+        assert_eq!(
+            (Some(Branch::Rst), Some(8)),
+            (info(&[0xCF]).branch, info(&[0xCF]).target)
+        );
+        assert_eq!(
+            (Some(Branch::Jump), None),
+            (info(&[0xE9]).branch, info(&[0xE9]).target)
+        );
+        assert_eq!(Some(3), info(&[0xD3, 0x03]).port);
+
+        // POP PSW writes every flag
+        assert_eq!(Flags::ALL, info(&[0xF1]).flags_written);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::Argument;
+use crate::Cmd;
+#[cfg(feature = "alloc")]
+use crate::{Command, Style, SymbolTable};
+use core::fmt::{self, Display, Formatter};
+
+/// Why [try_disassemble](fn.try_disassemble.html) could not decode an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// No bytes left
+    Empty,
+    /// `opcode` needs `size` bytes but only `available` are left
+    Truncated { opcode: u8, size: u8, available: u8 },
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "no bytes to decode"),
+            DecodeError::Truncated {
+                opcode,
+                size,
+                available,
+            } => write!(
+                f,
+                "opcode {:02X} needs {} bytes, only {} left",
+                opcode, size, available
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+/// Mnemonic, operands and size of an opcode, the result of the opcode table
+pub(crate) struct Opcode {
+    pub cmd: Cmd,
+    pub args: [Argument; 2],
+    pub count: u8,
+    pub size: u8,
+}
+
+impl From<(Cmd, u8)> for Opcode {
+    fn from((cmd, size): (Cmd, u8)) -> Self {
+        Opcode {
+            cmd,
+            args: [Argument::D8(0); 2],
+            count: 0,
+            size,
+        }
+    }
+}
+
+impl From<(Cmd, Argument, u8)> for Opcode {
+    fn from((cmd, arg, size): (Cmd, Argument, u8)) -> Self {
+        Opcode {
+            cmd,
+            args: [arg, Argument::D8(0)],
+            count: 1,
+            size,
+        }
+    }
+}
+
+impl From<(Cmd, Argument, Argument, u8)> for Opcode {
+    fn from((cmd, first, second, size): (Cmd, Argument, Argument, u8)) -> Self {
+        Opcode {
+            cmd,
+            args: [first, second],
+            count: 2,
+            size,
+        }
+    }
+}
+
+/// Decoded instruction that borrows its bytes, unlike [Command](struct.Command.html)
+/// it keeps operands inline and needs no allocation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction<'a> {
+    pub cmd: Cmd,
+    args: [Argument; 2],
+    count: u8,
+    /// Opcode and operand bytes
+    pub bytes: &'a [u8],
+}
+
+impl<'a> Instruction<'a> {
+    pub(crate) fn new(opcode: Opcode, bytes: &'a [u8]) -> Self {
+        Instruction {
+            cmd: opcode.cmd,
+            args: opcode.args,
+            count: opcode.count,
+            bytes,
+        }
+    }
+
+    pub fn args(&self) -> &[Argument] {
+        &self.args[..self.count as usize]
+    }
+
+    pub fn size(&self) -> u8 {
+        self.bytes.len() as u8
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<Instruction<'_>> for Command {
+    fn from(instruction: Instruction<'_>) -> Self {
+        Command {
+            cmd: instruction.cmd,
+            args: instruction.args().to_vec(),
+            size: instruction.size(),
+            bytes: Some(instruction.bytes.to_vec()),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> From<&'a Command> for Instruction<'a> {
+    /// Keeps the first two arguments, decoded commands have no more
+    fn from(command: &'a Command) -> Self {
+        let mut args = [Argument::D8(0); 2];
+        let count = command.args.len().min(args.len());
+        args[..count].copy_from_slice(&command.args[..count]);
+        Instruction {
+            cmd: command.cmd,
+            args,
+            count: count as u8,
+            bytes: command.get_bytes(),
+        }
+    }
+}
+
+/// Written in the default [Style](struct.Style.html)
+#[cfg(feature = "alloc")]
+impl Display for Instruction<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let text = Style::default().instruction(*self, &SymbolTable::default());
+        f.pad(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{disassemble, try_disassemble};
+
+    #[test]
+    fn try_disassemble_errors() {
+        let instruction = try_disassemble(&[0x21, 0x5C, 0x0A, 0x00]).unwrap();
+        assert_eq!(Cmd::LXI, instruction.cmd);
+        assert_eq!(
+            &[Argument::H, Argument::D16(0x5C, 0x0A)],
+            instruction.args()
+        );
+        assert_eq!(&[0x21, 0x5C, 0x0A], instruction.bytes);
+        assert_eq!(
+            disassemble(&[0x21, 0x5C, 0x0A]).to_string(),
+            instruction.to_string()
+        );
+        assert_eq!(
+            "C9        RET",
+            try_disassemble(&[0xC9]).unwrap().to_string()
+        );
+        assert_eq!("21 5C 0A  LXI  H, $0A5C", format!("{}", instruction));
+
+        assert_eq!(Err(DecodeError::Empty), try_disassemble(&[]));
+        assert_eq!(
+            Err(DecodeError::Truncated {
+                opcode: 0xCD,
+                size: 3,
+                available: 2
+            }),
+            try_disassemble(&[0xCD, 0x00])
+        );
+        assert_eq!(0, Command::iterator(&[0x00, 0xCD, 0x00]).skip(1).count());
+    }
+}
@@ -1,17 +1,94 @@
-use std::fmt::Formatter;
+//! Intel 8080 disassembler.
+//!
+//! Without the default `std` feature only [try_disassemble](fn.try_disassemble.html)
+//! and [Instruction](struct.Instruction.html) are built and the crate needs only `core`,
+//! `alloc` feature adds [Command](struct.Command.html), instruction text in
+//! a [Syntax](struct.Syntax.html) and symbol tables.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::fmt::Formatter;
 use Argument::*;
 
 extern crate strum;
 #[macro_use]
 extern crate strum_macros;
 
+#[cfg(feature = "alloc")]
 pub mod command;
+#[cfg(feature = "alloc")]
 pub use command::*;
 
 pub mod argument;
 pub use argument::*;
 
-#[derive(IntoStaticStr)]
+#[cfg(feature = "alloc")]
+pub mod image;
+#[cfg(feature = "alloc")]
+pub use image::*;
+
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub use analysis::*;
+
+#[cfg(feature = "std")]
+pub mod coverage;
+#[cfg(feature = "std")]
+pub use coverage::*;
+
+#[cfg(feature = "alloc")]
+pub mod symbols;
+#[cfg(feature = "alloc")]
+pub use symbols::*;
+
+#[cfg(feature = "alloc")]
+pub mod source;
+#[cfg(feature = "alloc")]
+pub use source::*;
+
+#[cfg(feature = "std")]
+pub mod xref;
+#[cfg(feature = "std")]
+pub use xref::*;
+
+#[cfg(feature = "std")]
+pub mod graph;
+#[cfg(feature = "std")]
+pub use graph::*;
+
+#[cfg(feature = "alloc")]
+pub mod info;
+#[cfg(feature = "alloc")]
+pub use info::*;
+
+pub mod instruction;
+pub use instruction::*;
+
+#[cfg(feature = "std")]
+pub mod ports;
+#[cfg(feature = "std")]
+pub use ports::*;
+
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub use diff::*;
+
+#[cfg(feature = "std")]
+pub mod search;
+#[cfg(feature = "std")]
+pub use search::*;
+
+#[cfg(feature = "std")]
+pub mod sprite;
+#[cfg(feature = "std")]
+pub use sprite::*;
+
+/// Mnemonic, `NOPU` stands for undocumented opcodes which act as `NOP`
+#[derive(IntoStaticStr, EnumString, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Cmd {
     NOP,
     NOPU,
@@ -96,8 +173,8 @@ pub enum Cmd {
     CPI,
 }
 
-impl std::fmt::Display for Cmd {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Cmd {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         let s: &'static str = self.into();
         write!(f, "{}", s)
     }
@@ -109,16 +186,32 @@ impl AsRef<str> for Cmd {
     }
 }
 
+/// Decodes the instruction at the start of `bytes`
+///
+/// # Panics
+/// If `bytes` is empty or ends inside the instruction,
+/// [try_disassemble](fn.try_disassemble.html) returns an error instead
+#[cfg(feature = "alloc")]
 pub fn disassemble(bytes: &[u8]) -> Command {
-    // use Cmd::*;
-    let mut command: Command = match *bytes {
+    match try_disassemble(bytes) {
+        Ok(instruction) => instruction.into(),
+        Err(err) => panic!("{}", err),
+    }
+}
+
+/// Decodes the instruction at the start of `bytes` without allocating
+pub fn try_disassemble(bytes: &[u8]) -> Result<Instruction<'_>, DecodeError> {
+    let mut padded = [0; 3];
+    let available = bytes.len().min(padded.len());
+    padded[..available].copy_from_slice(&bytes[..available]);
+    let opcode: Opcode = match padded {
         [0x0, ..] => (Cmd::NOP, 1).into(),
         [0x01, d16_lo, d16_hi, ..] => (Cmd::LXI, B, Addr(d16_lo, d16_hi), 3).into(),
         [0x02, ..] => (Cmd::STAX, B, 1).into(),
         [0x03, ..] => (Cmd::INX, B, 1).into(),
         [0x04, ..] => (Cmd::INR, B, 1).into(),
         [0x05, ..] => (Cmd::DCR, B, 1).into(),
-        [0x06, d8, ..] => (Cmd::MVI, vec![B, D8(d8)], 2).into(),
+        [0x06, d8, ..] => (Cmd::MVI, B, D8(d8), 2).into(),
         [0x07, ..] => (Cmd::RLC, 1).into(),
         [0x08, ..] => (Cmd::NOPU, 1).into(),
         [0x09, ..] => (Cmd::DAD, B, 1).into(),
@@ -172,7 +265,7 @@ pub fn disassemble(bytes: &[u8]) -> Command {
         [0x36, d8, ..] => (Cmd::MVI, M, D8(d8), 2).into(),
         [0x37, ..] => (Cmd::STC, 1).into(),
         [0x38, ..] => (Cmd::NOPU, 1).into(),
-        [0x39, ..] => (Cmd::DAD, 1).into(),
+        [0x39, ..] => (Cmd::DAD, SP, 1).into(),
         [0x3A, lo, hi, ..] => (Cmd::LDA, Addr(lo, hi), 3).into(),
         [0x3B, ..] => (Cmd::DCX, SP, 1).into(),
         [0x3C, ..] => (Cmd::INR, A, 1).into(),
@@ -383,15 +476,19 @@ pub fn disassemble(bytes: &[u8]) -> Command {
         [0xFD, ..] => (Cmd::NOPU, 1).into(),
         [0xFE, d8, ..] => (Cmd::CPI, D8(d8), 2).into(),
         [0xFF, ..] => (Cmd::RST, D8(7), 1).into(),
-
-        _ => {
-            let what = bytes.iter().take(3).collect::<Vec<_>>();
-            eprintln!("Next three bytes: {:X?}", what);
-            todo!()
-        } //[0x,..] => (vec!["".into(), ], 1),
     };
-    command.bytes = Some(bytes[..command.size as usize].to_vec());
-    command
+    if bytes.is_empty() {
+        return Err(DecodeError::Empty);
+    }
+    if opcode.size as usize > available {
+        return Err(DecodeError::Truncated {
+            opcode: padded[0],
+            size: opcode.size,
+            available: available as u8,
+        });
+    }
+    let size = opcode.size as usize;
+    Ok(Instruction::new(opcode, &bytes[..size]))
 }
 
 // #[cfg(test)]
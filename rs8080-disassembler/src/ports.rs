@@ -0,0 +1,191 @@
+use crate::symbols::is_name;
+use crate::{parse_number, Argument, Cmd, Command, SymbolError};
+use std::collections::BTreeMap;
+
+/// Ports of the Space Invaders board as handled by the emulator
+const INVADERS: &str = "\
+# Taito Space Invaders
+in  0 inputs0                                       # unused by the game
+in  1 inputs1  0=coin 1=start2 2=start1 4=shot1 5=left1 6=right1
+in  2 inputs2  0-1=ships 2=tilt 3=extra_ship 4=shot2 5=left2 6=right2 7=coin_info
+in  3 shift_result
+out 2 shift_amount
+out 3 sound1   0=ufo 1=shot 2=player_die 3=invader_die 4=extended_play 5=amplifier
+out 4 shift_data
+out 5 sound2   0=fleet1 1=fleet2 2=fleet3 3=fleet4 4=ufo_hit
+out 6 watchdog
+";
+
+/// Bits `low..=high` of a port
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortBits {
+    pub low: u8,
+    pub high: u8,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    pub name: String,
+    /// Named bits in the order of the map
+    pub bits: Vec<PortBits>,
+}
+
+impl Port {
+    /// Name followed by its bits, e.g. `inputs1 (0 coin, 1 start2)`
+    pub fn describe(&self) -> String {
+        if self.bits.is_empty() {
+            return self.name.clone();
+        }
+        let bits: Vec<String> = self
+            .bits
+            .iter()
+            .map(|bits| match bits.low == bits.high {
+                true => format!("{} {}", bits.low, bits.name),
+                false => format!("{}-{} {}", bits.low, bits.high, bits.name),
+            })
+            .collect();
+        format!("{} ({})", self.name, bits.join(", "))
+    }
+}
+
+/// Names and bit meanings of the input and output ports of a machine
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PortMap {
+    inputs: BTreeMap<u8, Port>,
+    outputs: BTreeMap<u8, Port>,
+}
+
+fn bits(word: &str) -> Option<PortBits> {
+    let (range, name) = word.split_once('=')?;
+    let bit = |text: &str| parse_number(text).filter(|b| *b < 8).map(|b| b as u8);
+    let (low, high) = match range.split_once('-') {
+        Some((low, high)) => (bit(low)?, bit(high)?),
+        None => (bit(range)?, bit(range)?),
+    };
+    (low <= high && is_name(name)).then(|| PortBits {
+        low,
+        high,
+        name: name.to_owned(),
+    })
+}
+
+impl PortMap {
+    /// Parses a port map, each line is
+    /// `in|out port name [bit=name | low-high=name]...`,
+    /// text after `#` or `;` is ignored
+    pub fn parse(text: &str) -> Result<PortMap, SymbolError> {
+        let mut map = PortMap::default();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| SymbolError {
+                line: i + 1,
+                message,
+            };
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let (ports, number, name, rest) = match words.as_slice() {
+                [] => continue,
+                [direction, number, name, rest @ ..] => {
+                    let ports = match direction.to_ascii_lowercase().as_str() {
+                        "in" => &mut map.inputs,
+                        "out" => &mut map.outputs,
+                        _ => return Err(error(format!("expected in or out, got '{}'", direction))),
+                    };
+                    (ports, number, name, rest)
+                }
+                _ => {
+                    return Err(error(format!(
+                        "expected `in|out port name`, got '{}'",
+                        line.trim()
+                    )))
+                }
+            };
+            let number = parse_number(number)
+                .and_then(|n| u8::try_from(n).ok())
+                .ok_or_else(|| error(format!("invalid port '{}'", number)))?;
+            if !is_name(name) {
+                return Err(error(format!("invalid name '{}'", name)));
+            }
+            let bits = rest
+                .iter()
+                .map(|word| bits(word).ok_or_else(|| error(format!("invalid bits '{}'", word))))
+                .collect::<Result<_, _>>()?;
+            ports.insert(
+                number,
+                Port {
+                    name: (*name).to_owned(),
+                    bits,
+                },
+            );
+        }
+        Ok(map)
+    }
+
+    /// Ports of Space Invaders
+    pub fn space_invaders() -> PortMap {
+        PortMap::parse(INVADERS).expect("built-in port map is valid")
+    }
+
+    pub fn input(&self, port: u8) -> Option<&Port> {
+        self.inputs.get(&port)
+    }
+
+    pub fn output(&self, port: u8) -> Option<&Port> {
+        self.outputs.get(&port)
+    }
+
+    /// Input ports in ascending order
+    pub fn inputs(&self) -> impl Iterator<Item = (u8, &Port)> {
+        self.inputs.iter().map(|(port, p)| (*port, p))
+    }
+
+    /// Output ports in ascending order
+    pub fn outputs(&self) -> impl Iterator<Item = (u8, &Port)> {
+        self.outputs.iter().map(|(port, p)| (*port, p))
+    }
+
+    /// Port read by `IN` or written by `OUT`
+    pub fn port(&self, cmd: &Command) -> Option<&Port> {
+        match (cmd.cmd, cmd.args.as_slice()) {
+            (Cmd::IN, [Argument::D8(port)]) => self.input(*port),
+            (Cmd::OUT, [Argument::D8(port)]) => self.output(*port),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassemble;
+
+    #[test]
+    fn ports_parse() {
+        let map = PortMap::space_invaders();
+        let describe = |bytes: &[u8]| map.port(&disassemble(bytes)).map(Port::describe);
+        assert_eq!(
+            Some("inputs1 (0 coin, 1 start2, 2 start1, 4 shot1, 5 left1, 6 right1)".to_owned()),
+            describe(&[0xDB, 0x01])
+        );
+        assert_eq!(Some("shift_amount".to_owned()), describe(&[0xD3, 0x02]));
+        assert_eq!(Some("shift_result".to_owned()), describe(&[0xDB, 0x03]));
+        assert_eq!(None, describe(&[0xD3, 0x07]));
+        assert_eq!(None, describe(&[0x3E, 0x01]));
+        assert_eq!(
+            PortBits {
+                low: 0,
+                high: 1,
+                name: "ships".to_owned()
+            },
+            map.input(2).unwrap().bits[0]
+        );
+
+        let err = PortMap::parse("in 1 a\nout 256 b").unwrap_err();
+        assert_eq!("line 2: invalid port '256'", err.to_string());
+        assert_eq!(1, PortMap::parse("inout 1 a").unwrap_err().line);
+        assert_eq!(1, PortMap::parse("in 1").unwrap_err().line);
+        assert_eq!(1, PortMap::parse("in 1 a 8=b").unwrap_err().line);
+        assert_eq!(1, PortMap::parse("in 1 a 3-2=b").unwrap_err().line);
+        assert_eq!(1, PortMap::parse("in 1 a 3").unwrap_err().line);
+    }
+}
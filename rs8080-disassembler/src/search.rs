@@ -0,0 +1,307 @@
+use crate::{Argument, Cmd, Command, SymbolError};
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// Error in the text of a [Pattern](struct.Pattern.html)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatternError {
+    pub message: String,
+}
+
+impl Display for PatternError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+fn error(message: String) -> PatternError {
+    PatternError { message }
+}
+
+/// Pattern of one operand
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operand {
+    /// `?`, any operand
+    Any,
+    /// `r`, A, B, C, D, E, H, L or M
+    Register,
+    /// `rp`, B, D, H, SP or PSW
+    Pair,
+    Exact(Argument),
+    /// Hex digits, `None` for a `?` digit
+    Number(Vec<Option<u8>>),
+}
+
+impl Operand {
+    fn parse(text: &str) -> Result<Operand, PatternError> {
+        let register = match text.to_ascii_uppercase().as_str() {
+            "?" => return Ok(Operand::Any),
+            "R" => return Ok(Operand::Register),
+            "RP" => return Ok(Operand::Pair),
+            "A" => Argument::A,
+            "B" => Argument::B,
+            "C" => Argument::C,
+            "D" => Argument::D,
+            "E" => Argument::E,
+            "H" => Argument::H,
+            "L" => Argument::L,
+            "M" => Argument::M,
+            "SP" => Argument::SP,
+            "PSW" => Argument::PSW,
+            _ => return Operand::number(text),
+        };
+        Ok(Operand::Exact(register))
+    }
+
+    /// Hex digits with an optional `$` or `0x` prefix or `H` suffix
+    fn number(text: &str) -> Result<Operand, PatternError> {
+        let digits = text
+            .strip_prefix('$')
+            .or_else(|| text.strip_prefix("0x"))
+            .or_else(|| text.strip_prefix("0X"))
+            .or_else(|| text.strip_suffix(['h', 'H']))
+            .unwrap_or(text);
+        let digits: Option<Vec<Option<u8>>> = digits
+            .chars()
+            .map(|c| match c {
+                '?' => Some(None),
+                _ => c.to_digit(16).map(|d| Some(d as u8)),
+            })
+            .collect();
+        match digits {
+            Some(digits) if (1..=4).contains(&digits.len()) => Ok(Operand::Number(digits)),
+            _ => Err(error(format!("invalid operand '{}'", text))),
+        }
+    }
+
+    fn matches(&self, arg: &Argument) -> bool {
+        use Argument::*;
+        let (value, width) = match *arg {
+            Addr(lo, hi) | D16(lo, hi) => (u16::from_le_bytes([lo, hi]), 4),
+            D8(d8) => (d8 as u16, 2),
+            _ => (0, 0),
+        };
+        match self {
+            Operand::Any => true,
+            Operand::Register => matches!(arg, A | B | C | D | E | H | L | M),
+            Operand::Pair => matches!(arg, B | D | H | SP | PSW),
+            Operand::Exact(register) => register == arg,
+            Operand::Number(digits) => {
+                // shorter patterns have leading zeros
+                width >= digits.len()
+                    && (0..width).all(|i| {
+                        let digit = (value >> (4 * (width - 1 - i))) as u8 & 0xF;
+                        match i.checked_sub(width - digits.len()) {
+                            Some(i) => digits[i].is_none_or(|d| d == digit),
+                            None => digit == 0,
+                        }
+                    })
+            }
+        }
+    }
+}
+
+/// Pattern of one instruction, without operands it matches any
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    /// `None` for `?`, any mnemonic
+    cmd: Option<Cmd>,
+    operands: Option<Vec<Operand>>,
+}
+
+impl Step {
+    fn parse(text: &str) -> Result<Step, PatternError> {
+        let text = text.trim();
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (mnemonic, Some(operands)),
+            None => (text, None),
+        };
+        let cmd = match mnemonic {
+            "" => return Err(error("empty instruction".to_owned())),
+            "?" => None,
+            _ => Some(
+                Cmd::from_str(&mnemonic.to_ascii_uppercase())
+                    .map_err(|_| error(format!("unknown mnemonic '{}'", mnemonic)))?,
+            ),
+        };
+        let operands = operands
+            .map(|operands| {
+                operands
+                    .split(',')
+                    .map(|operand| Operand::parse(operand.trim()))
+                    .collect()
+            })
+            .transpose()?;
+        Ok(Step { cmd, operands })
+    }
+
+    fn matches(&self, cmd: &Command) -> bool {
+        self.cmd.is_none_or(|c| c == cmd.cmd)
+            && self.operands.as_ref().is_none_or(|operands| {
+                operands.len() == cmd.args.len()
+                    && operands.iter().zip(&cmd.args).all(|(o, a)| o.matches(a))
+            })
+    }
+}
+
+/// Sequence of instructions separated by `;`, e.g. `MVI A,?; OUT 03`.
+/// Operands are `?` for any, `r` for a register or M, `rp` for a register pair,
+/// register names or hex numbers where `?` is any digit, e.g. `$20??`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    steps: Vec<Step>,
+}
+
+/// Instructions that match a pattern, the first one at `address`
+#[derive(Clone)]
+pub struct Match<'a> {
+    pub address: u16,
+    pub instructions: Vec<(u16, &'a Command)>,
+}
+
+impl Pattern {
+    pub fn parse(text: &str) -> Result<Pattern, PatternError> {
+        let steps = text.split(';').map(Step::parse).collect::<Result<_, _>>()?;
+        Ok(Pattern { steps })
+    }
+
+    /// Number of instructions
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Runs of consecutive instructions that match, they may overlap;
+    /// `instructions` are decoded ones in address order, e.g. of an
+    /// [Analysis](struct.Analysis.html), so data is never matched
+    pub fn search<'a>(
+        &self,
+        instructions: impl IntoIterator<Item = (u16, &'a Command)>,
+    ) -> Vec<Match<'a>> {
+        let instructions: Vec<(u16, &Command)> = instructions.into_iter().collect();
+        let follows = |pair: &[(u16, &Command)]| {
+            let (address, cmd) = pair[0];
+            address as u32 + cmd.size as u32 == pair[1].0 as u32
+        };
+        instructions
+            .windows(self.steps.len())
+            .filter(|window| {
+                window.windows(2).all(follows)
+                    && self
+                        .steps
+                        .iter()
+                        .zip(*window)
+                        .all(|(s, (_, c))| s.matches(c))
+            })
+            .map(|window| Match {
+                address: window[0].0,
+                instructions: window.to_vec(),
+            })
+            .collect()
+    }
+}
+
+/// Named pattern of a signature file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: String,
+    pub pattern: Pattern,
+}
+
+impl Signature {
+    /// Parses a signature file, each line is `name = pattern`,
+    /// text after `#` is ignored
+    pub fn parse_file(text: &str) -> Result<Vec<Signature>, SymbolError> {
+        let mut signatures = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| SymbolError {
+                line: i + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, pattern) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected `name = pattern`, got '{}'", line)))?;
+            let pattern = Pattern::parse(pattern).map_err(|err| error(err.message))?;
+            signatures.push(Signature {
+                name: name.trim().to_owned(),
+                pattern,
+            });
+        }
+        Ok(signatures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Analysis, MemoryImage};
+
+    #[test]
+    fn search_patterns() {
+        let mut image = MemoryImage::default();
+        // 0: LXI H,$20F8  3: CALL $000D  6: MVI A,$01  8: OUT $03  A: JMP $0000
+        // D: MOV A,M  E: RET  F: MVI A,$01 in data
+        image
+            .load(
+                0,
+                &[
+                    0x21, 0xF8, 0x20, 0xCD, 0x0D, 0x00, 0x3E, 0x01, 0xD3, 0x03, 0xC3, 0x00, 0x00,
+                    0x7E, 0xC9, 0x3E, 0x01,
+                ],
+            )
+            .unwrap();
+        let analysis = Analysis::new(&image, &[0]);
+        let search = |text: &str| -> Vec<u16> {
+            Pattern::parse(text)
+                .unwrap()
+                .search(analysis.instructions())
+                .iter()
+                .map(|m| m.address)
+                .collect()
+        };
+        assert_eq!(vec![3], search("CALL ????"));
+        assert_eq!(vec![0], search("lxi h,$20??"));
+        assert_eq!(Vec::<u16>::new(), search("LXI H,$21??"));
+        assert_eq!(vec![6], search("MVI A,?; OUT 03"));
+        assert_eq!(vec![6], search("MVI r,1; ?; JMP 0"));
+        assert_eq!(vec![0], search("LXI rp,?"));
+        assert_eq!(vec![0xD], search("MOV A,M"));
+        assert_eq!(vec![0xD], search("MOV r,r; RET"));
+        assert_eq!(vec![0, 3, 6, 8, 0xA, 0xD, 0xE], search("?"));
+        assert_eq!(Vec::<u16>::new(), search("OUT ???"));
+        let found = Pattern::parse("CALL; MVI")
+            .unwrap()
+            .search(analysis.instructions());
+        assert_eq!(2, found[0].instructions.len());
+        assert_eq!(6, found[0].instructions[1].0);
+        // data and instructions with a gap between them do not match
+        assert_eq!(Vec::<u16>::new(), search("RET; MVI A,1"));
+        let gap = analysis.instructions().filter(|(address, _)| *address != 6);
+        assert_eq!(0, Pattern::parse("CALL; OUT").unwrap().search(gap).len());
+
+        assert!(Pattern::parse("FOO A").is_err());
+        assert!(Pattern::parse("MVI A,$12345").is_err());
+        assert!(Pattern::parse("MVI A,X").is_err());
+        assert!(Pattern::parse("CALL;").is_err());
+
+        let signatures =
+            Signature::parse_file("# sounds\nplay_sound = MVI A,?; OUT 3\n\ncall = CALL").unwrap();
+        assert_eq!(2, signatures.len());
+        assert_eq!("play_sound", signatures[0].name);
+        let err = Signature::parse_file("a = RET\nb RET").unwrap_err();
+        assert_eq!(2, err.line);
+        assert_eq!(
+            "line 1: unknown mnemonic 'RETX'",
+            Signature::parse_file("a = RETX").unwrap_err().to_string()
+        );
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{Argument, Cmd, Instruction, SymbolTable};
+use alloc::borrow::ToOwned;
+use alloc::string::{String, ToString};
+use alloc::{format, vec, vec::Vec};
+
+/// How hex numbers are written
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HexStyle {
+    /// `$0A5C`
+    #[default]
+    Dollar,
+    /// `0x0A5C`
+    Prefix,
+    /// `0A5CH`, with a leading zero when the first digit is a letter
+    Suffix,
+}
+
+/// Assembler syntax of listings, traces and generated source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Syntax {
+    pub hex: HexStyle,
+    /// Z80 mnemonics instead of Intel ones
+    pub z80: bool,
+    /// Lowercase mnemonics, registers and hex digits, names keep their case
+    pub lowercase: bool,
+    /// Between operands and between values of data lines
+    pub separator: String,
+}
+
+impl Default for Syntax {
+    fn default() -> Self {
+        Syntax {
+            hex: HexStyle::default(),
+            z80: false,
+            lowercase: false,
+            separator: ", ".to_owned(),
+        }
+    }
+}
+
+impl Syntax {
+    pub fn byte(&self, value: u8) -> String {
+        self.hex(format!("{:02X}", value))
+    }
+
+    pub fn word(&self, value: u16) -> String {
+        self.hex(format!("{:04X}", value))
+    }
+
+    fn hex(&self, digits: String) -> String {
+        let number = match self.hex {
+            HexStyle::Dollar => format!("${}", digits),
+            HexStyle::Prefix => format!("0x{}", digits),
+            HexStyle::Suffix if digits.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+                format!("0{}H", digits)
+            }
+            HexStyle::Suffix => format!("{}H", digits),
+        };
+        self.case(&number)
+    }
+
+    /// Mnemonic, register or directive such as `DB` in the case of this syntax
+    pub fn case(&self, text: &str) -> String {
+        match self.lowercase {
+            true => text.to_ascii_lowercase(),
+            false => text.to_owned(),
+        }
+    }
+
+    /// Directive such as `DB` followed by `values` joined by the separator
+    pub fn directive(&self, name: &str, values: &[String]) -> String {
+        format!("{:4} {}", self.case(name), values.join(&self.separator))
+    }
+
+    /// `DB` with the bytes of `cmd`, for opcodes that have no mnemonic in this syntax
+    fn bytes(&self, cmd: &Instruction) -> String {
+        let values: Vec<String> = cmd.bytes.iter().map(|b| self.byte(*b)).collect();
+        self.directive("DB", &values)
+    }
+
+    fn operand(&self, arg: &Argument, symbols: &SymbolTable) -> String {
+        match *arg {
+            Argument::Addr(lo, hi) => {
+                let address = u16::from_le_bytes([lo, hi]);
+                match symbols.get(address) {
+                    Some(name) => name.to_owned(),
+                    None => self.word(address),
+                }
+            }
+            Argument::D16(lo, hi) => self.word(u16::from_le_bytes([lo, hi])),
+            Argument::D8(value) => self.byte(value),
+            ref reg if self.z80 => z80_register(reg).to_owned(),
+            ref reg => <&str>::from(reg).to_owned(),
+        }
+    }
+
+    /// Instruction in this syntax, addresses named in `symbols` are shown by name
+    pub fn instruction<'a>(
+        &self,
+        cmd: impl Into<Instruction<'a>>,
+        symbols: &SymbolTable,
+    ) -> String {
+        let cmd = cmd.into();
+        let args: Vec<String> = match (cmd.cmd, cmd.args()) {
+            // Intel writes the vector number, Z80 its address
+            (Cmd::RST, [Argument::D8(n)]) if self.z80 => vec![self.byte(n * 8)],
+            (Cmd::RST, [Argument::D8(n)]) => vec![n.to_string()],
+            (_, args) => args.iter().map(|arg| self.operand(arg, symbols)).collect(),
+        };
+        let (mnemonic, args) = match (cmd.cmd, self.z80) {
+            (Cmd::NOPU, _) => return self.bytes(&cmd),
+            (_, false) => (cmd.cmd.as_ref(), args),
+            (_, true) => match z80(cmd.cmd, args) {
+                Some(z80) => z80,
+                None => return self.bytes(&cmd),
+            },
+        };
+        let name = cmd.args().iter().find_map(|arg| match *arg {
+            Argument::Addr(lo, hi) => symbols.get(u16::from_le_bytes([lo, hi])),
+            _ => None,
+        });
+        let args: Vec<String> = match (self.lowercase, name) {
+            // registers written by `z80` are lowercased around the name
+            (true, Some(name)) => args
+                .iter()
+                .map(|arg| {
+                    let parts: Vec<String> = arg.split(name).map(|p| self.case(p)).collect();
+                    parts.join(name)
+                })
+                .collect(),
+            (true, None) => args.iter().map(|arg| self.case(arg)).collect(),
+            (false, _) => args,
+        };
+        if args.is_empty() {
+            self.case(mnemonic)
+        } else {
+            format!("{:4} {}", self.case(mnemonic), args.join(&self.separator))
+        }
+    }
+}
+
+/// How listings and traces write instructions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Style {
+    pub syntax: Syntax,
+    /// Column of instruction bytes before the text
+    pub bytes: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            syntax: Syntax::default(),
+            bytes: true,
+        }
+    }
+}
+
+impl Style {
+    /// Bytes as `21 5C 0A` padded for three bytes, empty without the byte column
+    pub fn byte_column(&self, bytes: &[u8]) -> String {
+        if !self.bytes {
+            return String::new();
+        }
+        let bytes: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{:<8}  ", self.syntax.case(&bytes.join(" ")))
+    }
+
+    /// Byte column and instruction
+    pub fn instruction<'a>(
+        &self,
+        cmd: impl Into<Instruction<'a>>,
+        symbols: &SymbolTable,
+    ) -> String {
+        let cmd = cmd.into();
+        format!(
+            "{}{}",
+            self.byte_column(cmd.bytes),
+            self.syntax.instruction(cmd, symbols)
+        )
+    }
+}
+
+fn z80_register(reg: &Argument) -> &'static str {
+    match reg {
+        Argument::M => "(HL)",
+        Argument::PSW => "AF",
+        Argument::SP => "SP",
+        reg => reg.into(),
+    }
+}
+
+/// Register pair named by its first register
+fn z80_pair(reg: &str) -> String {
+    match reg {
+        "B" => "BC".to_owned(),
+        "D" => "DE".to_owned(),
+        "H" => "HL".to_owned(),
+        other => other.to_owned(),
+    }
+}
+
+fn z80_condition(cmd: Cmd) -> &'static str {
+    match cmd {
+        Cmd::JNZ | Cmd::CNZ | Cmd::RNZ => "NZ",
+        Cmd::JZ | Cmd::CZ | Cmd::RZ => "Z",
+        Cmd::JNC | Cmd::CNC | Cmd::RNC => "NC",
+        Cmd::JC | Cmd::CC | Cmd::RC => "C",
+        Cmd::JPO | Cmd::CPO | Cmd::RPO => "PO",
+        Cmd::JPE | Cmd::CPE | Cmd::RPE => "PE",
+        Cmd::JP | Cmd::CP | Cmd::RP => "P",
+        _ => "M",
+    }
+}
+
+/// Z80 mnemonic and operands of an 8080 instruction whose operands are
+/// already written in Z80 syntax, `None` for 8085 only opcodes
+fn z80(cmd: Cmd, args: Vec<String>) -> Option<(&'static str, Vec<String>)> {
+    let a = || "A".to_owned();
+    let hl = || "HL".to_owned();
+    let indirect = |arg: &String| format!("({})", arg);
+    let mut args = args.into_iter();
+    let mut arg = || args.next().unwrap_or_default();
+    Some(match cmd {
+        Cmd::NOP | Cmd::NOPU => ("NOP", vec![]),
+        Cmd::RIM | Cmd::SIM => return None,
+        Cmd::MOV | Cmd::MVI => ("LD", vec![arg(), arg()]),
+        Cmd::LXI => ("LD", vec![z80_pair(&arg()), arg()]),
+        Cmd::LDA => ("LD", vec![a(), indirect(&arg())]),
+        Cmd::STA => ("LD", vec![indirect(&arg()), a()]),
+        Cmd::LHLD => ("LD", vec![hl(), indirect(&arg())]),
+        Cmd::SHLD => ("LD", vec![indirect(&arg()), hl()]),
+        Cmd::LDAX => ("LD", vec![a(), indirect(&z80_pair(&arg()))]),
+        Cmd::STAX => ("LD", vec![indirect(&z80_pair(&arg())), a()]),
+        Cmd::XCHG => ("EX", vec!["DE".to_owned(), hl()]),
+        Cmd::XTHL => ("EX", vec!["(SP)".to_owned(), hl()]),
+        Cmd::SPHL => ("LD", vec!["SP".to_owned(), hl()]),
+        Cmd::PCHL => ("JP", vec!["(HL)".to_owned()]),
+        Cmd::ADD | Cmd::ADI => ("ADD", vec![a(), arg()]),
+        Cmd::ADC | Cmd::ACI => ("ADC", vec![a(), arg()]),
+        Cmd::SUB | Cmd::SUI => ("SUB", vec![arg()]),
+        Cmd::SBB | Cmd::SBI => ("SBC", vec![a(), arg()]),
+        Cmd::ANA | Cmd::ANI => ("AND", vec![arg()]),
+        Cmd::XRA | Cmd::XRI => ("XOR", vec![arg()]),
+        Cmd::ORA | Cmd::ORI => ("OR", vec![arg()]),
+        Cmd::CMP | Cmd::CPI => ("CP", vec![arg()]),
+        Cmd::INR => ("INC", vec![arg()]),
+        Cmd::DCR => ("DEC", vec![arg()]),
+        Cmd::INX => ("INC", vec![z80_pair(&arg())]),
+        Cmd::DCX => ("DEC", vec![z80_pair(&arg())]),
+        Cmd::DAD => ("ADD", vec![hl(), z80_pair(&arg())]),
+        Cmd::PUSH => ("PUSH", vec![z80_pair(&arg())]),
+        Cmd::POP => ("POP", vec![z80_pair(&arg())]),
+        Cmd::RLC => ("RLCA", vec![]),
+        Cmd::RRC => ("RRCA", vec![]),
+        Cmd::RAL => ("RLA", vec![]),
+        Cmd::RAR => ("RRA", vec![]),
+        Cmd::CMA => ("CPL", vec![]),
+        Cmd::STC => ("SCF", vec![]),
+        Cmd::CMC => ("CCF", vec![]),
+        Cmd::DAA => ("DAA", vec![]),
+        Cmd::HLT => ("HALT", vec![]),
+        Cmd::EI => ("EI", vec![]),
+        Cmd::DI => ("DI", vec![]),
+        Cmd::IN => ("IN", vec![a(), indirect(&arg())]),
+        Cmd::OUT => ("OUT", vec![indirect(&arg()), a()]),
+        Cmd::JMP => ("JP", vec![arg()]),
+        Cmd::CALL => ("CALL", vec![arg()]),
+        Cmd::RET => ("RET", vec![]),
+        Cmd::RST => ("RST", vec![arg()]),
+        Cmd::JNZ | Cmd::JZ | Cmd::JNC | Cmd::JC | Cmd::JPO | Cmd::JPE | Cmd::JP | Cmd::JM => {
+            ("JP", vec![z80_condition(cmd).to_owned(), arg()])
+        }
+        Cmd::CNZ | Cmd::CZ | Cmd::CNC | Cmd::CC | Cmd::CPO | Cmd::CPE | Cmd::CP | Cmd::CM => {
+            ("CALL", vec![z80_condition(cmd).to_owned(), arg()])
+        }
+        Cmd::RNZ | Cmd::RZ | Cmd::RNC | Cmd::RC | Cmd::RPO | Cmd::RPE | Cmd::RP | Cmd::RM => {
+            ("RET", vec![z80_condition(cmd).to_owned()])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassemble;
+
+    #[test]
+    fn source_syntax() {
+        let symbols = SymbolTable::parse("DrawSprite $15D3").unwrap();
+        let intel = Syntax {
+            hex: HexStyle::Suffix,
+            ..Syntax::default()
+        };
+        let z80 = Syntax {
+            z80: true,
+            ..Syntax::default()
+        };
+        let text =
+            |syntax: &Syntax, bytes: &[u8]| syntax.instruction(&disassemble(bytes), &symbols);
+        assert_eq!("CALL DrawSprite", text(&intel, &[0xCD, 0xD3, 0x15]));
+        assert_eq!("LXI  H, 0A5CH", text(&intel, &[0x21, 0x5C, 0x0A]));
+        assert_eq!("MVI  A, 10H", text(&intel, &[0x3E, 0x10]));
+        assert_eq!("RST  7", text(&intel, &[0xFF]));
+        assert_eq!("DB   08H", text(&intel, &[0x08]));
+        assert_eq!("LD   HL, $0A5C", text(&z80, &[0x21, 0x5C, 0x0A]));
+        assert_eq!("LD   (HL), A", text(&z80, &[0x77]));
+        assert_eq!("LD   A, ($20F8)", text(&z80, &[0x3A, 0xF8, 0x20]));
+        assert_eq!("CALL NZ, DrawSprite", text(&z80, &[0xC4, 0xD3, 0x15]));
+        assert_eq!("PUSH AF", text(&z80, &[0xF5]));
+        assert_eq!("RST  $38", text(&z80, &[0xFF]));
+        assert_eq!("OUT  ($03), A", text(&z80, &[0xD3, 0x03]));
+        assert_eq!("DB   $20", text(&z80, &[0x20]));
+
+        let lower = Style {
+            syntax: Syntax {
+                hex: HexStyle::Prefix,
+                z80: true,
+                lowercase: true,
+                separator: ",".to_owned(),
+            },
+            bytes: true,
+        };
+        let text = |style: &Style, bytes: &[u8]| style.instruction(&disassemble(bytes), &symbols);
+        assert_eq!(
+            "c4 d3 15  call nz,DrawSprite",
+            text(&lower, &[0xC4, 0xD3, 0x15])
+        );
+        assert_eq!(
+            "3a 5c 0a  ld   a,(0x0a5c)",
+            text(&lower, &[0x3A, 0x5C, 0x0A])
+        );
+        let plain = Style {
+            bytes: false,
+            ..Style::default()
+        };
+        assert_eq!("MVI  A, $10", text(&plain, &[0x3E, 0x10]));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::MemoryImage;
+use std::fmt::{self, Display, Formatter};
+
+/// Error in an image file or a bitmap that does not fit a sprite sheet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitmapError {
+    pub message: String,
+}
+
+impl Display for BitmapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for BitmapError {}
+
+fn error(message: impl Into<String>) -> BitmapError {
+    BitmapError {
+        message: message.into(),
+    }
+}
+
+/// 1 bit per pixel image, `true` for a lit pixel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bitmap {
+    pub width: usize,
+    pub height: usize,
+    /// Rows from the top
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn new(width: usize, height: usize) -> Bitmap {
+        Bitmap {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, lit: bool) {
+        self.pixels[y * self.width + x] = lit;
+    }
+
+    /// Decodes sprite bytes as `draw_space_invaders_vram` shows them: every
+    /// column is `rows` bytes from the bottom up with bit 0 at the bottom
+    pub fn from_columns(bytes: &[u8], rows: usize) -> Bitmap {
+        let height = rows * 8;
+        let mut bitmap = Bitmap::new(bytes.len() / rows, height);
+        for (x, column) in bytes.chunks_exact(rows).enumerate() {
+            for y in 0..height {
+                let bit = height - 1 - y;
+                bitmap.set(x, y, column[bit / 8] & (1 << (bit % 8)) != 0);
+            }
+        }
+        bitmap
+    }
+
+    /// Sprite bytes of [from_columns](#method.from_columns), the height must be a multiple of 8
+    pub fn to_columns(&self) -> Vec<u8> {
+        let rows = self.height / 8;
+        let mut bytes = vec![0; self.width * rows];
+        for x in 0..self.width {
+            for bit in 0..rows * 8 {
+                if self.get(x, self.height - 1 - bit) {
+                    bytes[x * rows + bit / 8] |= 1 << (bit % 8);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Rows of bits packed from the most significant one, lit pixels are 1,
+    /// as binary PBM and 1-bit PNG store them
+    pub fn packed_rows(&self) -> Vec<u8> {
+        let stride = self.width.div_ceil(8);
+        let mut data = vec![0; stride * self.height];
+        for y in 0..self.height {
+            for x in (0..self.width).filter(|x| self.get(*x, y)) {
+                data[y * stride + x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        data
+    }
+
+    /// Binary PBM (P4), lit pixels are 1 and show as black
+    pub fn to_pbm(&self) -> Vec<u8> {
+        let mut pbm = format!("P4\n{} {}\n", self.width, self.height).into_bytes();
+        pbm.extend(self.packed_rows());
+        pbm
+    }
+
+    /// Reads plain (P1) and binary (P4) PBM files
+    pub fn parse_pbm(data: &[u8]) -> Result<Bitmap, BitmapError> {
+        let mut pos = 0;
+        // header fields are separated by whitespace and `#` comments
+        let mut field = || {
+            loop {
+                match data.get(pos) {
+                    Some(b'#') => {
+                        while data.get(pos).is_some_and(|b| *b != b'\n') {
+                            pos += 1;
+                        }
+                    }
+                    Some(b) if b.is_ascii_whitespace() => pos += 1,
+                    _ => break,
+                }
+            }
+            let start = pos;
+            while data.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            std::str::from_utf8(&data[start..pos]).unwrap_or_default()
+        };
+        let magic = field().to_owned();
+        let mut size = || {
+            field()
+                .parse::<usize>()
+                .map_err(|_| error("invalid PBM size"))
+        };
+        let (width, height) = (size()?, size()?);
+        let truncated = || error("PBM data is truncated");
+        // sizes are checked against the data before allocating
+        let pixels = width.checked_mul(height).ok_or_else(truncated)?;
+        let mut bitmap;
+        match magic.as_str() {
+            "P4" => {
+                let stride = width.div_ceil(8);
+                let len = stride.checked_mul(height).ok_or_else(truncated)?;
+                let rows = data
+                    .get(pos + 1..)
+                    .and_then(|rest| rest.get(..len))
+                    .ok_or_else(truncated)?;
+                bitmap = Bitmap::new(width, height);
+                for y in 0..height {
+                    for x in 0..width {
+                        bitmap.set(x, y, rows[y * stride + x / 8] & (0x80 >> (x % 8)) != 0);
+                    }
+                }
+            }
+            "P1" => {
+                // every pixel takes at least one byte
+                if data.len() - pos < pixels {
+                    return Err(truncated());
+                }
+                bitmap = Bitmap::new(width, height);
+                let mut bits = data[pos..].iter().filter(|b| !b.is_ascii_whitespace());
+                for i in 0..pixels {
+                    let lit = match bits.next() {
+                        Some(b'0') => false,
+                        Some(b'1') => true,
+                        Some(_) => return Err(error("PBM pixels must be 0 or 1")),
+                        None => return Err(truncated()),
+                    };
+                    bitmap.pixels[i] = lit;
+                }
+            }
+            _ => return Err(error("not a PBM file")),
+        }
+        Ok(bitmap)
+    }
+}
+
+/// `count` sprites of `width` columns of `rows` bytes each, stored one after another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteSheet {
+    pub name: String,
+    pub address: u16,
+    pub width: usize,
+    pub rows: usize,
+    pub count: usize,
+}
+
+/// Sprites and character set of Space Invaders: name, address, width, rows, count
+const INVADERS: &[(&str, u16, usize, usize, usize)] = &[
+    ("aliens", 0x1C00, 16, 1, 6),
+    ("player", 0x1C60, 16, 1, 1),
+    ("player_explosion", 0x1C70, 16, 1, 2),
+    ("alien_explosion", 0x1CC0, 16, 1, 1),
+    ("shield", 0x1D20, 22, 2, 1),
+    ("saucer", 0x1D64, 24, 1, 1),
+    ("saucer_explosion", 0x1D7C, 24, 1, 1),
+    ("font", 0x1E00, 8, 1, 64),
+];
+
+impl SpriteSheet {
+    /// Known sprites of Space Invaders
+    pub fn space_invaders() -> Vec<SpriteSheet> {
+        INVADERS
+            .iter()
+            .map(|&(name, address, width, rows, count)| SpriteSheet {
+                name: name.to_owned(),
+                address,
+                width,
+                rows,
+                count,
+            })
+            .collect()
+    }
+
+    /// Size in bytes
+    pub fn len(&self) -> usize {
+        self.width * self.rows * self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Addresses of the sheet
+    pub fn range(&self) -> std::ops::Range<u32> {
+        self.address as u32..self.address as u32 + self.len() as u32
+    }
+
+    /// Sprites side by side in one bitmap
+    pub fn extract(&self, image: &MemoryImage) -> Bitmap {
+        let bytes = image.bytes_from(self.address);
+        Bitmap::from_columns(&bytes[..self.len().min(bytes.len())], self.rows)
+    }
+
+    /// Bytes of `bitmap`, which must have the size that `extract` gives
+    pub fn encode(&self, bitmap: &Bitmap) -> Result<Vec<u8>, BitmapError> {
+        let (width, height) = (self.width * self.count, self.rows * 8);
+        if (bitmap.width, bitmap.height) != (width, height) {
+            return Err(error(format!(
+                "{} needs {}x{} pixels, the image has {}x{}",
+                self.name, width, height, bitmap.width, bitmap.height
+            )));
+        }
+        Ok(bitmap.to_columns())
+    }
+}
+
+/// Fewest columns of a sprite found by [scan](fn.scan.html)
+pub const MIN_SPRITE_WIDTH: usize = 4;
+
+/// Neighbouring columns of a shape touch each other
+fn touches(a: u8, b: u8) -> bool {
+    a & (b | b << 1 | b >> 1) != 0
+}
+
+/// Whether columns of one sprite row look like a drawn shape: lit columns
+/// touch their neighbours, not too dense or sparse and not one repeated value
+fn is_shape(columns: &[u8]) -> bool {
+    let lit: u32 = columns.iter().map(|c| c.count_ones()).sum();
+    let density = lit as f32 / (columns.len() * 8) as f32;
+    columns.len() >= MIN_SPRITE_WIDTH
+        && (0.15..=0.75).contains(&density)
+        && columns.windows(2).all(|pair| touches(pair[0], pair[1]))
+        && columns.iter().any(|c| *c != columns[0])
+}
+
+/// Heuristic search for one-row sprites in bytes that `is_code` rejects:
+/// runs of non-zero columns between zero columns that look like a drawn
+/// shape, named `sprite_XXXX` by their address
+pub fn scan(image: &MemoryImage, is_code: impl Fn(u16) -> bool) -> Vec<SpriteSheet> {
+    let mut found = Vec::new();
+    for region in image.regions() {
+        let bytes = image.bytes_from(region.start as u16);
+        let mut i = 0;
+        while i < bytes.len() {
+            let start = i;
+            while i < bytes.len() && bytes[i] != 0 && !is_code((region.start as usize + i) as u16) {
+                i += 1;
+            }
+            let columns = &bytes[start..i];
+            if is_shape(columns) {
+                let address = (region.start as usize + start) as u16;
+                found.push(SpriteSheet {
+                    name: format!("sprite_{:04X}", address),
+                    address,
+                    width: columns.len(),
+                    rows: 1,
+                    count: 1,
+                });
+            }
+            i = i.max(start + 1);
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sprite_bitmaps() {
+        // the player ship of Space Invaders
+        let ship = [
+            0x00, 0x00, 0x0F, 0x1F, 0x1F, 0x1F, 0x1F, 0x7F, 0xFF, 0x7F, 0x1F, 0x1F, 0x1F, 0x1F,
+            0x0F, 0x00,
+        ];
+        let bitmap = Bitmap::from_columns(&ship, 1);
+        assert_eq!((16, 8), (bitmap.width, bitmap.height));
+        assert!(bitmap.get(8, 0) && !bitmap.get(7, 0) && bitmap.get(2, 7) && !bitmap.get(2, 3));
+        assert_eq!(ship.to_vec(), bitmap.to_columns());
+        // two bytes per column, the first at the bottom
+        let tall = Bitmap::from_columns(&[0x01, 0x80, 0xFF, 0x00], 2);
+        assert_eq!((2, 16), (tall.width, tall.height));
+        assert!(tall.get(0, 15) && tall.get(0, 0) && !tall.get(0, 7) && tall.get(1, 8));
+        assert_eq!(vec![0x01, 0x80, 0xFF, 0x00], tall.to_columns());
+
+        for bitmap in [&bitmap, &tall] {
+            assert_eq!(bitmap, &Bitmap::parse_pbm(&bitmap.to_pbm()).unwrap());
+        }
+        let plain = Bitmap::parse_pbm(b"P1\n# comment\n3 2\n1 0 1\n010").unwrap();
+        assert!(plain.get(0, 0) && !plain.get(1, 0) && plain.get(1, 1));
+        assert!(Bitmap::parse_pbm(b"P4\n8 2\n\x01").is_err());
+        assert!(Bitmap::parse_pbm(b"P2\n1 1\n1").is_err());
+        // sizes of the header that do not fit the data
+        assert!(Bitmap::parse_pbm(b"P4\n4294967296 4294967296\n").is_err());
+        assert!(Bitmap::parse_pbm(b"P4\n18446744073709551615 2\n\x01").is_err());
+        assert!(Bitmap::parse_pbm(b"P1\n100000 100000\n1").is_err());
+
+        let mut image = MemoryImage::default();
+        let mut rom = vec![0; 0x2000];
+        rom[0x1C60..0x1C70].copy_from_slice(&ship);
+        // a run of one value and a pointer-like pair are not shapes
+        rom[0x100..0x108].copy_from_slice(&[0x20; 8]);
+        rom[0x200..0x202].copy_from_slice(&[0x00, 0x20]);
+        image.load(0, &rom).unwrap();
+        let sheets = SpriteSheet::space_invaders();
+        let player = sheets.iter().find(|s| s.name == "player").unwrap();
+        assert_eq!(bitmap, player.extract(&image));
+        assert_eq!(ship.to_vec(), player.encode(&bitmap).unwrap());
+        assert!(player.encode(&tall).is_err());
+        let font = sheets.iter().find(|s| s.name == "font").unwrap();
+        assert_eq!(0x1E00..0x2000, font.range());
+
+        let found = scan(&image, |_| false);
+        assert_eq!(1, found.len());
+        assert_eq!(
+            ("sprite_1C62", 0x1C62, 13),
+            (found[0].name.as_str(), found[0].address, found[0].width)
+        );
+        assert!(scan(&image, |a| (0x1C60..0x1C70).contains(&a)).is_empty());
+    }
+}
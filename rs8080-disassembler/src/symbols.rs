@@ -0,0 +1,372 @@
+use crate::MemoryImage;
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+use core::ops::Range;
+
+/// Parses a number: decimal, or hex with `0x`, `$` prefix or `H` suffix
+pub fn parse_number(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let (digits, radix) = if let Some(hex) = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .or_else(|| text.strip_prefix('$'))
+    {
+        (hex, 16)
+    } else if let Some(hex) = text.strip_suffix(['h', 'H']) {
+        (hex, 16)
+    } else {
+        (text, 10)
+    };
+    if digits.is_empty() || digits.starts_with(['+', '-']) {
+        return None;
+    }
+    u32::from_str_radix(digits, radix).ok()
+}
+
+/// Error in a symbol, port map or signature file, `line` is 1-based
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for SymbolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SymbolError {}
+
+/// How a data region is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataKind {
+    Bytes,
+    /// Little-endian 16-bit values
+    Words,
+    /// Text, bytes that are not printable ASCII are shown as numbers
+    String,
+    /// Space Invaders bitmap, each byte is a column of 8 pixels with bit 0 at the bottom
+    Sprite,
+}
+
+impl DataKind {
+    /// Kind named as in symbol files, `bytes`, `words`, `string` or `sprite`
+    pub fn parse(word: &str) -> Option<DataKind> {
+        match word.to_ascii_lowercase().as_str() {
+            "byte" | "bytes" => Some(DataKind::Bytes),
+            "word" | "words" => Some(DataKind::Words),
+            "string" | "text" => Some(DataKind::String),
+            "sprite" | "sprites" => Some(DataKind::Sprite),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataKind::Bytes => "bytes",
+            DataKind::Words => "words",
+            DataKind::String => "string",
+            DataKind::Sprite => "sprite",
+        }
+    }
+}
+
+/// Bytes that are known to be data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataRegion {
+    pub start: u16,
+    /// Address after the last byte, `None` for one byte, one word or a
+    /// string that ends before the first byte that is not printable ASCII
+    pub end: Option<u32>,
+    pub kind: DataKind,
+}
+
+impl DataRegion {
+    /// Addresses covered by the region in `image`
+    pub fn range(&self, image: &MemoryImage) -> Range<u32> {
+        let start = self.start as u32;
+        let end = match (self.end, self.kind) {
+            (Some(end), _) => end,
+            (None, DataKind::Bytes | DataKind::Sprite) => start + 1,
+            (None, DataKind::Words) => start + 2,
+            (None, DataKind::String) => {
+                let text = image
+                    .bytes_from(self.start)
+                    .iter()
+                    .take_while(|b| b.is_ascii_graphic() || **b == b' ')
+                    .count();
+                start + text.max(1) as u32
+            }
+        };
+        start..end.min(0x1_0000)
+    }
+}
+
+/// Names start with a letter or `_` followed by letters, digits or `_`
+pub fn is_name(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && word.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn address(word: &str) -> Option<u16> {
+    parse_number(word).and_then(|a| u16::try_from(a).ok())
+}
+
+/// Names, comments and data regions of a program, one name and one comment per address
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SymbolTable {
+    names: BTreeMap<u16, String>,
+    comments: BTreeMap<u16, String>,
+    data: Vec<DataRegion>,
+}
+
+impl SymbolTable {
+    /// Parses a symbol file, each line is one of
+    /// - `name address`, `name = address`, `name EQU address` or `address = name`
+    /// - `start..end bytes|words|string` with inclusive `end`
+    /// - `address` or any of the above, followed by `bytes|words|string` for one value
+    ///   or a string that starts at the address
+    /// - `address` alone to comment it
+    ///
+    /// Text after `;` is a comment of the line's address, `#` starts a comment that is ignored
+    pub fn parse(text: &str) -> Result<SymbolTable, SymbolError> {
+        let mut table = SymbolTable::default();
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| SymbolError {
+                line: i + 1,
+                message,
+            };
+            let line = line.split('#').next().unwrap_or_default();
+            let (line, comment) = match line.split_once(';') {
+                Some((line, comment)) => (line, Some(comment.trim())),
+                None => (line, None),
+            };
+            let mut words: Vec<&str> = line
+                .split(|c: char| c.is_whitespace() || c == '=')
+                .filter(|w| !w.is_empty())
+                .collect();
+            let kind = words.last().and_then(|w| DataKind::parse(w));
+            if kind.is_some() {
+                words.pop();
+            }
+            let (address, end) = match words.as_slice() {
+                [] if kind.is_none() => continue,
+                [range] if kind.is_some() && range.contains("..") => {
+                    let invalid = || error(format!("invalid range '{}'", range));
+                    let (start, end) = range.split_once("..").unwrap_or_default();
+                    let start = address(start).ok_or_else(invalid)?;
+                    let end = address(end)
+                        .filter(|end| *end >= start)
+                        .ok_or_else(invalid)?;
+                    (start, Some(end as u32 + 1))
+                }
+                [word] => {
+                    let address = address(word)
+                        .ok_or_else(|| error(format!("invalid address '{}'", word)))?;
+                    (address, None)
+                }
+                [first, second] | [first, _, second] => {
+                    if words.len() == 3 && !words[1].eq_ignore_ascii_case("EQU") {
+                        return Err(error(format!("unexpected '{}'", words[1])));
+                    }
+                    let first = first.trim_end_matches(':');
+                    let (name, address) = match (address(second), address(first)) {
+                        (Some(address), _) if is_name(first) => (first, address),
+                        (None, Some(address)) if is_name(second) => (*second, address),
+                        _ if !is_name(first) && !is_name(second) => {
+                            return Err(error(format!("invalid name '{}'", first)))
+                        }
+                        _ => {
+                            let address = if is_name(first) { second } else { first };
+                            return Err(error(format!("invalid address '{}'", address)));
+                        }
+                    };
+                    table.insert(address, name);
+                    (address, None)
+                }
+                _ => {
+                    return Err(error(format!(
+                        "expected `name address`, got '{}'",
+                        line.trim()
+                    )))
+                }
+            };
+            if let Some(kind) = kind {
+                table.data.push(DataRegion {
+                    start: address,
+                    end,
+                    kind,
+                });
+            }
+            if let Some(comment) = comment.filter(|c| !c.is_empty()) {
+                table.comments.insert(address, comment.to_owned());
+            }
+        }
+        Ok(table)
+    }
+
+    /// Reads label lists as published on computerarcheology-style pages:
+    /// lines of a hex address without prefix and a name, e.g. `1A5C: DrawSprite`,
+    /// other lines are skipped
+    pub fn import_plain(text: &str) -> SymbolTable {
+        let mut table = SymbolTable::default();
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            let (Some(address), Some(name)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let address = address.trim_end_matches(':');
+            let name = name.trim_end_matches(':');
+            if address.len() > 4 || !is_name(name) {
+                continue;
+            }
+            if let Ok(address) = u16::from_str_radix(address, 16) {
+                table.insert(address, name);
+            }
+        }
+        table
+    }
+
+    /// Adds names, comments and data regions of `other`, its names and comments win
+    pub fn extend(&mut self, other: &SymbolTable) {
+        self.names.extend(other.names.clone());
+        self.comments.extend(other.comments.clone());
+        self.data.extend(other.data.iter().cloned());
+    }
+
+    /// Names `address`, replaces the previous name
+    pub fn insert(&mut self, address: u16, name: &str) {
+        self.names.insert(address, name.to_owned());
+    }
+
+    /// Comments `address`, replaces the previous comment
+    pub fn insert_comment(&mut self, address: u16, comment: &str) {
+        self.comments.insert(address, comment.to_owned());
+    }
+
+    /// Marks bytes as data, the first region that covers an address wins
+    pub fn insert_data(&mut self, region: DataRegion) {
+        self.data.push(region);
+    }
+
+    pub fn get(&self, address: u16) -> Option<&str> {
+        self.names.get(&address).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.names.iter().map(|(a, name)| (*a, name.as_str()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn comment(&self, address: u16) -> Option<&str> {
+        self.comments.get(&address).map(String::as_str)
+    }
+
+    pub fn data(&self) -> &[DataRegion] {
+        &self.data
+    }
+
+    /// Data region that covers `address` in `image`
+    pub fn data_at(&self, image: &MemoryImage, address: u16) -> Option<&DataRegion> {
+        self.data
+            .iter()
+            .find(|region| region.range(image).contains(&(address as u32)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbols_parse() {
+        let table = SymbolTable::parse(
+            "# Space Invaders\nreset 0\nISR_1: = $0008\n\nDrawSprite EQU 15D3H # draws\nscore 0x20F8\n\
+             $1A5C = ClearScreen ; clears the screen\n0x1A00..0x1A3F bytes\n1B00H string ; message\n\
+             $0100 ; comment only\nflags $20F0 bytes\n",
+        )
+        .unwrap();
+        assert_eq!(Some("ISR_1"), table.get(8));
+        assert_eq!(Some("DrawSprite"), table.get(0x15D3));
+        assert_eq!(Some("score"), table.get(0x20F8));
+        assert_eq!(Some("ClearScreen"), table.get(0x1A5C));
+        assert_eq!(6, table.iter().count());
+        assert_eq!(Some("clears the screen"), table.comment(0x1A5C));
+        assert_eq!(Some("comment only"), table.comment(0x100));
+        assert_eq!(Some("message"), table.comment(0x1B00));
+        assert_eq!(
+            &[
+                DataRegion {
+                    start: 0x1A00,
+                    end: Some(0x1A40),
+                    kind: DataKind::Bytes
+                },
+                DataRegion {
+                    start: 0x1B00,
+                    end: None,
+                    kind: DataKind::String
+                },
+                DataRegion {
+                    start: 0x20F0,
+                    end: None,
+                    kind: DataKind::Bytes
+                }
+            ],
+            table.data()
+        );
+
+        let sprite = SymbolTable::parse("$1C00..$1C10 sprite").unwrap();
+        assert_eq!(DataKind::Sprite, sprite.data()[0].kind);
+
+        let err = SymbolTable::parse("a 1\nb 0x10000").unwrap_err();
+        assert_eq!("line 2: invalid address '0x10000'", err.to_string());
+        assert_eq!(1, SymbolTable::parse("1a 1").unwrap_err().line);
+        assert_eq!(1, SymbolTable::parse("a b c").unwrap_err().line);
+        assert_eq!(1, SymbolTable::parse("$20..$10 bytes").unwrap_err().line);
+        assert_eq!(1, SymbolTable::parse("$10..$20").unwrap_err().line);
+        assert_eq!(1, SymbolTable::parse("words").unwrap_err().line);
+    }
+
+    #[test]
+    fn symbols_data_regions() {
+        let mut image = MemoryImage::default();
+        image.load(0x10, b"SCORE<1>\x1C\x26").unwrap();
+        let table = SymbolTable::parse("$10 string\n$18 word").unwrap();
+        assert_eq!(0x10..0x18, table.data()[0].range(&image));
+        assert_eq!(
+            0x18..0x1A,
+            table.data_at(&image, 0x19).unwrap().range(&image)
+        );
+        assert!(table.data_at(&image, 0x1A).is_none());
+    }
+
+    #[test]
+    fn symbols_import_plain() {
+        let text = "Labels\n0000: Reset\n0008 ScanLine96 ; interrupt\n0010: 00 NOP\n12345 Bad\n";
+        let table = SymbolTable::import_plain(text);
+        assert_eq!(
+            vec![(0, "Reset"), (8, "ScanLine96")],
+            table.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn symbols_parse_number() {
+        assert_eq!(Some(0x1A5C), parse_number("$1A5C"));
+        assert_eq!(Some(0x1A5C), parse_number("0x1a5c"));
+        assert_eq!(Some(0x1A5C), parse_number("1A5CH"));
+        assert_eq!(Some(100), parse_number("100"));
+        assert_eq!(None, parse_number("$"));
+        assert_eq!(None, parse_number("-1"));
+        assert_eq!(None, parse_number("12x"));
+    }
+}
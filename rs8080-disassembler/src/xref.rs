@@ -0,0 +1,127 @@
+use crate::{Argument, Branch, Cmd, Command, Location};
+use std::collections::BTreeMap;
+
+/// How an instruction refers to an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RefKind {
+    /// `JMP` and conditional jumps
+    Jump,
+    /// `CALL` and conditional calls
+    Call,
+    Rst,
+    /// `LDA`, `STA`, `LHLD`, `SHLD` or `LXI`
+    Data,
+}
+
+impl RefKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RefKind::Jump => "jump",
+            RefKind::Call => "call",
+            RefKind::Rst => "rst",
+            RefKind::Data => "data",
+        }
+    }
+}
+
+/// Instruction at `from` that refers to an address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub from: u16,
+    pub kind: RefKind,
+    pub cmd: Cmd,
+}
+
+/// Address `cmd` refers to and how
+pub fn reference(cmd: &Command) -> Option<(u16, RefKind)> {
+    let info = cmd.info();
+    let kind = match info.branch {
+        Some(Branch::Jump) => RefKind::Jump,
+        Some(Branch::Call) => RefKind::Call,
+        Some(Branch::Rst) => RefKind::Rst,
+        Some(Branch::Return) => return None,
+        None => {
+            let access = info.memory_read.or(info.memory_written);
+            return match (access.map(|a| a.location), cmd.cmd, cmd.args.as_slice()) {
+                (Some(Location::Address(address)), ..) => Some((address, RefKind::Data)),
+                (_, Cmd::LXI, [_, Argument::Addr(lo, hi) | Argument::D16(lo, hi)]) => {
+                    Some((u16::from_le_bytes([*lo, *hi]), RefKind::Data))
+                }
+                _ => None,
+            };
+        }
+    };
+    info.target.map(|target| (target, kind))
+}
+
+/// Instructions that jump, call or `RST` to an address or use it as data
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Xrefs {
+    refs: BTreeMap<u16, Vec<Reference>>,
+}
+
+impl Xrefs {
+    /// Collects references of `(address, instruction)` pairs
+    pub fn new<'a>(instructions: impl IntoIterator<Item = (u16, &'a Command)>) -> Xrefs {
+        let mut xrefs = Xrefs::default();
+        for (from, cmd) in instructions {
+            if let Some((target, kind)) = reference(cmd) {
+                xrefs.refs.entry(target).or_default().push(Reference {
+                    from,
+                    kind,
+                    cmd: cmd.cmd,
+                });
+            }
+        }
+        for refs in xrefs.refs.values_mut() {
+            refs.sort_by_key(|r| r.from);
+        }
+        xrefs
+    }
+
+    /// References to `address` ordered by the referring address
+    pub fn to(&self, address: u16) -> &[Reference] {
+        self.refs
+            .get(&address)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Referenced addresses in ascending order with their references
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &[Reference])> {
+        self.refs.iter().map(|(a, refs)| (*a, refs.as_slice()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassemble;
+
+    #[test]
+    fn xrefs_collect() {
+        let program = [
+            (0x100, disassemble(&[0xCD, 0x00, 0x02])), // CALL $0200
+            (0x103, disassemble(&[0x32, 0xF8, 0x20])), // STA $20F8
+            (0x106, disassemble(&[0x21, 0xF8, 0x20])), // LXI H, $20F8
+            (0x109, disassemble(&[0xC2, 0x00, 0x02])), // JNZ $0200
+            (0x10C, disassemble(&[0xCF])),             // RST 1
+            (0x10D, disassemble(&[0x3E, 0x08])),       // MVI A, $08
+        ];
+        let xrefs = Xrefs::new(program.iter().map(|(a, cmd)| (*a, cmd)));
+        let to = |address| -> Vec<(u16, RefKind)> {
+            xrefs.to(address).iter().map(|r| (r.from, r.kind)).collect()
+        };
+        assert_eq!(
+            vec![(0x100, RefKind::Call), (0x109, RefKind::Jump)],
+            to(0x200)
+        );
+        assert_eq!(
+            vec![(0x103, RefKind::Data), (0x106, RefKind::Data)],
+            to(0x20F8)
+        );
+        assert_eq!(vec![(0x10C, RefKind::Rst)], to(0x8));
+        assert_eq!(3, xrefs.iter().count());
+        assert_eq!(Cmd::STA, xrefs.to(0x20F8)[0].cmd);
+    }
+}
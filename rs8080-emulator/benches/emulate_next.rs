@@ -1,4 +1,4 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 extern crate rs8080_emulator;
 use rs8080_emulator::{RS8080, DataBus};
 
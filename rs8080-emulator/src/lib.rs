@@ -1,16 +1,29 @@
-//extern crate rs8080_disassembler as disasm;
+//! Intel 8080 emulator core.
+//!
+//! Without the default `std` feature the crate only needs `core`,
+//! `alloc` feature adds [Machine](trait.Machine.html), saving of state and
+//! decoding into the disassembler's `Command`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+pub mod loader;
 mod structs;
 mod traits;
 
 pub use crate::traits::DataBus;
-pub use structs::RS8080;
+pub use structs::rs8080::AllowAll;
+pub use structs::{Registers, StateError, RS8080};
+#[cfg(feature = "alloc")]
+pub use traits::{AudioEvent, Framebuffer, Machine};
 pub use traits::{MemLimiter, WriteAction};
 
 extern crate derive_more;
 use derive_more::{Add, Display, From};
 
 #[derive(Add, Display, From)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClockCycles(pub u32);
 
 impl ClockCycles {
@@ -0,0 +1,188 @@
+use super::{intel_hex, srec, LoadError};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Bytes placed at consecutive addresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u16,
+    pub data: Vec<u8>,
+}
+
+impl Segment {
+    /// Address after the last byte
+    pub fn end(&self) -> u32 {
+        self.address as u32 + self.data.len() as u32
+    }
+}
+
+/// Program image: segments that never overlap and an optional entry address
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub segments: Vec<Segment>,
+    pub entry: Option<u16>,
+}
+
+impl Image {
+    /// Parses Intel HEX records, data, extended address and start address records are supported
+    pub fn from_intel_hex(text: &str) -> Result<Image, LoadError> {
+        intel_hex::parse(text)
+    }
+
+    /// Parses Motorola S-records (S19, S28 and S37)
+    pub fn from_srec(text: &str) -> Result<Image, LoadError> {
+        srec::parse(text)
+    }
+
+    /// Raw binary placed at `origin`
+    pub fn raw(bytes: &[u8], origin: u16) -> Result<Image, LoadError> {
+        let mut image = Image::default();
+        image.add(origin as u32, bytes)?;
+        Ok(image)
+    }
+
+    /// CP/M `.COM` program, loaded and started at 0x100
+    pub fn com(bytes: &[u8]) -> Result<Image, LoadError> {
+        let mut image = Image::raw(bytes, 0x100)?;
+        image.entry = Some(0x100);
+        Ok(image)
+    }
+
+    /// Copies `ranges` of `mem`, e.g. to write them with [to_intel_hex](#method.to_intel_hex)
+    pub fn from_memory(mem: &[u8], ranges: &[Range<usize>]) -> Result<Image, LoadError> {
+        let mut image = Image::default();
+        for range in ranges {
+            if range.end > mem.len() {
+                return Err(LoadError::Overflow {
+                    address: mem.len().max(range.start) as u32,
+                });
+            }
+            image.add(range.start as u32, &mem[range.clone()])?;
+        }
+        Ok(image)
+    }
+
+    /// Writes data records of 16 bytes, a start linear address record
+    /// if there is an entry address, and the end of file record
+    pub fn to_intel_hex(&self) -> String {
+        intel_hex::write(self)
+    }
+
+    /// Adds `data` at `address`, adjacent data is merged into one segment
+    pub(crate) fn add(&mut self, address: u32, data: &[u8]) -> Result<(), LoadError> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let end = match address.checked_add(data.len() as u32) {
+            Some(end) if end <= 0x1_0000 => end,
+            _ => {
+                return Err(LoadError::Overflow {
+                    address: address.max(0x1_0000),
+                })
+            }
+        };
+        if let Some(seg) = self
+            .segments
+            .iter()
+            .find(|seg| address < seg.end() && (seg.address as u32) < end)
+        {
+            return Err(LoadError::Overlap {
+                address: address.max(seg.address as u32) as u16,
+            });
+        }
+        match self.segments.last_mut() {
+            Some(last) if last.end() == address => last.data.extend_from_slice(data),
+            _ => self.segments.push(Segment {
+                address: address as u16,
+                data: data.to_vec(),
+            }),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_entry(&mut self, entry: u32) -> Result<(), LoadError> {
+        let entry = u16::try_from(entry).map_err(|_| LoadError::Overflow { address: entry })?;
+        self.entry = Some(entry);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DataBus, RS8080};
+
+    struct DummyIO {}
+    impl DataBus for DummyIO {
+        fn port_in(&mut self, _: u8) -> u8 {
+            0
+        }
+        fn port_out(&mut self, _: u8, _: u8) {}
+        fn port(&mut self, _: usize) -> &mut u8 {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn image_load_to_cpu() {
+        let mut emu = RS8080::new(DummyIO {});
+        emu.load_image(&Image::com(&[0x3E, 0x42]).unwrap()).unwrap();
+        assert_eq!(0x100, emu.get_pc());
+        assert_eq!(&[0x3E, 0x42], &emu.get_mem()[0x100..0x102]);
+        emu.emulate_next();
+        assert_eq!(0x42, emu.registers().a);
+
+        // memory ends at 0xFFFE
+        let image = Image::raw(&[1, 2], 0xFFFE).unwrap();
+        assert_eq!(
+            Err(LoadError::Overflow { address: 0xFFFF }),
+            emu.load_image(&image)
+        );
+        assert_eq!(
+            Err(LoadError::Overflow { address: 0xFFFF }),
+            emu.try_load_to_mem(&[1, 2], 0xFFFE)
+        );
+        assert_eq!(0, emu.get_mem()[0xFFFE]);
+    }
+
+    #[test]
+    fn image_raw_and_com() {
+        let image = Image::com(&[1, 2, 3]).unwrap();
+        assert_eq!(Some(0x100), image.entry);
+        assert_eq!(0x100, image.segments[0].address);
+        assert_eq!(
+            Err(LoadError::Overflow { address: 0x1_0000 }),
+            Image::raw(&[0; 4], 0xFFFE)
+        );
+        assert!(Image::raw(&[0; 2], 0xFFFE).is_ok());
+    }
+
+    #[test]
+    fn image_segments() {
+        let mut image = Image::default();
+        image.add(0x10, &[1, 2]).unwrap();
+        image.add(0x12, &[3]).unwrap();
+        image.add(0x08, &[0; 8]).unwrap();
+        assert_eq!(2, image.segments.len());
+        assert_eq!(vec![1, 2, 3], image.segments[0].data);
+        assert_eq!(
+            Err(LoadError::Overlap { address: 0x12 }),
+            image.add(0x12, &[0])
+        );
+        assert_eq!(
+            Err(LoadError::Overlap { address: 0x10 }),
+            image.add(0x0F, &[0, 0])
+        );
+    }
+
+    #[test]
+    fn image_from_memory() {
+        let mem: Vec<u8> = (0..=0xFF).collect();
+        let image = Image::from_memory(&mem, &[0x10..0x12, 0x20..0x21]).unwrap();
+        assert_eq!(vec![0x10, 0x11], image.segments[0].data);
+        assert_eq!(0x20, image.segments[1].address);
+        assert!(Image::from_memory(&mem, &[0..4, 0xF0..0x101]).is_err());
+        assert!(Image::from_memory(&mem, &[0..4, 2..3]).is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use super::{decode_hex, Image, LoadError};
+use alloc::string::String;
+use core::fmt::Write;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Bytes in one data record written by [write](fn.write.html)
+const RECORD_DATA_LEN: usize = 16;
+
+pub(crate) fn parse(text: &str) -> Result<Image, LoadError> {
+    let mut image = Image::default();
+    // set by extended address records
+    let mut base: u32 = 0;
+    for (i, record) in text.lines().enumerate() {
+        let line = i + 1;
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let bytes = record
+            .strip_prefix(':')
+            .and_then(decode_hex)
+            .filter(|b| b.len() >= 5 && b.len() == b[0] as usize + 5)
+            .ok_or(LoadError::Syntax { line })?;
+        if bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+            return Err(LoadError::Checksum { line });
+        }
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        let syntax = LoadError::Syntax { line };
+        match bytes[3] {
+            DATA => image.add(base + offset, data)?,
+            END_OF_FILE => break,
+            EXTENDED_SEGMENT_ADDRESS => base = be_u16(data).ok_or(syntax)? << 4,
+            EXTENDED_LINEAR_ADDRESS => base = be_u16(data).ok_or(syntax)? << 16,
+            START_SEGMENT_ADDRESS => {
+                let [cs_hi, cs_lo, ip_hi, ip_lo]: [u8; 4] = data.try_into().map_err(|_| syntax)?;
+                let cs = u16::from_be_bytes([cs_hi, cs_lo]) as u32;
+                let ip = u16::from_be_bytes([ip_hi, ip_lo]) as u32;
+                image.set_entry((cs << 4) + ip)?;
+            }
+            START_LINEAR_ADDRESS => {
+                let entry: [u8; 4] = data.try_into().map_err(|_| syntax)?;
+                image.set_entry(u32::from_be_bytes(entry))?;
+            }
+            _ => return Err(LoadError::UnsupportedRecord { line }),
+        }
+    }
+    Ok(image)
+}
+
+fn be_u16(data: &[u8]) -> Option<u32> {
+    match *data {
+        [hi, lo] => Some(u16::from_be_bytes([hi, lo]) as u32),
+        _ => None,
+    }
+}
+
+fn write_record(out: &mut String, address: u16, kind: u8, data: &[u8]) {
+    let [hi, lo] = address.to_be_bytes();
+    let header = [data.len() as u8, hi, lo, kind];
+    let sum = header
+        .iter()
+        .chain(data)
+        .fold(0u8, |sum, b| sum.wrapping_add(*b));
+    out.push(':');
+    for byte in header.iter().chain(data) {
+        let _ = write!(out, "{:02X}", byte);
+    }
+    let _ = writeln!(out, "{:02X}", sum.wrapping_neg());
+}
+
+pub(crate) fn write(image: &Image) -> String {
+    let mut out = String::new();
+    for seg in &image.segments {
+        for (i, chunk) in seg.data.chunks(RECORD_DATA_LEN).enumerate() {
+            let address = seg.address.wrapping_add((i * RECORD_DATA_LEN) as u16);
+            write_record(&mut out, address, DATA, chunk);
+        }
+    }
+    if let Some(entry) = image.entry {
+        write_record(
+            &mut out,
+            0,
+            START_LINEAR_ADDRESS,
+            &(entry as u32).to_be_bytes(),
+        );
+    }
+    write_record(&mut out, 0, END_OF_FILE, &[]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Segment;
+
+    #[test]
+    fn intel_hex_parse() {
+        let text = "\
+:0300300002337A1E
+:02000002010DEE
+:01000000FF00
+
+:0400000300000100F8
+:00000001FF
+:01000000AA55
+";
+        let image = parse(text).unwrap();
+        assert_eq!(
+            vec![
+                Segment {
+                    address: 0x30,
+                    data: vec![0x02, 0x33, 0x7A]
+                },
+                Segment {
+                    address: 0x10D0,
+                    data: vec![0xFF]
+                },
+            ],
+            image.segments
+        );
+        assert_eq!(Some(0x100), image.entry);
+    }
+
+    #[test]
+    fn intel_hex_errors() {
+        assert_eq!(
+            Err(LoadError::Checksum { line: 1 }),
+            parse(":0300300002337A1F")
+        );
+        assert_eq!(
+            Err(LoadError::Syntax { line: 2 }),
+            parse("\n0300300002337A1E")
+        );
+        assert_eq!(Err(LoadError::Syntax { line: 1 }), parse(":+1000000FF00"));
+        assert_eq!(
+            Err(LoadError::Syntax { line: 1 }),
+            parse(":0400300002337A1D")
+        );
+        assert_eq!(
+            Err(LoadError::UnsupportedRecord { line: 1 }),
+            parse(":00000006FA")
+        );
+        assert_eq!(
+            Err(LoadError::Overflow { address: 0x1_0000 }),
+            parse(":020000040001F9\n:01000000FF00")
+        );
+        assert_eq!(
+            Err(LoadError::Overflow {
+                address: 0xFFFF_FFFF
+            }),
+            parse(":02000004FFFFFC\n:01FFFF000001")
+        );
+        assert_eq!(
+            Err(LoadError::Overlap { address: 0x31 }),
+            parse(":0300300002337A1E\n:01003100FFCF")
+        );
+    }
+
+    #[test]
+    fn intel_hex_roundtrip() {
+        let mut image = Image::raw(&(0..40).collect::<Vec<u8>>(), 0xFF00).unwrap();
+        image.add(0x10, &[0xC3, 0x00, 0x01]).unwrap();
+        image.entry = Some(0x1234);
+        let text = write(&image);
+        assert!(text.starts_with(":10FF0000000102030405060708090A0B0C0D0E0F"));
+        assert!(text.ends_with(":00000001FF\n"));
+        assert_eq!(image, parse(&text).unwrap());
+    }
+}
@@ -0,0 +1,67 @@
+//! Loading of program images: Intel HEX, Motorola S-records, raw binaries and CP/M `.COM` files,
+//! and IPS/BPS patching.
+use core::fmt::{self, Display, Formatter};
+
+#[cfg(feature = "alloc")]
+mod image;
+#[cfg(feature = "alloc")]
+mod intel_hex;
+#[cfg(feature = "alloc")]
+mod patch;
+#[cfg(feature = "alloc")]
+mod srec;
+
+#[cfg(feature = "alloc")]
+pub use image::{Image, Segment};
+#[cfg(feature = "alloc")]
+pub use patch::{Patch, PatchError};
+
+/// Error returned when an image cannot be parsed or does not fit in memory
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// Malformed record on the line (1-based)
+    Syntax { line: usize },
+    /// Record checksum does not match
+    Checksum { line: usize },
+    /// Record type is not supported
+    UnsupportedRecord { line: usize },
+    /// Data or entry address does not fit in memory, `address` is the first one outside
+    Overflow { address: u32 },
+    /// Byte at the address is written twice
+    Overlap { address: u16 },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Syntax { line } => write!(f, "line {}: malformed record", line),
+            LoadError::Checksum { line } => write!(f, "line {}: bad checksum", line),
+            LoadError::UnsupportedRecord { line } => {
+                write!(f, "line {}: unsupported record type", line)
+            }
+            LoadError::Overflow { address } => {
+                write!(f, "address {:#06X} is out of memory", address)
+            }
+            LoadError::Overlap { address } => {
+                write!(f, "address {:#06X} is loaded twice", address)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LoadError {}
+
+/// Decodes pairs of hex digits
+#[cfg(feature = "alloc")]
+fn decode_hex(hex: &str) -> Option<alloc::vec::Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    // only hex digits, `from_str_radix` would take "+1"
+    let digit = |d: u8| (d as char).to_digit(16);
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| Some((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect()
+}
@@ -0,0 +1,364 @@
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Source, target and patch CRC32 at the end of a BPS patch
+const BPS_FOOTER_LEN: usize = 12;
+/// Largest BPS target, far more than the 8080 can address
+const BPS_MAX_TARGET_LEN: usize = 1 << 24;
+
+/// Error returned when a patch is malformed or does not fit the data
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// Neither IPS nor BPS header
+    UnknownFormat,
+    /// Patch ends in the middle of a record, `offset` is in the patch
+    Truncated { offset: usize },
+    /// Record reads outside of the source or target, `offset` is in the patch
+    OutOfBounds { offset: usize },
+    /// Source size or CRC32 differs from the one the BPS patch was made for
+    SourceMismatch,
+    /// Patched data CRC32 differs from the one stored in the BPS patch
+    TargetMismatch,
+    /// BPS patch itself is damaged
+    PatchChecksum,
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "not an IPS or BPS patch"),
+            PatchError::Truncated { offset } => {
+                write!(f, "patch is truncated at offset {:#X}", offset)
+            }
+            PatchError::OutOfBounds { offset } => {
+                write!(f, "record at offset {:#X} is out of bounds", offset)
+            }
+            PatchError::SourceMismatch => write!(f, "patch is made for different data"),
+            PatchError::TargetMismatch => write!(f, "patched data has wrong checksum"),
+            PatchError::PatchChecksum => write!(f, "patch has wrong checksum"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PatchError {}
+
+/// IPS or BPS patch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Patch {
+    Ips(Vec<u8>),
+    Bps(Vec<u8>),
+}
+
+impl Patch {
+    /// Detects the format by its header
+    pub fn parse(bytes: &[u8]) -> Result<Patch, PatchError> {
+        if bytes.starts_with(IPS_MAGIC) {
+            Ok(Patch::Ips(bytes.to_vec()))
+        } else if bytes.starts_with(BPS_MAGIC) {
+            Ok(Patch::Bps(bytes.to_vec()))
+        } else {
+            Err(PatchError::UnknownFormat)
+        }
+    }
+
+    /// Returns patched copy of `source`
+    pub fn apply(&self, source: &[u8]) -> Result<Vec<u8>, PatchError> {
+        match self {
+            Patch::Ips(patch) => apply_ips(patch, source),
+            Patch::Bps(patch) => apply_bps(patch, source),
+        }
+    }
+}
+
+/// Reads patch bytes and keeps the offset for error messages
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self
+            .offset
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .ok_or(PatchError::Truncated {
+                offset: self.offset,
+            })?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn be(&mut self, len: usize) -> Result<usize, PatchError> {
+        Ok(self.take(len)?.iter().fold(0, |n, b| n << 8 | *b as usize))
+    }
+
+    /// BPS variable-length number
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let start = self.offset;
+        let (mut n, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = self.take(1)?[0] as usize;
+            n = (byte & 0x7F)
+                .checked_mul(shift)
+                .and_then(|x| x.checked_add(n))
+                .ok_or(PatchError::OutOfBounds { offset: start })?;
+            if byte & 0x80 != 0 {
+                return Ok(n);
+            }
+            shift = shift
+                .checked_mul(0x80)
+                .ok_or(PatchError::OutOfBounds { offset: start })?;
+            n = n
+                .checked_add(shift)
+                .ok_or(PatchError::OutOfBounds { offset: start })?;
+        }
+    }
+}
+
+fn apply_ips(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = source.to_vec();
+    let mut reader = Reader {
+        bytes: patch,
+        offset: IPS_MAGIC.len(),
+    };
+    loop {
+        let record = reader.offset;
+        if reader.take(IPS_EOF.len())? == IPS_EOF {
+            break;
+        }
+        reader.offset = record;
+        let offset = reader.be(3)?;
+        let data = match reader.be(2)? {
+            // run-length encoded record
+            0 => {
+                let len = reader.be(2)?;
+                let value = reader.take(1)?[0];
+                core::iter::repeat_n(value, len).collect()
+            }
+            len => reader.take(len)?.to_vec(),
+        };
+        if target.len() < offset + data.len() {
+            target.resize(offset + data.len(), 0);
+        }
+        target[offset..offset + data.len()].copy_from_slice(&data);
+    }
+    // optional truncation extension
+    if let Ok(len) = reader.be(3) {
+        target.truncate(len);
+    }
+    Ok(target)
+}
+
+fn apply_bps(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_LEN {
+        return Err(PatchError::Truncated {
+            offset: patch.len(),
+        });
+    }
+    let actions_end = patch.len() - BPS_FOOTER_LEN;
+    let footer = |i: usize| {
+        let at = actions_end + i * 4;
+        u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]])
+    };
+    if crc32fast::hash(&patch[..patch.len() - 4]) != footer(2) {
+        return Err(PatchError::PatchChecksum);
+    }
+    let mut reader = Reader {
+        bytes: &patch[..actions_end],
+        offset: BPS_MAGIC.len(),
+    };
+    let source_len = reader.number()?;
+    let target_start = reader.offset;
+    let target_len = reader.number()?;
+    if target_len > BPS_MAX_TARGET_LEN {
+        return Err(PatchError::OutOfBounds {
+            offset: target_start,
+        });
+    }
+    let metadata_len = reader.number()?;
+    reader.take(metadata_len)?;
+    if source_len != source.len() || crc32fast::hash(source) != footer(0) {
+        return Err(PatchError::SourceMismatch);
+    }
+
+    let mut target = Vec::with_capacity(target_len);
+    let (mut source_pos, mut target_pos) = (0usize, 0usize);
+    while reader.offset < actions_end {
+        let record = reader.offset;
+        let out_of_bounds = PatchError::OutOfBounds { offset: record };
+        let command = reader.number()?;
+        let len = (command >> 2) + 1;
+        if target
+            .len()
+            .checked_add(len)
+            .is_none_or(|end| end > target_len)
+        {
+            return Err(out_of_bounds);
+        }
+        match command & 3 {
+            // source read
+            0 => {
+                let at = target.len();
+                let data = source.get(at..at + len).ok_or(out_of_bounds)?;
+                target.extend_from_slice(data);
+            }
+            // target read
+            1 => target.extend_from_slice(reader.take(len)?),
+            // source copy
+            2 => {
+                source_pos = relative(source_pos, reader.number()?).ok_or(out_of_bounds)?;
+                let data = source_pos
+                    .checked_add(len)
+                    .and_then(|end| source.get(source_pos..end))
+                    .ok_or(out_of_bounds)?;
+                target.extend_from_slice(data);
+                source_pos += len;
+            }
+            // target copy, may overlap the bytes being written
+            _ => {
+                target_pos = relative(target_pos, reader.number()?).ok_or(out_of_bounds)?;
+                if target_pos >= target.len() {
+                    return Err(out_of_bounds);
+                }
+                for _ in 0..len {
+                    target.push(target[target_pos]);
+                    target_pos += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_len || crc32fast::hash(&target) != footer(1) {
+        return Err(PatchError::TargetMismatch);
+    }
+    Ok(target)
+}
+
+/// Applies BPS signed offset, the lowest bit is the sign
+fn relative(pos: usize, offset: usize) -> Option<usize> {
+    if offset & 1 == 1 {
+        pos.checked_sub(offset >> 1)
+    } else {
+        pos.checked_add(offset >> 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(mut n: usize, out: &mut Vec<u8>) {
+        loop {
+            let x = (n & 0x7F) as u8;
+            n >>= 7;
+            if n == 0 {
+                out.push(0x80 | x);
+                return;
+            }
+            out.push(x);
+            n -= 1;
+        }
+    }
+
+    /// BPS patch with `actions` between the header and the footer
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        number(source.len(), &mut header);
+        number(target.len(), &mut header);
+        number(0, &mut header);
+        header.extend_from_slice(actions);
+        bps_raw(source, target, &header)
+    }
+
+    /// BPS patch with `body` as both the header numbers and the actions
+    fn bps_raw(source: &[u8], target: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        patch.extend_from_slice(body);
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&crc32fast::hash(target).to_le_bytes());
+        let crc = crc32fast::hash(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn patch_ips() {
+        let source = [0u8; 8];
+        let mut patch = b"PATCH".to_vec();
+        // 2 bytes at 1, 3 times 0xAA at 5, 1 byte at 9 grows the data
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0x11, 0x22]);
+        patch.extend_from_slice(&[0, 0, 5, 0, 0, 0, 3, 0xAA]);
+        patch.extend_from_slice(&[0, 0, 9, 0, 1, 0x33]);
+        patch.extend_from_slice(b"EOF");
+        let patch = Patch::parse(&patch).unwrap();
+        assert_eq!(
+            vec![0, 0x11, 0x22, 0, 0, 0xAA, 0xAA, 0xAA, 0, 0x33],
+            patch.apply(&source).unwrap()
+        );
+
+        let truncated = b"PATCH\x00\x00\x01\x00\x02\x11";
+        assert_eq!(
+            Err(PatchError::Truncated { offset: 10 }),
+            Patch::parse(truncated).unwrap().apply(&source)
+        );
+        assert_eq!(Err(PatchError::UnknownFormat), Patch::parse(b"PAT"));
+    }
+
+    #[test]
+    fn patch_bps() {
+        let source = b"ABCDEFGH";
+        let target = b"ABCDxyxyxEFG";
+        let mut actions = Vec::new();
+        // source read 4, target read "xy", target copy 3 from 4, source copy 3 from 4
+        number(3 << 2, &mut actions);
+        number((1 << 2) | 1, &mut actions);
+        actions.extend_from_slice(b"xy");
+        number((2 << 2) | 3, &mut actions);
+        number(4 << 1, &mut actions);
+        number((2 << 2) | 2, &mut actions);
+        number(4 << 1, &mut actions);
+        let patch = Patch::parse(&bps(source, target, &actions)).unwrap();
+        assert_eq!(target.to_vec(), patch.apply(source).unwrap());
+        assert_eq!(Err(PatchError::SourceMismatch), patch.apply(b"ABCDEFGX"));
+
+        let mut damaged = bps(source, target, &actions);
+        damaged[8] ^= 1;
+        assert_eq!(
+            Err(PatchError::PatchChecksum),
+            Patch::parse(&damaged).unwrap().apply(source)
+        );
+        let wrong = Patch::parse(&bps(source, b"ABCDxyxyxEFF", &actions)).unwrap();
+        assert_eq!(Err(PatchError::TargetMismatch), wrong.apply(source));
+
+        // corrupt sizes are errors, not huge allocations or overflows
+        let apply = |numbers: &[usize]| {
+            let mut body = Vec::new();
+            for n in numbers {
+                number(*n, &mut body);
+            }
+            Patch::parse(&bps_raw(source, target, &body))
+                .unwrap()
+                .apply(source)
+        };
+        assert_eq!(
+            Err(PatchError::OutOfBounds { offset: 5 }),
+            apply(&[8, 1 << 62, 0])
+        );
+        assert_eq!(
+            Err(PatchError::Truncated { offset: 16 }),
+            apply(&[8, 12, usize::MAX - 1])
+        );
+        assert_eq!(
+            Err(PatchError::OutOfBounds { offset: 7 }),
+            apply(&[8, 12, 0, usize::MAX - 3])
+        );
+        assert_eq!(
+            Err(PatchError::OutOfBounds { offset: 7 }),
+            apply(&[8, 12, 0, 2, usize::MAX - 1])
+        );
+    }
+}
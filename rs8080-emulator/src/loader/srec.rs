@@ -0,0 +1,101 @@
+use super::{decode_hex, Image, LoadError};
+
+pub(crate) fn parse(text: &str) -> Result<Image, LoadError> {
+    let mut image = Image::default();
+    for (i, record) in text.lines().enumerate() {
+        let line = i + 1;
+        let record = record.trim();
+        if record.is_empty() {
+            continue;
+        }
+        let kind = record
+            .strip_prefix('S')
+            .and_then(|r| r.chars().next())
+            .ok_or(LoadError::Syntax { line })?;
+        // count byte covers address, data and checksum
+        let bytes = record
+            .get(2..)
+            .and_then(decode_hex)
+            .filter(|b| b.len() >= 3 && b.len() == b[0] as usize + 1)
+            .ok_or(LoadError::Syntax { line })?;
+        let (body, checksum) = bytes.split_at(bytes.len() - 1);
+        if !body.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != checksum[0] {
+            return Err(LoadError::Checksum { line });
+        }
+        let address_len = match kind {
+            '0' | '5' | '6' => continue,
+            '1' | '9' => 2,
+            '2' | '8' => 3,
+            '3' | '7' => 4,
+            _ => return Err(LoadError::UnsupportedRecord { line }),
+        };
+        if body.len() < 1 + address_len {
+            return Err(LoadError::Syntax { line });
+        }
+        let address = body[1..=address_len]
+            .iter()
+            .fold(0u32, |adr, b| adr << 8 | *b as u32);
+        match kind {
+            '1' | '2' | '3' => image.add(address, &body[1 + address_len..])?,
+            _ => image.set_entry(address)?,
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::Segment;
+
+    #[test]
+    fn srec_parse() {
+        let text = "\
+S00600004844521B
+S1060100C3000134
+S206000103313292
+S5030002FA
+S9030100FB
+";
+        let image = parse(text).unwrap();
+        assert_eq!(
+            vec![Segment {
+                address: 0x100,
+                data: vec![0xC3, 0x00, 0x01, 0x31, 0x32]
+            }],
+            image.segments
+        );
+        assert_eq!(Some(0x100), image.entry);
+    }
+
+    #[test]
+    fn srec_errors() {
+        assert_eq!(
+            Err(LoadError::Checksum { line: 1 }),
+            parse("S1060100C3000135")
+        );
+        assert_eq!(
+            Err(LoadError::Syntax { line: 1 }),
+            parse("S1070100C3000134")
+        );
+        assert_eq!(Err(LoadError::Syntax { line: 1 }), parse(":1060100"));
+        assert_eq!(
+            Err(LoadError::Syntax { line: 1 }),
+            parse("S1+60100C3000134")
+        );
+        assert_eq!(
+            Err(LoadError::UnsupportedRecord { line: 1 }),
+            parse("S4030000FC")
+        );
+        assert_eq!(
+            Err(LoadError::Overflow { address: 0x1_0000 }),
+            parse("S2050100000FEA")
+        );
+        assert_eq!(
+            Err(LoadError::Overflow {
+                address: 0xFFFF_FFFF
+            }),
+            parse("S306FFFFFFFF00FD")
+        );
+    }
+}
@@ -34,7 +34,10 @@ fn main() {
     //emu.get_mut_mem()[368] = 0x7;
     let mut i = 0;
     loop {
-        let s = format!("{}", emu.disassemble_next());
+        let s = match emu.try_disassemble_next() {
+            Ok(instruction) => instruction.to_string(),
+            Err(err) => err.to_string(),
+        };
         println!("{:04} | {:028} |{:>}", i, s, emu);
         emu.emulate_next();
         i += 1;
@@ -1,7 +1,8 @@
 use crate::traits::FlagHelpers;
-use std::fmt::{self, Display, Formatter};
+use core::fmt::{self, Display, Formatter};
 
 #[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Represents 8 bit flag register
 pub(crate) struct ConditionalCodes {
     /// Zero flag, set when result is zero
@@ -63,4 +64,26 @@ impl ConditionalCodes {
         self.s = x.sign();
         self.p = x.parity();
     }
+
+    /// Packs flags the way `PUSH PSW` stores them
+    /// # Layout
+    /// `[ S, Z, 0, AC, 0, P, 0, CY ]`
+    pub fn psw_byte(&self) -> u8 {
+        let mut data = 0;
+        data |= (self.s as u8) << 7;
+        data |= (self.z as u8) << 6;
+        data |= (self.ac as u8) << 4;
+        data |= (self.p as u8) << 2;
+        data |= self.cy as u8;
+        data
+    }
+
+    /// Unpacks flags the way `POP PSW` loads them
+    pub fn set_psw_byte(&mut self, x: u8) {
+        self.s = x & 0b1000_0000 > 0;
+        self.z = x & 0b0100_0000 > 0;
+        self.ac = x & 0b0001_0000 > 0;
+        self.p = x & 0b0000_0100 > 0;
+        self.cy = x & 0b0000_0001 > 0;
+    }
 }
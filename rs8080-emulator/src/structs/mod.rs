@@ -7,5 +7,10 @@ pub(crate) use cond_codes::*;
 mod twou8;
 pub(crate) use twou8::*;
 
+mod state;
+#[cfg(feature = "serde")]
+mod serde_mem;
+pub use state::{Registers, StateError};
+
 pub mod rs8080;
 pub use rs8080::RS8080;
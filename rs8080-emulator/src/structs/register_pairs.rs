@@ -1,6 +1,6 @@
 use crate::structs::TwoU8;
 use crate::traits::OverflowMath;
-use std::ops::{AddAssign, SubAssign};
+use core::ops::{AddAssign, SubAssign};
 
 fn merge(lo: u8, hi: u8) -> u16 {
     TwoU8 { lo, hi }.into()
@@ -11,16 +11,14 @@ macro_rules! impl_ops {
         impl AddAssign<u16> for $struct_name {
             fn add_assign(&mut self, rhs: u16) {
                 let val = merge(self.$lo, self.$hi).wrapping_add(rhs);
-                self.$hi = (val >> 8) as u8;
-                self.$lo = (val) as u8;
+                self.set(val);
             }
         }
 
         impl SubAssign<u16> for $struct_name {
             fn sub_assign(&mut self, rhs: u16) {
                 let val = merge(self.$lo, self.$hi).wrapping_sub(rhs);
-                self.$hi = (val >> 8) as u8;
-                self.$lo = (val) as u8;
+                self.set(val);
             }
         }
 
@@ -106,16 +104,19 @@ macro_rules! impl_ops {
 }
 
 #[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct BC {
     pub(crate) b: u8,
     pub(crate) c: u8,
 }
 #[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct DE {
     pub(crate) d: u8,
     pub(crate) e: u8,
 }
 #[derive(Copy, Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct HL {
     pub(crate) h: u8,
     pub(crate) l: u8,
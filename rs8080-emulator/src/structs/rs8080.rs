@@ -1,12 +1,23 @@
-use crate::structs::{ConditionalCodes, TwoU8, BC, DE, HL};
+#[cfg(feature = "alloc")]
+use crate::loader::Image;
+use crate::loader::LoadError;
+use crate::structs::{ConditionalCodes, Registers, StateError, TwoU8, BC, DE, HL};
 use crate::traits::{DataBus, OverflowMath};
-use std::fmt::{self, Formatter};
-extern crate rs8080_disassembler as disasm;
 use crate::traits::{MemLimiter, WriteAction};
 use crate::ClockCycles;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt::{self, Formatter};
+#[cfg(feature = "alloc")]
 use disasm::{disassemble, Command};
+use disasm::{try_disassemble, DecodeError, Instruction};
+extern crate rs8080_disassembler as disasm;
+
+const STATE_MAGIC: &[u8; 4] = b"RS80";
+const STATE_VERSION: u8 = 1;
 
 /// Default mem access policy, allowing all writes and reads
+#[derive(Default)]
 pub struct AllowAll {}
 impl MemLimiter for AllowAll {
     fn check_write(&self, _: u16, _: u8) -> WriteAction {
@@ -18,6 +29,17 @@ impl MemLimiter for AllowAll {
 }
 
 /// Intel 8080
+///
+/// With `serde` feature CPU, memory and `IO` are serialized,
+/// `LIM` is stateless and is recreated with `Default`
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(
+        from = "Fields<IO>",
+        bound(deserialize = "IO: serde::Deserialize<'de>, LIM: Default")
+    )
+)]
 pub struct RS8080<IO, LIM>
 where
     IO: DataBus,
@@ -32,15 +54,66 @@ where
     sp: u16,
     /// program counter
     pc: u16,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::structs::serde_mem::serialize")
+    )]
     mem: [u8; 0xFFFF],
     /// Conditional codes
     cc: ConditionalCodes,
     /// Interrupts enabled
     int_enable: bool,
+    /// Stopped by HLT until the next interrupt
+    #[cfg_attr(feature = "serde", serde(default))]
+    halted: bool,
     io_device: IO,
+    #[cfg_attr(feature = "serde", serde(skip))]
     mem_limiter: LIM,
 }
 
+/// Serialized fields of [RS8080](struct.RS8080.html) with memory on the heap,
+/// so that deserializing does not copy the whole CPU around the stack
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct Fields<IO> {
+    a: u8,
+    bc: BC,
+    de: DE,
+    hl: HL,
+    sp: u16,
+    pc: u16,
+    #[serde(deserialize_with = "crate::structs::serde_mem::deserialize")]
+    mem: Vec<u8>,
+    cc: ConditionalCodes,
+    int_enable: bool,
+    #[serde(default)]
+    halted: bool,
+    io_device: IO,
+}
+
+#[cfg(feature = "serde")]
+impl<IO, LIM> From<Fields<IO>> for RS8080<IO, LIM>
+where
+    IO: DataBus,
+    LIM: MemLimiter + Default,
+{
+    fn from(fields: Fields<IO>) -> Self {
+        let mut cpu = RS8080::new_with_limit(fields.io_device, LIM::default());
+        cpu.a = fields.a;
+        cpu.bc = fields.bc;
+        cpu.de = fields.de;
+        cpu.hl = fields.hl;
+        cpu.sp = fields.sp;
+        cpu.pc = fields.pc;
+        // the length is checked by `serde_mem`
+        cpu.mem.copy_from_slice(&fields.mem);
+        cpu.cc = fields.cc;
+        cpu.int_enable = fields.int_enable;
+        cpu.halted = fields.halted;
+        cpu
+    }
+}
+
 impl<IO, LIM> fmt::Display for RS8080<IO, LIM>
 where
     IO: DataBus,
@@ -81,6 +154,7 @@ where
             mem: [0; 0xFFFF],
             cc: Default::default(),
             int_enable: false,
+            halted: false,
             io_device,
             mem_limiter: AllowAll {},
         }
@@ -103,6 +177,7 @@ where
             mem: [0; 0xFFFF],
             cc: Default::default(),
             int_enable: false,
+            halted: false,
             io_device,
             mem_limiter,
         }
@@ -113,6 +188,10 @@ where
     //     self.mem_limiter = new_mem_limiter;
     // }
 
+    pub fn get_io(&self) -> &IO {
+        &self.io_device
+    }
+
     pub fn get_io_mut(&mut self) -> &mut IO {
         &mut self.io_device
     }
@@ -127,15 +206,49 @@ where
     }
 
     /// # Panics
-    /// length of slice > mem
+    /// slice does not fit in memory, see [try_load_to_mem](#method.try_load_to_mem)
     #[inline]
     pub fn load_to_mem(&mut self, slice: &[u8], offset: u16) {
-        if slice.len() > self.mem.len() {
-            panic!("input was too large for emulated memory (max 0xFFFF)");
+        if let Err(err) = self.try_load_to_mem(slice, offset) {
+            panic!("input was too large for emulated memory: {}", err);
         }
-        self.mem[offset as usize..(slice.len() + offset as usize)].copy_from_slice(slice);
     }
 
+    /// Copies `slice` to memory at `offset`, memory is left untouched if it does not fit
+    pub fn try_load_to_mem(&mut self, slice: &[u8], offset: u16) -> Result<(), LoadError> {
+        let end = offset as usize + slice.len();
+        if end > self.mem.len() {
+            return Err(LoadError::Overflow {
+                address: self.mem.len().max(offset as usize) as u32,
+            });
+        }
+        self.mem[offset as usize..end].copy_from_slice(slice);
+        Ok(())
+    }
+
+    /// Copies all segments of `image` to memory and jumps to its entry address if there is one.
+    /// Nothing is loaded if any segment does not fit
+    #[cfg(feature = "alloc")]
+    pub fn load_image(&mut self, image: &Image) -> Result<(), LoadError> {
+        if let Some(seg) = image
+            .segments
+            .iter()
+            .find(|seg| seg.end() > self.mem.len() as u32)
+        {
+            return Err(LoadError::Overflow {
+                address: self.mem.len().max(seg.address as usize) as u32,
+            });
+        }
+        for seg in &image.segments {
+            self.try_load_to_mem(&seg.data, seg.address)?;
+        }
+        if let Some(entry) = image.entry {
+            self.pc = entry;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "alloc")]
     #[inline]
     /// Returns [Command](./../rs8080_disassembler/command/struct.Command.html) that
     /// implements `Display` trait
@@ -143,9 +256,19 @@ where
         disassemble(&self.mem[self.pc as usize..])
     }
 
+    #[inline]
+    /// Instruction pointed by program counter, decoded without allocating
+    pub fn try_disassemble_next(&self) -> Result<Instruction<'_>, DecodeError> {
+        try_disassemble(&self.mem[self.pc as usize..])
+    }
+
     /// Emulate next opcode pointed by program counter.
     /// Returns [ClockCycles](./struct.ClockCycles.html) spent on the opcode
     pub fn emulate_next(&mut self) -> ClockCycles {
+        // halted CPU idles until an interrupt
+        if self.halted {
+            return ClockCycles(4);
+        }
         let mut cycles = ClockCycles(0);
         let mem_from_pc = &self.mem[self.pc as usize..];
         self.pc.add_un(1);
@@ -781,9 +904,8 @@ where
             }
             // HLT
             [0x76, ..] => {
-                // TODO: HLT
-                todo!();
                 cycles.add(7);
+                self.halted = true;
             }
             // MOV M,A
             [0x77, ..] => {
@@ -1158,7 +1280,7 @@ where
             [0xC0, ..] => {
                 cycles.add(5);
                 if !self.cc.z {
-                    cycles.add(11);
+                    cycles.add(6);
                     self.ret();
                 }
             }
@@ -1187,7 +1309,7 @@ where
                 cycles.add(11);
                 self.pc += 2;
                 if !self.cc.z {
-                    cycles.add(17);
+                    cycles.add(6);
                     self.call(TwoU8 { lo, hi }.into());
                 }
             }
@@ -1212,7 +1334,7 @@ where
             [0xC8, ..] => {
                 cycles.add(5);
                 if self.cc.z {
-                    cycles.add(11);
+                    cycles.add(6);
                     self.ret();
                 }
             }
@@ -1237,7 +1359,7 @@ where
                 cycles.add(11);
                 self.pc += 2;
                 if self.cc.z {
-                    cycles.add(17);
+                    cycles.add(6);
                     self.call(TwoU8 { lo, hi }.into());
                 }
             }
@@ -1292,7 +1414,7 @@ where
             [0xD0, ..] => {
                 cycles.add(5);
                 if !self.cc.cy {
-                    cycles.add(11);
+                    cycles.add(6);
                     self.ret();
                 }
             }
@@ -1322,7 +1444,7 @@ where
                 cycles.add(11);
                 self.pc.add_un(2);
                 if !self.cc.cy {
-                    cycles.add(17);
+                    cycles.add(6);
                     self.call(TwoU8 { lo, hi }.into());
                 }
             }
@@ -1347,7 +1469,7 @@ where
             [0xD8, ..] => {
                 cycles.add(5);
                 if self.cc.cy {
-                    cycles.add(11);
+                    cycles.add(6);
                     self.ret();
                 }
             }
@@ -1373,7 +1495,7 @@ where
                 cycles.add(11);
                 self.pc += 2;
                 if self.cc.cy {
-                    cycles.add(17);
+                    cycles.add(6);
                     self.call(TwoU8 { lo, hi }.into());
                 }
             }
@@ -1398,7 +1520,7 @@ where
             [0xE0, ..] => {
                 cycles.add(5);
                 if !self.cc.p {
-                    cycles.add(11);
+                    cycles.add(6);
                     self.ret();
                 }
             }
@@ -1429,7 +1551,7 @@ where
                 cycles.add(11);
                 self.pc += 2;
                 if !self.cc.p {
-                    cycles.add(17);
+                    cycles.add(6);
                     self.call(TwoU8 { lo, hi }.into());
                 }
             }
@@ -1455,7 +1577,7 @@ where
             [0xE8, ..] => {
                 cycles.add(5);
                 if self.cc.p {
-                    cycles.add(11);
+                    cycles.add(6);
                     self.ret();
                 }
             }
@@ -1485,7 +1607,7 @@ where
                 cycles.add(11);
                 self.pc += 2;
                 if self.cc.p {
-                    cycles.add(17);
+                    cycles.add(6);
                     self.call(TwoU8 { lo, hi }.into());
                 }
             }
@@ -1509,7 +1631,7 @@ where
             [0xF0, ..] => {
                 cycles.add(5);
                 if !self.cc.s {
-                    cycles.add(11);
+                    cycles.add(6);
                     self.ret();
                 }
             }
@@ -1517,16 +1639,10 @@ where
             [0xF1, ..] => {
                 cycles.add(10);
                 // 15                               0
-                // [a : u8][ 7, 6, 5,  4, 3, 2, 1,  0 ]
                 // [a : u8][ S, Z,  , AC,  , P,  , CY ]
                 let popped = self.pop();
-                let x = popped.lo;
                 self.a = popped.hi;
-                self.cc.s = x & 0b1000_0000 > 0;
-                self.cc.z = x & 0b0100_0000 > 0;
-                self.cc.ac = x & 0b0001_0000 > 0;
-                self.cc.p = x & 0b0000_0100 > 0;
-                self.cc.cy = x & 0b0000_0001 > 0;
+                self.cc.set_psw_byte(popped.lo);
             }
             // JP adr
             [0xF2, lo, hi, ..] => {
@@ -1547,22 +1663,15 @@ where
                 cycles.add(11);
                 self.pc += 2;
                 if !self.cc.s {
-                    cycles.add(17);
+                    cycles.add(6);
                     self.call(TwoU8 { lo, hi }.into());
                 }
             }
             // PUSH PSW
             [0xF5, ..] => {
                 cycles.add(11);
-                // [a : u8][ 7, 6, 5,  4, 3, 2, 1,  0 ]
                 // [a : u8][ S, Z,  , AC,  , P,  , CY ]
-                let mut data = 0;
-                data |= (self.cc.s as u8) << 7;
-                data |= (self.cc.z as u8) << 6;
-                data |= (self.cc.ac as u8) << 4;
-                data |= (self.cc.p as u8) << 2;
-                data |= self.cc.cy as u8;
-                self.push(TwoU8::new(data, self.a));
+                self.push(TwoU8::new(self.cc.psw_byte(), self.a));
             }
             // ORI D8
             [0xF6, d8, ..] => {
@@ -1581,7 +1690,7 @@ where
             [0xF8, ..] => {
                 cycles.add(5);
                 if self.cc.s {
-                    cycles.add(11);
+                    cycles.add(6);
                     self.ret();
                 }
             }
@@ -1608,7 +1717,7 @@ where
                 cycles.add(11);
                 self.pc += 2;
                 if self.cc.s {
-                    cycles.add(17);
+                    cycles.add(6);
                     self.call(TwoU8 { lo, hi }.into());
                 }
             }
@@ -1639,6 +1748,89 @@ where
         self.pc
     }
 
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    /// Returns copy of CPU registers
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.a,
+            b: self.bc.b,
+            c: self.bc.c,
+            d: self.de.d,
+            e: self.de.e,
+            h: self.hl.h,
+            l: self.hl.l,
+            sp: self.sp,
+            pc: self.pc,
+            flags: self.cc.psw_byte(),
+            int_enable: self.int_enable,
+            halted: self.halted,
+        }
+    }
+
+    pub fn set_registers(&mut self, regs: &Registers) {
+        self.a = regs.a;
+        self.bc = BC {
+            b: regs.b,
+            c: regs.c,
+        };
+        self.de = DE {
+            d: regs.d,
+            e: regs.e,
+        };
+        self.hl = HL {
+            h: regs.h,
+            l: regs.l,
+        };
+        self.sp = regs.sp;
+        self.pc = regs.pc;
+        self.cc.set_psw_byte(regs.flags);
+        self.int_enable = regs.int_enable;
+        self.halted = regs.halted;
+    }
+
+    /// Power-on reset: clears registers and disables interrupts,
+    /// memory is left untouched
+    pub fn reset(&mut self) {
+        self.set_registers(&Registers::default());
+    }
+
+    /// Appends registers and memory to `out`
+    #[cfg(feature = "alloc")]
+    pub fn save_state(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(STATE_MAGIC);
+        out.push(STATE_VERSION);
+        out.extend_from_slice(&self.registers().to_bytes());
+        out.extend_from_slice(&self.mem);
+    }
+
+    /// Restores registers and memory saved by [save_state](#method.save_state).
+    /// Returns number of bytes consumed from `state`
+    pub fn load_state(&mut self, state: &[u8]) -> Result<usize, StateError> {
+        let header_len = STATE_MAGIC.len() + 1;
+        let total_len = header_len + Registers::SIZE + self.mem.len();
+        if state.len() < header_len {
+            return Err(StateError::Truncated);
+        }
+        if &state[..STATE_MAGIC.len()] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        if state[STATE_MAGIC.len()] != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(state[STATE_MAGIC.len()]));
+        }
+        if state.len() < total_len {
+            return Err(StateError::Truncated);
+        }
+        let mut regs = [0; Registers::SIZE];
+        regs.copy_from_slice(&state[header_len..header_len + Registers::SIZE]);
+        self.set_registers(&Registers::from_bytes(&regs));
+        self.mem
+            .copy_from_slice(&state[header_len + Registers::SIZE..total_len]);
+        Ok(total_len)
+    }
+
     fn read_mem(&self, adr: impl Into<usize> + Copy) -> u8 {
         let adr: usize = adr.into();
         self.mem_limiter.check_read(adr as u16, self.mem[adr])
@@ -1659,11 +1851,13 @@ where
     /// and sets `int_enable` to `false`
     pub fn generate_interrupt(&mut self, interrupt_num: u16) {
         self.int_enable = false;
+        self.halted = false;
         self.call(8 * interrupt_num);
     }
 
     pub fn call_interrupt(&mut self, call_adr: u16) {
         //self.int_enable = false;
+        self.halted = false;
         self.call(call_adr);
     }
 
@@ -1752,3 +1946,80 @@ where
         self.int_enable
     }
 }
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    struct DummyIO {}
+    impl DataBus for DummyIO {
+        fn port_in(&mut self, _: u8) -> u8 {
+            0
+        }
+        fn port_out(&mut self, _: u8, _: u8) {}
+        fn port(&mut self, _: usize) -> &mut u8 {
+            unimplemented!()
+        }
+    }
+
+    /// Cycles of every opcode match the disassembler's instruction info,
+    /// conditional ones both when taken and when not
+    #[test]
+    fn emulator_cycles_match_info() {
+        for opcode in 0..=0xFFu8 {
+            let bytes = [opcode, 0x00, 0x10];
+            let cycles = disassemble(&bytes).info().cycles;
+            let size = disassemble(&bytes).size as u16;
+            for flags in [0x00, 0xFF] {
+                let mut emu = RS8080::new(DummyIO {});
+                emu.load_to_mem(&bytes, 0x100);
+                // return address for RET
+                emu.load_to_mem(&[0x00, 0x30], 0x2000);
+                emu.set_registers(&Registers {
+                    sp: 0x2000,
+                    pc: 0x100,
+                    flags,
+                    ..Registers::default()
+                });
+                let spent = emu.emulate_next().0;
+                let taken = emu.get_pc() != 0x100 + size;
+                let expected = if taken {
+                    cycles.taken
+                } else {
+                    cycles.not_taken
+                };
+                assert_eq!(
+                    expected as u32, spent,
+                    "opcode {:02X}, taken: {}",
+                    opcode, taken
+                );
+            }
+        }
+    }
+    #[test]
+    fn halt_until_interrupt() {
+        let mut emu = RS8080::new(DummyIO {});
+        // EI; HLT; NOP
+        emu.load_to_mem(&[0xFB, 0x76, 0x00], 0x100);
+        emu.set_registers(&Registers {
+            sp: 0x2000,
+            pc: 0x100,
+            ..Registers::default()
+        });
+        emu.emulate_next();
+        assert_eq!(7, emu.emulate_next().0);
+        assert!(emu.registers().halted);
+        for _ in 0..3 {
+            assert_eq!(4, emu.emulate_next().0);
+            assert_eq!(0x102, emu.get_pc());
+        }
+        emu.generate_interrupt(1);
+        assert!(!emu.registers().halted);
+        assert_eq!(8, emu.get_pc());
+        // interrupt returns after HLT
+        assert_eq!(
+            0x102,
+            u16::from_le_bytes([emu.get_mem()[0x1FFE], emu.get_mem()[0x1FFF]])
+        );
+    }
+}
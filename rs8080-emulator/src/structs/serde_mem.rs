@@ -0,0 +1,88 @@
+//! Memory is serialized as a hex string by human readable formats
+//! and as a byte array by binary ones
+use alloc::vec::Vec;
+use core::fmt::{self, Formatter};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserializer, Serializer};
+
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn serialize<S, const N: usize>(mem: &[u8; N], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    if serializer.is_human_readable() {
+        serializer.collect_str(&Hex(mem))
+    } else {
+        serializer.serialize_bytes(mem)
+    }
+}
+
+/// Reads the 0xFFFF bytes of CPU memory to the heap, a memory array
+/// deserialized by value is copied on the stack several times
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(MemVisitor::<0xFFFF>)
+    } else {
+        deserializer.deserialize_bytes(MemVisitor::<0xFFFF>)
+    }
+}
+
+struct MemVisitor<const N: usize>;
+
+impl<'de, const N: usize> Visitor<'de> for MemVisitor<N> {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes of memory", N)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        if v.len() != N * 2 {
+            return Err(E::invalid_length(v.len() / 2, &self));
+        }
+        v.as_bytes()
+            .chunks(2)
+            .map(|hex| {
+                let digit = |d: &u8| (*d as char).to_digit(16);
+                match (digit(&hex[0]), digit(&hex[1])) {
+                    (Some(hi), Some(lo)) => Ok((hi << 4 | lo) as u8),
+                    _ => Err(E::invalid_value(
+                        de::Unexpected::Bytes(hex),
+                        &"two hex digits",
+                    )),
+                }
+            })
+            .collect()
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        if v.len() != N {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        Ok(v.to_vec())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut mem = Vec::with_capacity(N);
+        for i in 0..N {
+            mem.push(
+                seq.next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(i, &self))?,
+            );
+        }
+        Ok(mem)
+    }
+}
@@ -0,0 +1,181 @@
+use core::fmt::{self, Display, Formatter};
+
+/// Copy of CPU registers, see [RS8080::registers](./struct.RS8080.html#method.registers)
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Registers {
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    /// stack pointer
+    pub sp: u16,
+    /// program counter
+    pub pc: u16,
+    /// Conditional codes packed as `PUSH PSW` does
+    pub flags: u8,
+    /// Interrupts enabled
+    pub int_enable: bool,
+    /// Stopped by HLT until the next interrupt
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub halted: bool,
+}
+
+impl Registers {
+    /// Size of registers encoded by [to_bytes](#method.to_bytes)
+    pub const SIZE: usize = 13;
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        [
+            self.a,
+            self.b,
+            self.c,
+            self.d,
+            self.e,
+            self.h,
+            self.l,
+            self.sp as u8,
+            (self.sp >> 8) as u8,
+            self.pc as u8,
+            (self.pc >> 8) as u8,
+            self.flags,
+            // bit 1 was always clear before HLT was emulated
+            self.int_enable as u8 | (self.halted as u8) << 1,
+        ]
+    }
+
+    pub fn from_bytes(bytes: &[u8; Self::SIZE]) -> Registers {
+        Registers {
+            a: bytes[0],
+            b: bytes[1],
+            c: bytes[2],
+            d: bytes[3],
+            e: bytes[4],
+            h: bytes[5],
+            l: bytes[6],
+            sp: u16::from_le_bytes([bytes[7], bytes[8]]),
+            pc: u16::from_le_bytes([bytes[9], bytes[10]]),
+            flags: bytes[11],
+            int_enable: bytes[12] & 1 != 0,
+            halted: bytes[12] & 2 != 0,
+        }
+    }
+}
+
+/// Error returned when a saved state cannot be loaded
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StateError {
+    /// Data does not start with a state header
+    BadMagic,
+    /// State was saved by an incompatible version
+    UnsupportedVersion(u8),
+    /// Data ends before the state does
+    Truncated,
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a saved state"),
+            StateError::UnsupportedVersion(v) => {
+                write!(f, "saved state version {} is not supported", v)
+            }
+            StateError::Truncated => write!(f, "saved state is truncated"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StateError {}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::*;
+    use crate::{DataBus, RS8080};
+    use alloc::vec::Vec;
+
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    struct DummyIO {}
+    impl DataBus for DummyIO {
+        fn port_in(&mut self, _: u8) -> u8 {
+            0
+        }
+        fn port_out(&mut self, _: u8, _: u8) {}
+        fn port(&mut self, _: usize) -> &mut u8 {
+            unimplemented!()
+        }
+    }
+
+    #[test]
+    fn registers_bytes_roundtrip() {
+        let regs = Registers {
+            a: 1,
+            b: 2,
+            c: 3,
+            d: 4,
+            e: 5,
+            h: 6,
+            l: 7,
+            sp: 0x2400,
+            pc: 0x1A5C,
+            flags: 0b1100_0101,
+            int_enable: true,
+            halted: true,
+        };
+        assert_eq!(regs, Registers::from_bytes(&regs.to_bytes()));
+    }
+
+    #[test]
+    fn save_load_state() {
+        let mut emu = RS8080::new(DummyIO {});
+        // LXI SP,$2400; MVI A,$42; PUSH PSW; EI
+        emu.load_to_mem(&[0x31, 0x00, 0x24, 0x3E, 0x42, 0xF5, 0xFB], 0);
+        for _ in 0..4 {
+            emu.emulate_next();
+        }
+        let mut state = Vec::new();
+        emu.save_state(&mut state);
+
+        let mut other = RS8080::new(DummyIO {});
+        assert_eq!(Ok(state.len()), other.load_state(&state));
+        assert_eq!(emu.registers(), other.registers());
+        assert_eq!(emu.get_mem(), other.get_mem());
+        assert_eq!(
+            Err(StateError::Truncated),
+            other.load_state(&state[..state.len() - 1])
+        );
+        assert_eq!(Err(StateError::BadMagic), other.load_state(&[0; 8]));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json_roundtrip() {
+        let mut emu = RS8080::new(DummyIO {});
+        // LXI SP,$2400; MVI A,$42; PUSH PSW; EI
+        emu.load_to_mem(&[0x31, 0x00, 0x24, 0x3E, 0x42, 0xF5, 0xFB], 0);
+        for _ in 0..4 {
+            emu.emulate_next();
+        }
+        let json = serde_json::to_string(&emu).unwrap();
+        assert!(json.contains("\"mem\":\"3100243e42f5fb"));
+        assert!(json.len() < 2 * 0xFFFF + 300);
+
+        let other: RS8080<DummyIO, crate::structs::rs8080::AllowAll> =
+            serde_json::from_str(&json).unwrap();
+        assert_eq!(emu.registers(), other.registers());
+        assert_eq!(emu.get_mem(), other.get_mem());
+
+        let regs = serde_json::to_string(&emu.registers()).unwrap();
+        assert_eq!(emu.registers(), serde_json::from_str(&regs).unwrap());
+
+        // only hex digits, `from_str_radix` would take "+1"
+        type Cpu = RS8080<DummyIO, crate::structs::rs8080::AllowAll>;
+        let signed = json.replacen("\"mem\":\"31", "\"mem\":\"+1", 1);
+        assert!(serde_json::from_str::<Cpu>(&signed).is_err());
+        let short = json.replacen("\"mem\":\"31", "\"mem\":\"", 1);
+        assert!(serde_json::from_str::<Cpu>(&short).is_err());
+    }
+}
@@ -33,6 +33,7 @@ impl FlagHelpers for u8 {
         *self & 0x80 != 0
     }
 
+    #[allow(clippy::manual_is_multiple_of)]
     fn parity(&self) -> bool {
         self.count_ones() % 2 == 0
     }
@@ -50,6 +51,7 @@ impl FlagHelpers for u16 {
         *self & 0x8000 != 0
     }
 
+    #[allow(clippy::manual_is_multiple_of)]
     fn parity(&self) -> bool {
         self.count_ones() % 2 == 0
     }
@@ -59,6 +61,7 @@ impl FlagHelpers for u16 {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::FlagHelpers;
     #[test]
@@ -19,6 +19,7 @@ impl HiPart for u16 {
     }
 }
 
+#[allow(dead_code)]
 pub(crate) trait LoPart {
     type Output;
     fn get_lopart(&self) -> Self::Output;
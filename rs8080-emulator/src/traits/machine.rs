@@ -0,0 +1,50 @@
+use crate::structs::StateError;
+use alloc::vec::Vec;
+
+/// Picture of the last emulated frame, one byte per pixel, row by row.
+/// Zero is a dark pixel, anything else is a pixel that emits light
+pub struct Framebuffer<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: &'a [u8],
+}
+
+/// Change of a sound effect, produced while a frame is emulated
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AudioEvent<S> {
+    /// Play sound once
+    Play(S),
+    /// Start playing sound in a loop
+    Loop(S),
+    /// Stop looped sound
+    Stop(S),
+}
+
+/// Whole machine built around the 8080: CPU, memory map, IO ports and video.
+/// Frontends drive it frame by frame and don't need to know about its hardware
+pub trait Machine {
+    /// Buttons, joysticks and switches of the machine
+    type Input: Copy;
+    /// Sound effects the machine can produce
+    type Sound: Copy;
+
+    /// Emulates one video frame, including interrupts raised during the frame
+    fn run_frame(&mut self);
+    /// Returns picture drawn by the last [run_frame](#tymethod.run_frame)
+    fn framebuffer(&self) -> Framebuffer<'_>;
+    /// Presses or releases `input`
+    fn set_input(&mut self, input: Self::Input, pressed: bool);
+    /// Returns audio events produced since the previous call
+    fn audio_events(&mut self) -> Vec<AudioEvent<Self::Sound>>;
+    /// Power-on reset, loaded ROM stays in place
+    fn reset(&mut self);
+    /// Serializes CPU, memory and hardware state
+    fn save_state(&self) -> Vec<u8>;
+    /// Restores state produced by [save_state](#tymethod.save_state)
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError>;
+
+    /// Video frames per second of the original hardware
+    fn frame_rate(&self) -> f64 {
+        60.0
+    }
+}
@@ -12,3 +12,8 @@ pub use databus::*;
 
 mod mem_limiter;
 pub use mem_limiter::{MemLimiter, WriteAction};
+
+#[cfg(feature = "alloc")]
+mod machine;
+#[cfg(feature = "alloc")]
+pub use machine::{AudioEvent, Framebuffer, Machine};
@@ -0,0 +1,93 @@
+use std::path::PathBuf;
+
+pub(crate) const USAGE: &str = "\
+usage: rs8080-space-invaders [options]
+    --headless <frames>     run without a window and print the final state hash
+    --replay <movie>        play a movie without a window and verify its final state hash
+    --record <movie>        record inputs into a movie
+    --play <movie>          play a movie in the window
+    --load-state <file>     start from a saved state
+    --save-state <file>     save state on exit
+    --coverage <file>       record executed opcodes for the disassembler, adds to an existing file
+    --rom-dir <path>        directory with ROM files or a zipped ROM set
+    --patch <file>          apply an IPS or BPS patch to the ROMs, can be repeated
+    --patched-crc32 <hex>   expected CRC32 of the patched ROMs
+    --patched-sha1 <hex>    expected SHA-1 of the patched ROMs";
+
+/// Command line options
+#[derive(Default, Debug, PartialEq)]
+pub(crate) struct Options {
+    pub headless: Option<u64>,
+    pub replay: Option<PathBuf>,
+    pub record: Option<PathBuf>,
+    pub play: Option<PathBuf>,
+    pub load_state: Option<PathBuf>,
+    pub save_state: Option<PathBuf>,
+    pub coverage: Option<PathBuf>,
+    pub rom_dir: Option<PathBuf>,
+    pub patches: Vec<PathBuf>,
+    pub patched_crc32: Option<String>,
+    pub patched_sha1: Option<String>,
+}
+
+pub(crate) fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}\n{}", flag, USAGE))
+        };
+        match flag.as_str() {
+            "--headless" => {
+                let frames = value()?;
+                let frames = frames
+                    .parse()
+                    .map_err(|_| format!("invalid frame count '{}'", frames))?;
+                options.headless = Some(frames);
+            }
+            "--replay" => options.replay = Some(value()?.into()),
+            "--record" => options.record = Some(value()?.into()),
+            "--play" => options.play = Some(value()?.into()),
+            "--load-state" => options.load_state = Some(value()?.into()),
+            "--save-state" => options.save_state = Some(value()?.into()),
+            "--coverage" => options.coverage = Some(value()?.into()),
+            "--rom-dir" => options.rom_dir = Some(value()?.into()),
+            "--patch" => options.patches.push(value()?.into()),
+            "--patched-crc32" => options.patched_crc32 = Some(value()?),
+            "--patched-sha1" => options.patched_sha1 = Some(value()?),
+            _ => return Err(format!("unknown option '{}'\n{}", flag, USAGE)),
+        }
+    }
+    if options.record.is_some() && (options.play.is_some() || options.replay.is_some()) {
+        return Err("a movie cannot be recorded while playing another one".to_owned());
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn cli_parse() {
+        let options = parse(args("--headless 60 --load-state a.state --rom-dir roms")).unwrap();
+        assert_eq!(Some(60), options.headless);
+        assert_eq!(Some(PathBuf::from("roms")), options.rom_dir);
+        assert_eq!(Some(PathBuf::from("a.state")), options.load_state);
+        let options = parse(args("--patch a.ips --patch b.bps --patched-crc32 1234abcd")).unwrap();
+        assert_eq!(
+            vec![PathBuf::from("a.ips"), "b.bps".into()],
+            options.patches
+        );
+        assert_eq!(Some("1234abcd".to_owned()), options.patched_crc32);
+        assert_eq!(Options::default(), parse(args("")).unwrap());
+        assert!(parse(args("--headless")).is_err());
+        assert!(parse(args("--headless x")).is_err());
+        assert!(parse(args("--fast")).is_err());
+        assert!(parse(args("--record a --play b")).is_err());
+    }
+}
@@ -2,13 +2,17 @@ use sdl2::keyboard::Keycode;
 use serde::Deserialize;
 use std::fmt::{self, Formatter};
 use std::fs::File;
+use std::path::PathBuf;
 use std::{error::Error, io::Read, ops::Deref};
 
 #[derive(Deserialize)]
 pub(crate) struct Config {
     pub(crate) controls: Controls,
+    #[cfg_attr(not(feature = "sound"), allow(dead_code))]
     pub volume: Sound,
     pub screen: Screen,
+    #[serde(default)]
+    pub roms: Roms,
 }
 
 pub(crate) fn load_config(path: &str) -> Result<Config, Box<dyn std::error::Error>> {
@@ -78,9 +82,23 @@ pub(crate) struct Controls {
 
 #[derive(Deserialize, Copy, Clone)]
 pub(crate) struct Sound {
+    #[cfg_attr(not(feature = "sound"), allow(dead_code))]
     pub volume: u8,
 }
 
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub(crate) struct Roms {
+    /// Directory with ROM files or a zipped ROM set, bundled ROMs or `./roms` are used if not set
+    pub dir: Option<PathBuf>,
+    /// IPS or BPS patches applied in order
+    pub patches: Vec<PathBuf>,
+    /// Expected hashes of the patched ROMs
+    pub patched_crc32: Option<String>,
+    pub patched_sha1: Option<String>,
+}
+
+#[allow(dead_code)]
 #[derive(Copy, Clone)]
 pub(crate) struct ParseKeycodeErr {}
 impl std::fmt::Display for ParseKeycodeErr {
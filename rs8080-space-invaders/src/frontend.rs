@@ -0,0 +1,129 @@
+extern crate rs8080_emulator as emulator;
+use crate::config::Screen;
+use emulator::{AudioEvent, Framebuffer, Machine};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+use sdl2::{pixels::PixelFormatEnum, video::FullscreenType};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Runs `machine` in an SDL2 window until the window is closed or `Escape` is pressed.
+/// Every key in `keymap` is forwarded to the machine as its input,
+/// audio events are passed to `play_audio`.
+/// `before_frame` is called before every frame, returning `false` from it stops the machine
+pub(crate) fn run<M: Machine>(
+    machine: &mut M,
+    title: &str,
+    keymap: &[(Keycode, M::Input)],
+    screen: &Screen,
+    mut play_audio: impl FnMut(AudioEvent<M::Sound>),
+    mut before_frame: impl FnMut(&mut M) -> Result<bool, Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let sdl_context = sdl2::init()?;
+    let _audio = sdl_context.audio()?;
+    let video_subsystem = sdl_context.video()?;
+
+    let mut window = video_subsystem
+        .window(title, screen.width, screen.height)
+        .position_centered()
+        .resizable()
+        .build()?;
+    if screen.fullscreen {
+        window.set_fullscreen(FullscreenType::Desktop).unwrap();
+    }
+
+    let mut canvas = window.into_canvas().present_vsync().accelerated().build()?;
+    let tc = canvas.texture_creator();
+    let fb = machine.framebuffer();
+    let mut texture = tc.create_texture_streaming(PixelFormatEnum::RGB332, fb.width, fb.height)?;
+    let mut event_pump = sdl_context.event_pump()?;
+    let frame_time = Duration::from_secs_f64(1f64 / machine.frame_rate());
+
+    'running: loop {
+        let start = Instant::now();
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Window {
+                    win_event: sdl2::event::WindowEvent::Resized(..),
+                    ..
+                } => {
+                    canvas.clear();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(x), ..
+                } => {
+                    for (_, input) in keymap.iter().filter(|(key, _)| *key == x) {
+                        machine.set_input(*input, true);
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(x), ..
+                } => {
+                    for (_, input) in keymap.iter().filter(|(key, _)| *key == x) {
+                        machine.set_input(*input, false);
+                    }
+                }
+                Event::Quit { .. } => break 'running,
+                _ => {}
+            }
+        }
+
+        if !before_frame(machine)? {
+            break 'running;
+        }
+        machine.run_frame();
+        for event in machine.audio_events() {
+            play_audio(event);
+        }
+        draw_framebuffer(&mut canvas, &mut texture, &machine.framebuffer(), screen)?;
+        canvas.present();
+        let elapsed = start.elapsed();
+        if elapsed <= frame_time {
+            thread::sleep(frame_time - elapsed);
+        }
+    }
+
+    Ok(())
+}
+
+fn draw_framebuffer(
+    canvas: &mut WindowCanvas,
+    tex: &mut Texture,
+    fb: &Framebuffer,
+    screen: &Screen,
+) -> Result<(), Box<dyn std::error::Error>> {
+    tex.with_lock(None, |buf, pitch| {
+        for (row, pixels) in fb.pixels.chunks(fb.width as usize).enumerate() {
+            let line = &mut buf[row * pitch..row * pitch + pixels.len()];
+            for (dst, pixel) in line.iter_mut().zip(pixels) {
+                *dst = if *pixel != 0 {
+                    screen.white_color
+                } else {
+                    screen.black_color
+                };
+            }
+        }
+    })?;
+
+    let canvas_size = canvas.window().size();
+    let new_width = (screen.width as f64 * canvas_size.1 as f64 / screen.height as f64) as u32;
+    canvas.copy(
+        tex,
+        None,
+        Rect::new(
+            canvas_size.0 as i32 / 2 - new_width as i32 / 2,
+            0,
+            new_width,
+            canvas_size.1,
+        ),
+    )?;
+    Ok(())
+}
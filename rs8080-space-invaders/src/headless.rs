@@ -0,0 +1,18 @@
+extern crate rs8080_emulator as emulator;
+use emulator::Machine;
+
+/// Runs `frames` frames without video and sound, returns hash of the final state
+pub(crate) fn run_headless<M: Machine>(machine: &mut M, frames: u64) -> u64 {
+    for _ in 0..frames {
+        machine.run_frame();
+        machine.audio_events();
+    }
+    state_hash(&machine.save_state())
+}
+
+/// FNV-1a hash of a saved state
+pub(crate) fn state_hash(state: &[u8]) -> u64 {
+    state.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
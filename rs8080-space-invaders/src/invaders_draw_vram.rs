@@ -1,49 +1,25 @@
-use crate::config::Screen;
-use sdl2::rect::Rect;
-use sdl2::render::{Texture, WindowCanvas};
+/// Screen is rotated by 90 degrees in the cabinet
+pub(crate) const SCREEN_WIDTH: usize = 224;
+pub(crate) const SCREEN_HEIGHT: usize = 256;
 
-pub(crate) fn draw_space_invaders_vram(
-    canvas: &mut WindowCanvas,
-    tex: &mut Texture,
-    vram: &[u8],
-    screen: &Screen,
-) -> Result<(), Box<dyn std::error::Error>> {
+/// Decodes 1 bit per pixel video memory into `pixels`, one byte per pixel:
+/// 1 when the pixel emits light, 0 otherwise
+pub(crate) fn draw_space_invaders_vram(vram: &[u8], pixels: &mut [u8]) {
     assert_eq!(vram.len(), 0x1BFF);
-    //let mut slice = [[0u8; 224]; 256];
+    assert_eq!(pixels.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
     let mut x = 0usize;
     let mut y = 255usize;
-    tex.with_lock(None, |buf, _pitch| {
-        for byte in vram {
-            for pixel_emit_light in ByteBitIter::new(*byte) {
-                if pixel_emit_light {
-                    buf[y * 224 + x] = screen.white_color;
-                } else {
-                    buf[y * 224 + x] = screen.black_color;
-                }
-                if y == 0 {
-                    x += 1;
-                    y = 255;
-                } else {
-                    y -= 1;
-                }
+    for byte in vram {
+        for pixel_emit_light in ByteBitIter::new(*byte) {
+            pixels[y * SCREEN_WIDTH + x] = pixel_emit_light as u8;
+            if y == 0 {
+                x += 1;
+                y = 255;
+            } else {
+                y -= 1;
             }
         }
-    })?;
-
-    //tex.update(None, &t, 224).unwrap();
-    let canvas_size = canvas.window().size();
-    let new_width = (screen.width as f64 * canvas_size.1 as f64 / screen.height as f64) as u32;
-    canvas.copy(
-        tex,
-        None,
-        Rect::new(
-            canvas_size.0 as i32 / 2 - new_width as i32 / 2,
-            0,
-            new_width,
-            canvas_size.1,
-        ),
-    )?;
-    Ok(())
+    }
 }
 
 struct ByteBitIter {
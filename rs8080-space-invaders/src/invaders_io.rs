@@ -1,49 +1,78 @@
 extern crate rs8080_emulator as emulator;
-#[cfg(feature = "sound")]
-use crate::invaders_sound::AudioCircuit;
-use emulator::DataBus;
+use emulator::{AudioEvent, DataBus, StateError};
 
-#[cfg(feature = "sound")]
-pub(crate) struct SpaceInvadersIO {
-    ports: [u8; 6],
-    shift0: u8,
-    shift1: u8,
-    shift_offset: u8,
-    audio_circuit: AudioCircuit,
+/// Sound effects wired to output ports 3 and 5
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum InvadersSound {
+    UfoHighpitch,
+    Shot,
+    PlayerDie,
+    InvaderDie,
+    Fleet1,
+    Fleet2,
+    Fleet3,
+    Fleet4,
 }
-#[cfg(not(feature = "sound"))]
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct SpaceInvadersIO {
     ports: [u8; 6],
     shift0: u8,
     shift1: u8,
     shift_offset: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    audio_events: Vec<AudioEvent<InvadersSound>>,
+}
+
+impl Default for SpaceInvadersIO {
+    fn default() -> Self {
+        SpaceInvadersIO::new()
+    }
 }
 
 impl SpaceInvadersIO {
-    #[cfg(feature = "sound")]
+    /// Size of state written by [to_bytes](#method.to_bytes)
+    pub const STATE_SIZE: usize = 9;
+
     pub fn new() -> SpaceInvadersIO {
         SpaceInvadersIO {
             ports: [0; 6],
             shift0: 0,
             shift1: 0,
             shift_offset: 0,
-            audio_circuit: AudioCircuit::new(),
+            audio_events: Vec::new(),
         }
     }
 
-    #[cfg(not(feature = "sound"))]
-    pub fn new() -> SpaceInvadersIO {
-        SpaceInvadersIO {
-            ports: [0; 6],
-            shift0: 0,
-            shift1: 0,
-            shift_offset: 0,
-        }
+    /// Returns audio events produced since the previous call
+    pub fn take_audio_events(&mut self) -> Vec<AudioEvent<InvadersSound>> {
+        std::mem::take(&mut self.audio_events)
     }
 
-    #[cfg(feature = "sound")]
-    pub fn get_audio(&self) -> &AudioCircuit {
-        &self.audio_circuit
+    pub fn to_bytes(&self) -> [u8; Self::STATE_SIZE] {
+        let p = &self.ports;
+        [
+            p[0],
+            p[1],
+            p[2],
+            p[3],
+            p[4],
+            p[5],
+            self.shift0,
+            self.shift1,
+            self.shift_offset,
+        ]
+    }
+
+    pub fn load_bytes(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        if bytes.len() < Self::STATE_SIZE {
+            return Err(StateError::Truncated);
+        }
+        self.ports.copy_from_slice(&bytes[..6]);
+        self.shift0 = bytes[6];
+        self.shift1 = bytes[7];
+        self.shift_offset = bytes[8];
+        Ok(())
     }
 
     fn set_shift_offset(&mut self, offset: u8) {
@@ -54,6 +83,13 @@ impl SpaceInvadersIO {
         (((self.shift0 as u16) << 8) | self.shift1 as u16).rotate_left(self.shift_offset as u32)
             as u8
     }
+
+    /// Emits `sound` when `bit` goes from 0 to 1
+    fn play_on_rise(&mut self, old: u8, new: u8, bit: u8, sound: InvadersSound) {
+        if new & (1 << bit) > 0 && old & (1 << bit) == 0 {
+            self.audio_events.push(AudioEvent::Play(sound));
+        }
+    }
 }
 
 impl DataBus for SpaceInvadersIO {
@@ -72,23 +108,17 @@ impl DataBus for SpaceInvadersIO {
                 self.set_shift_offset(value);
             }
             3 => {
-                #[cfg(feature = "sound")]
-                {
-                    if value & 0x1 > 0 && self.ports[3] & 0x1 == 0 {
-                        self.audio_circuit.start_playing_ufo_highpitch();
-                    } else if value & 0x1 == 0 && self.ports[3] & 0x1 > 0 {
-                        self.audio_circuit.stop_playing_ufo_highpitch();
-                    }
-                    if value & 0x2 > 0 && self.ports[3] & 0x2 == 0 {
-                        self.audio_circuit.play_shot();
-                    }
-                    if value & (1 << 2) > 0 && self.ports[3] & (1 << 2) == 0 {
-                        self.audio_circuit.play_player_die();
-                    }
-                    if value & (1 << 3) > 0 && self.ports[3] & (1 << 3) == 0 {
-                        self.audio_circuit.play_invader_die();
-                    }
+                let old = self.ports[3];
+                if value & 0x1 > 0 && old & 0x1 == 0 {
+                    self.audio_events
+                        .push(AudioEvent::Loop(InvadersSound::UfoHighpitch));
+                } else if value & 0x1 == 0 && old & 0x1 > 0 {
+                    self.audio_events
+                        .push(AudioEvent::Stop(InvadersSound::UfoHighpitch));
                 }
+                self.play_on_rise(old, value, 1, InvadersSound::Shot);
+                self.play_on_rise(old, value, 2, InvadersSound::PlayerDie);
+                self.play_on_rise(old, value, 3, InvadersSound::InvaderDie);
                 self.ports[3] = value;
             }
             4 => {
@@ -96,21 +126,11 @@ impl DataBus for SpaceInvadersIO {
                 self.shift1 = value;
             }
             5 => {
-                #[cfg(feature = "sound")]
-                {
-                    if value & (1 << 0) > 0 && self.ports[5] & (1 << 0) == 0 {
-                        self.audio_circuit.play_fleet1();
-                    }
-                    if value & (1 << 1) > 0 && self.ports[5] & (1 << 1) == 0 {
-                        self.audio_circuit.play_fleet2();
-                    }
-                    if value & (1 << 2) > 0 && self.ports[5] & (1 << 2) == 0 {
-                        self.audio_circuit.play_fleet3();
-                    }
-                    if value & (1 << 3) > 0 && self.ports[5] & (1 << 3) == 0 {
-                        self.audio_circuit.play_fleet4();
-                    }
-                }
+                let old = self.ports[5];
+                self.play_on_rise(old, value, 0, InvadersSound::Fleet1);
+                self.play_on_rise(old, value, 1, InvadersSound::Fleet2);
+                self.play_on_rise(old, value, 2, InvadersSound::Fleet3);
+                self.play_on_rise(old, value, 3, InvadersSound::Fleet4);
                 self.ports[5] = value;
             }
             _ => {}
@@ -141,4 +161,21 @@ mod tests {
         io.port_out(3, 2); // set shift_offset to 3
         assert_eq!(io.port_in(3), (0x0DFF >> (8 - 3)) as u8);
     }
+
+    #[test]
+    fn invaders_sound_edges() {
+        let mut io = SpaceInvadersIO::new();
+        io.port_out(0b0000_0011, 3);
+        io.port_out(0b0000_0011, 3);
+        io.port_out(0b0000_0000, 3);
+        assert_eq!(
+            io.take_audio_events(),
+            vec![
+                AudioEvent::Loop(InvadersSound::UfoHighpitch),
+                AudioEvent::Play(InvadersSound::Shot),
+                AudioEvent::Stop(InvadersSound::UfoHighpitch),
+            ]
+        );
+        assert!(io.take_audio_events().is_empty());
+    }
 }
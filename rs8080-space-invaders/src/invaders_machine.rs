@@ -0,0 +1,246 @@
+extern crate rs8080_emulator as emulator;
+use crate::invaders_draw_vram::{draw_space_invaders_vram, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::invaders_io::{InvadersSound, SpaceInvadersIO};
+use crate::invaders_mem_limit::SpaceInvadersLimit;
+use emulator::{AudioEvent, DataBus, Framebuffer, Machine, StateError, RS8080};
+use rs8080_disassembler::Coverage;
+
+/// Cabinet buttons and joysticks
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum InvadersInput {
+    InsertCoin,
+    Start1P,
+    Start2P,
+    Shot1P,
+    Shot2P,
+    Left1P,
+    Left2P,
+    Right1P,
+    Right2P,
+}
+
+impl InvadersInput {
+    /// Returns input port and bit mask wired to the input
+    fn port_bit(self) -> (usize, u8) {
+        match self {
+            InvadersInput::InsertCoin => (1, 0b0000_0001),
+            InvadersInput::Start2P => (1, 0b0000_0010),
+            InvadersInput::Start1P => (1, 0b0000_0100),
+            InvadersInput::Shot1P => (1, 0b0001_0000),
+            InvadersInput::Left1P => (1, 0b0010_0000),
+            InvadersInput::Right1P => (1, 0b0100_0000),
+            InvadersInput::Shot2P => (2, 0b0001_0000),
+            InvadersInput::Left2P => (2, 0b0010_0000),
+            InvadersInput::Right2P => (2, 0b0100_0000),
+        }
+    }
+}
+
+/// Video memory, the rest of RAM is work RAM and stack
+const VRAM: std::ops::Range<usize> = 0x2400..0x3FFF;
+const RAM: std::ops::Range<usize> = 0x2000..0x4000;
+/// 2 MHz = 2 * 10^6 Hz; 500 ns -- 1 cycle; 1/60/(500*10^-9) = 33333.333
+const CYCLES_PER_HALF_FRAME: i32 = 33333 / 2;
+
+/// Taito Space Invaders arcade board
+///
+/// With `serde` feature the whole machine state can be dumped, picture is
+/// not saved and stays blank until the next frame
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct SpaceInvaders {
+    cpu: RS8080<SpaceInvadersIO, SpaceInvadersLimit>,
+    /// Selects interrupt: RST 1 in the middle of the screen, RST 2 at the end
+    flipflop: bool,
+    /// CPU cycles executed since power-on
+    #[cfg_attr(feature = "serde", serde(default))]
+    cycles: u64,
+    #[cfg_attr(feature = "serde", serde(skip, default = "blank_screen"))]
+    pixels: Vec<u8>,
+    /// Executed opcodes, recorded if set
+    #[cfg_attr(feature = "serde", serde(skip))]
+    coverage: Option<Coverage>,
+}
+
+fn blank_screen() -> Vec<u8> {
+    vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]
+}
+
+impl SpaceInvaders {
+    pub fn new() -> SpaceInvaders {
+        SpaceInvaders {
+            cpu: RS8080::new_with_limit(SpaceInvadersIO::new(), SpaceInvadersLimit {}),
+            flipflop: false,
+            cycles: 0,
+            pixels: blank_screen(),
+            coverage: None,
+        }
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut RS8080<SpaceInvadersIO, SpaceInvadersLimit> {
+        &mut self.cpu
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Starts recording executed opcodes into `coverage`
+    pub fn record_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+}
+
+impl Machine for SpaceInvaders {
+    type Input = InvadersInput;
+    type Sound = InvadersSound;
+
+    fn run_frame(&mut self) {
+        for _ in 0..2 {
+            let mut cycles_left = CYCLES_PER_HALF_FRAME;
+            while cycles_left > 0 {
+                let pc = self.cpu.get_pc();
+                let opcode = self.cpu.get_mem().get(pc as usize).copied();
+                let cycles = self.cpu.emulate_next();
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.record(pc, opcode.unwrap_or_default(), self.cpu.get_pc());
+                }
+                cycles_left -= cycles.0 as i32;
+                self.cycles += cycles.0 as u64;
+            }
+            if self.cpu.int_enabled() {
+                if self.flipflop {
+                    self.cpu.call_interrupt(0x10);
+                } else {
+                    self.cpu.call_interrupt(0x8);
+                }
+                self.flipflop = !self.flipflop;
+            }
+        }
+        draw_space_invaders_vram(&self.cpu.get_mem()[VRAM], &mut self.pixels);
+    }
+
+    fn framebuffer(&self) -> Framebuffer<'_> {
+        Framebuffer {
+            width: SCREEN_WIDTH as u32,
+            height: SCREEN_HEIGHT as u32,
+            pixels: &self.pixels,
+        }
+    }
+
+    fn set_input(&mut self, input: InvadersInput, pressed: bool) {
+        let (port, bit) = input.port_bit();
+        let port = self.cpu.get_io_mut().port(port);
+        if pressed {
+            *port |= bit;
+        } else {
+            *port &= !bit;
+        }
+    }
+
+    fn audio_events(&mut self) -> Vec<AudioEvent<InvadersSound>> {
+        self.cpu.get_io_mut().take_audio_events()
+    }
+
+    fn reset(&mut self) {
+        self.cpu.reset();
+        self.cpu.get_mut_mem()[RAM].fill(0);
+        *self.cpu.get_io_mut() = SpaceInvadersIO::new();
+        self.flipflop = false;
+        self.cycles = 0;
+        self.pixels.fill(0);
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::new();
+        self.cpu.save_state(&mut state);
+        state.extend_from_slice(&self.cpu.get_io().to_bytes());
+        state.push(self.flipflop as u8);
+        state
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let cpu_len = self.cpu.load_state(state)?;
+        let rest = &state[cpu_len..];
+        if rest.len() < SpaceInvadersIO::STATE_SIZE + 1 {
+            return Err(StateError::Truncated);
+        }
+        self.cpu.get_io_mut().load_bytes(rest)?;
+        self.flipflop = rest[SpaceInvadersIO::STATE_SIZE] != 0;
+        draw_space_invaders_vram(&self.cpu.get_mem()[VRAM], &mut self.pixels);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_machine() -> SpaceInvaders {
+        let mut machine = SpaceInvaders::new();
+        let cpu = machine.cpu_mut();
+        // LXI SP,$2400; EI; JMP $0004
+        cpu.load_to_mem(&[0x31, 0x00, 0x24, 0xFB, 0xC3, 0x04, 0x00], 0);
+        // RST 1, RST 2: EI; RET
+        cpu.load_to_mem(&[0xFB, 0xC9], 0x08);
+        cpu.load_to_mem(&[0xFB, 0xC9], 0x10);
+        machine
+    }
+
+    #[test]
+    fn invaders_input_ports() {
+        let mut machine = test_machine();
+        machine.set_input(InvadersInput::Shot2P, true);
+        machine.set_input(InvadersInput::InsertCoin, true);
+        assert_eq!(0b0001_0000, machine.cpu_mut().get_io_mut().port_in(2));
+        machine.set_input(InvadersInput::Shot2P, false);
+        assert_eq!(0, machine.cpu_mut().get_io_mut().port_in(2));
+        assert_eq!(1, machine.cpu_mut().get_io_mut().port_in(1));
+    }
+
+    #[test]
+    fn invaders_coverage() {
+        let mut machine = test_machine();
+        machine.record_coverage(Coverage::default());
+        // RST 2 at the end of the first frame runs in the second one
+        machine.run_frame();
+        machine.run_frame();
+        let executed: Vec<u16> = machine.coverage().unwrap().executed().collect();
+        assert_eq!(vec![0, 3, 4, 8, 9, 0x10, 0x11], executed);
+    }
+
+    #[test]
+    fn invaders_state_roundtrip() {
+        let mut machine = test_machine();
+        machine.run_frame();
+        machine.set_input(InvadersInput::Left1P, true);
+        let state = machine.save_state();
+        machine.run_frame();
+        let expected = machine.save_state();
+
+        let mut other = test_machine();
+        other.load_state(&state).unwrap();
+        other.run_frame();
+        assert_eq!(expected, other.save_state());
+
+        other.reset();
+        assert_eq!(0, other.cpu_mut().get_pc());
+        assert!(other.load_state(&state[..state.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn invaders_toml_roundtrip() {
+        let mut machine = test_machine();
+        machine.run_frame();
+        machine.set_input(InvadersInput::Right2P, true);
+        let dump = toml::to_string(&machine).unwrap();
+        let mut other: SpaceInvaders = toml::from_str(&dump).unwrap();
+        machine.run_frame();
+        other.run_frame();
+        assert_eq!(machine.save_state(), other.save_state());
+    }
+}
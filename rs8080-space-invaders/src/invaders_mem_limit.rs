@@ -1,6 +1,7 @@
 extern crate rs8080_emulator as emulator;
 use emulator::{MemLimiter, WriteAction};
 
+#[derive(Default)]
 pub(crate) struct SpaceInvadersLimit {}
 impl MemLimiter for SpaceInvadersLimit {
     fn check_write(&self, adr: u16, _: u8) -> WriteAction {
@@ -1,3 +1,6 @@
+extern crate rs8080_emulator as emulator;
+use crate::invaders_io::InvadersSound;
+use emulator::AudioEvent;
 use sdl2::mixer;
 use sdl2::mixer::{Chunk, LoaderRWops};
 
@@ -73,6 +76,21 @@ impl AudioCircuit {
         }
     }
 
+    pub(crate) fn play(&self, event: AudioEvent<InvadersSound>) {
+        match event {
+            AudioEvent::Loop(InvadersSound::UfoHighpitch) => self.start_playing_ufo_highpitch(),
+            AudioEvent::Stop(InvadersSound::UfoHighpitch) => self.stop_playing_ufo_highpitch(),
+            AudioEvent::Play(InvadersSound::Shot) => self.play_shot(),
+            AudioEvent::Play(InvadersSound::PlayerDie) => self.play_player_die(),
+            AudioEvent::Play(InvadersSound::InvaderDie) => self.play_invader_die(),
+            AudioEvent::Play(InvadersSound::Fleet1) => self.play_fleet1(),
+            AudioEvent::Play(InvadersSound::Fleet2) => self.play_fleet2(),
+            AudioEvent::Play(InvadersSound::Fleet3) => self.play_fleet3(),
+            AudioEvent::Play(InvadersSound::Fleet4) => self.play_fleet4(),
+            _ => {}
+        }
+    }
+
     pub(crate) fn set_volume(&self, volume: u8) {
         mixer::Channel::all().set_volume(volume as i32);
     }
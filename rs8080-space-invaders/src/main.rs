@@ -1,215 +1,204 @@
 extern crate rs8080_emulator as emulator;
 extern crate sdl2;
-use emulator::{DataBus, RS8080};
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::{pixels::PixelFormatEnum, video::FullscreenType};
+use emulator::{Machine, RS8080};
 use std::fs::File;
 use std::io::Write;
-use std::ops::Deref;
-use std::{
-    thread,
-    time::{Duration, Instant},
-};
+use std::path::Path;
 
+mod cli;
 mod config;
+mod frontend;
+mod headless;
 mod invaders_draw_vram;
 mod invaders_io;
+mod invaders_machine;
 mod invaders_mem_limit;
 #[cfg(feature = "sound")]
 mod invaders_sound;
+mod movie;
+mod roms;
 
-use config::{load_config, Config};
-use invaders_draw_vram::draw_space_invaders_vram;
-use invaders_io::SpaceInvadersIO;
-use invaders_mem_limit::SpaceInvadersLimit;
+use cli::Options;
+use config::{load_config, Config, Roms};
+use invaders_machine::{InvadersInput, SpaceInvaders};
+use movie::{Movie, Player, Recorder, RomHash};
+use roms::{DirSource, RomSource, ZipSource};
+use rs8080_disassembler::Coverage;
 
+/// ROM directory or zip from `--rom-dir` or config, bundled ROMs or `./roms` otherwise
+fn rom_source(path: Option<&Path>) -> std::io::Result<Box<dyn RomSource>> {
+    Ok(match path {
+        Some(path) if path.is_file() => Box::new(ZipSource::open(path)?),
+        Some(dir) => Box::new(DirSource::new(dir)),
+        #[cfg(feature = "bundlerom")]
+        None => Box::new(roms::BundledSource),
+        #[cfg(not(feature = "bundlerom"))]
+        None => Box::new(DirSource::new("./roms")),
+    })
+}
+
+/// Finds a known ROM set, patches it and loads it into memory, returns hashes of ROMs
 fn setup_emulator<T: emulator::DataBus, L: emulator::MemLimiter>(
     emu: &mut RS8080<T, L>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    #[cfg(feature = "bundlerom")]
-    {
-        let h = include_bytes!("../../roms/invaders.h");
-        let g = include_bytes!("../../roms/invaders.g");
-        let f = include_bytes!("../../roms/invaders.f");
-        let e = include_bytes!("../../roms/invaders.e");
-        emu.load_to_mem(h, 0);
-        emu.load_to_mem(g, 0x0800);
-        emu.load_to_mem(f, 0x1000);
-        emu.load_to_mem(e, 0x1800);
+    roms: &Roms,
+) -> Result<Vec<RomHash>, Box<dyn std::error::Error>> {
+    let mut source = rom_source(roms.dir.as_deref())?;
+    let mut set = roms::find_rom_set(source.as_mut(), &roms::manifests())?;
+    eprintln!("ROM set: {}", set.manifest.description);
+    for name in &set.unknown {
+        eprintln!(
+            "warning: {} in {} is not part of the set",
+            name,
+            source.location()
+        );
     }
-    #[cfg(not(feature = "bundlerom"))]
-    {
-        let h_err =
-            std::io::Error::new(std::io::ErrorKind::Other, "cannot read './roms/invaders.h'");
-        let g_err =
-            std::io::Error::new(std::io::ErrorKind::Other, "cannot read './roms/invaders.g'");
-        let f_err =
-            std::io::Error::new(std::io::ErrorKind::Other, "cannot read './roms/invaders.f'");
-        let e_err =
-            std::io::Error::new(std::io::ErrorKind::Other, "cannot read './roms/invaders.e'");
-
-        let h = std::fs::read("./roms/invaders.h").or(Err(h_err))?;
-        let g = std::fs::read("./roms/invaders.g").or(Err(g_err))?;
-        let f = std::fs::read("./roms/invaders.f").or(Err(f_err))?;
-        let e = std::fs::read("./roms/invaders.e").or(Err(e_err))?;
-        emu.load_to_mem(&h, 0);
-        emu.load_to_mem(&g, 0x0800);
-        emu.load_to_mem(&f, 0x1000);
-        emu.load_to_mem(&e, 0x1800);
+    for path in &roms.patches {
+        set.patch(&path.display().to_string(), &std::fs::read(path)?)?;
+        eprintln!("patched: {}", path.display());
     }
-    Ok(())
+    set.verify(roms.patched_crc32.as_deref(), roms.patched_sha1.as_deref())?;
+    set.load_into(emu)?;
+    Ok(set.hashes())
 }
 
-// Replace Ok with never type '!'
-fn run_space_invaders_machine(config: Config) -> Result<(), Box<dyn std::error::Error>> {
-    let keycodes = config.controls;
-    let io = SpaceInvadersIO::new();
-    #[cfg(feature = "sound")]
-    {
-        io.get_audio().set_volume(config.volume.volume);
+/// Creates the machine with ROMs loaded, `--load-state` applied and coverage recorded,
+/// ROM options from the command line take precedence over `roms`
+fn new_machine(
+    options: &Options,
+    mut roms: Roms,
+) -> Result<(SpaceInvaders, Vec<RomHash>), Box<dyn std::error::Error>> {
+    if options.rom_dir.is_some() {
+        roms.dir = options.rom_dir.clone();
     }
-    let mut emu = RS8080::new_with_limit(io, SpaceInvadersLimit {});
-    setup_emulator(&mut emu)?;
-
-    let sdl_context = sdl2::init()?;
-    let _audio = sdl_context.audio()?;
-    let video_subsystem = sdl_context.video()?;
-
-    let mut window = video_subsystem
-        .window(
-            "rs8080-space-invaders",
-            config.screen.width,
-            config.screen.height,
-        )
-        .position_centered()
-        .resizable()
-        .build()?;
-    if config.screen.fullscreen {
-        window.set_fullscreen(FullscreenType::Desktop).unwrap();
+    if !options.patches.is_empty() {
+        roms.patches = options.patches.clone();
     }
+    if options.patched_crc32.is_some() || options.patched_sha1.is_some() {
+        roms.patched_crc32 = options.patched_crc32.clone();
+        roms.patched_sha1 = options.patched_sha1.clone();
+    }
+    let mut machine = SpaceInvaders::new();
+    let roms = setup_emulator(machine.cpu_mut(), &roms)?;
+    if let Some(path) = &options.load_state {
+        machine.load_state(&std::fs::read(path)?)?;
+    }
+    if let Some(path) = &options.coverage {
+        let coverage = match std::fs::read_to_string(path) {
+            Ok(text) => {
+                Coverage::parse(&text).map_err(|err| format!("{}:{}", path.display(), err))?
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Coverage::default(),
+            Err(err) => return Err(err.into()),
+        };
+        machine.record_coverage(coverage);
+    }
+    Ok((machine, roms))
+}
 
-    let mut canvas = window.into_canvas().present_vsync().accelerated().build()?;
-    let tc = canvas.texture_creator();
-    let mut texture = tc.create_texture_streaming(PixelFormatEnum::RGB332, 224, 256)?;
-    let mut flipflop = false;
-    let mut event_pump = sdl_context.event_pump()?;
-
-    //let mut fps = 0u64;
-    //let fps_start = Instant::now();
-    'running: loop {
-        let start = Instant::now();
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Window {
-                    win_event: sdl2::event::WindowEvent::Resized(..),
-                    ..
-                } => {
-                    canvas.clear();
-                }
-                Event::KeyDown {
-                    keycode: Some(x), ..
-                } => {
-                    if keycodes.insert_coin.eq(&x) {
-                        *emu.get_io_mut().port(1) |= 0x1;
-                    }
-                    if keycodes.start_1p.eq(&x) {
-                        *emu.get_io_mut().port(1) |= 0b0000_0100;
-                    }
-                    if keycodes.start_2p.eq(&x) {
-                        *emu.get_io_mut().port(1) |= 0b0000_0010;
-                    }
-                    if keycodes.shot_1p.eq(&x) {
-                        *emu.get_io_mut().port(1) |= 0b0001_0000;
-                    }
-                    if keycodes.shot_2p.eq(&x) {
-                        *emu.get_io_mut().port(2) |= 0b0001_0000;
-                    }
-                    if keycodes.left_1p.eq(&x) {
-                        *emu.get_io_mut().port(1) |= 0b0010_0000;
-                    }
-                    if keycodes.left_2p.eq(&x) {
-                        *emu.get_io_mut().port(2) |= 0b0010_0000;
-                    }
-                    if keycodes.right_1p.eq(&x) {
-                        *emu.get_io_mut().port(1) |= 0b0100_0000;
-                    }
-                    if keycodes.right_2p.eq(&x) {
-                        *emu.get_io_mut().port(2) |= 0b0100_0000;
-                    }
+/// Writes `--save-state` and `--coverage` files
+fn save_on_exit(machine: &SpaceInvaders, options: &Options) -> std::io::Result<()> {
+    if let Some(path) = &options.save_state {
+        std::fs::write(path, machine.save_state())?;
+    }
+    if let (Some(path), Some(coverage)) = (&options.coverage, machine.coverage()) {
+        std::fs::write(path, coverage.to_text())?;
+    }
+    Ok(())
+}
 
-                    if Keycode::Escape.eq(&x) {
-                        break 'running;
-                    }
-                }
-                Event::KeyUp {
-                    keycode: Some(x), ..
-                } => {
-                    if keycodes.insert_coin.deref().eq(&x) {
-                        *emu.get_io_mut().port(1) &= !0x1;
-                    }
-                    if keycodes.start_1p.eq(&x) {
-                        *emu.get_io_mut().port(1) &= !0b0000_0100;
-                    }
-                    if keycodes.start_2p.eq(&x) {
-                        *emu.get_io_mut().port(1) &= !0b0000_0010;
-                    }
-                    if keycodes.shot_1p.eq(&x) {
-                        *emu.get_io_mut().port(1) &= !0b0001_0000;
-                    }
-                    if keycodes.shot_2p.eq(&x) {
-                        *emu.get_io_mut().port(2) &= !0b0001_0000;
-                    }
-                    if keycodes.left_1p.eq(&x) {
-                        *emu.get_io_mut().port(1) &= !0b0010_0000;
-                    }
-                    if keycodes.left_2p.eq(&x) {
-                        *emu.get_io_mut().port(2) &= !0b0010_0000;
-                    }
-                    if keycodes.right_1p.eq(&x) {
-                        *emu.get_io_mut().port(1) &= !0b0100_0000;
-                    }
-                    if keycodes.right_2p.eq(&x) {
-                        *emu.get_io_mut().port(2) &= !0b0100_0000;
-                    }
-                }
-                Event::Quit { .. } => break 'running,
-                _ => {}
-            }
-        }
+fn run_space_invaders_machine(
+    config: Config,
+    options: &Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let keycodes = config.controls;
+    let keymap = [
+        (*keycodes.insert_coin, InvadersInput::InsertCoin),
+        (*keycodes.start_1p, InvadersInput::Start1P),
+        (*keycodes.start_2p, InvadersInput::Start2P),
+        (*keycodes.shot_1p, InvadersInput::Shot1P),
+        (*keycodes.shot_2p, InvadersInput::Shot2P),
+        (*keycodes.left_1p, InvadersInput::Left1P),
+        (*keycodes.left_2p, InvadersInput::Left2P),
+        (*keycodes.right_1p, InvadersInput::Right1P),
+        (*keycodes.right_2p, InvadersInput::Right2P),
+    ];
+    let (mut machine, roms) = new_machine(options, config.roms)?;
+    let mut recorder = options
+        .record
+        .as_ref()
+        .map(|_| Recorder::new(&mut machine, roms.clone(), options.load_state.is_some()));
+    let mut player = match &options.play {
+        Some(path) => Some(Player::new(Movie::load(path)?, &mut machine, &roms)?),
+        None => None,
+    };
+    // keyboard is ignored while a movie plays
+    let keymap: &[_] = if player.is_some() { &[] } else { &keymap };
 
-        // 2 MHz = 2 * 10^6 Hz; 500 ns -- 1 cycle; 1/60/(500*10^-9) = 33333.333
-        for _ in 0..2 {
-            let mut cycles_left = 33333i32 / 2;
-            while cycles_left > 0 {
-                let cycles = emu.emulate_next();
-                cycles_left -= cycles.0 as i32;
+    #[cfg(feature = "sound")]
+    let audio = {
+        let audio = invaders_sound::AudioCircuit::new();
+        audio.set_volume(config.volume.volume);
+        audio
+    };
+    frontend::run(
+        &mut machine,
+        "rs8080-space-invaders",
+        keymap,
+        &config.screen,
+        |_event| {
+            #[cfg(feature = "sound")]
+            audio.play(_event);
+        },
+        |machine| {
+            if let Some(recorder) = &mut recorder {
+                recorder.record(machine);
             }
-            if emu.int_enabled() {
-                if flipflop {
-                    emu.call_interrupt(0x10);
-                } else {
-                    emu.call_interrupt(0x8);
-                }
-                flipflop = !flipflop;
+            match &mut player {
+                Some(player) => Ok(player.play(machine)?),
+                None => Ok(true),
             }
-        }
-        draw_space_invaders_vram(
-            &mut canvas,
-            &mut texture,
-            &emu.get_mem()[0x2400..0x3FFF],
-            &config.screen,
-        )?;
-        canvas.present();
-        let elapsed = start.elapsed();
-        //println!("End of frame reached after {:?} ms", elapsed.as_millis());
-        if elapsed <= Duration::from_secs_f64(1f64 / 60f64) {
-            thread::sleep(Duration::from_secs_f64(1f64 / 60f64) - elapsed);
-        }
-        // println!("fps = {}", fps as f64 / fps_start.elapsed().as_secs_f64());
-        // fps += 1;
+        },
+    )?;
+
+    save_on_exit(&machine, options)?;
+    if let (Some(recorder), Some(path)) = (recorder, &options.record) {
+        recorder.finish(&machine).save(path)?;
     }
+    if let Some(player) = player.filter(|player| player.finished()) {
+        player.verify(&machine)?;
+        println!("movie ok, frames: {}", player.frames());
+    }
+    Ok(())
+}
 
+/// Runs the machine without a window and prints hash of its final state
+fn run_space_invaders_headless(
+    frames: u64,
+    options: &Options,
+    roms: Roms,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut machine, _) = new_machine(options, roms)?;
+    let hash = headless::run_headless(&mut machine, frames);
+    save_on_exit(&machine, options)?;
+    println!("frames: {}, state hash: {:016x}", frames, hash);
+    Ok(())
+}
+
+/// Plays a movie without a window and checks that it ends in the recorded state
+fn replay_movie_headless(
+    path: &std::path::Path,
+    options: &Options,
+    roms: Roms,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut machine, roms) = new_machine(options, roms)?;
+    let mut player = Player::new(Movie::load(path)?, &mut machine, &roms)?;
+    while player.play(&mut machine)? {
+        machine.run_frame();
+        machine.audio_events();
+    }
+    player.verify(&machine)?;
+    save_on_exit(&machine, options)?;
+    println!("movie ok, frames: {}", player.frames());
     Ok(())
 }
 
@@ -230,10 +219,20 @@ macro_rules! handle_err {
 }
 
 fn main() {
+    let options = handle_err!(cli::parse(std::env::args().skip(1)));
     let default_config = include_bytes!("../../config.default.toml");
     if !std::path::Path::new("config.toml").exists() {
         handle_err!(handle_err!(File::create("config.toml")).write_all(default_config));
     }
     let config = handle_err!(load_config("config.toml"));
-    handle_err!(run_space_invaders_machine(config));
+    // headless runs use the same ROM set as the window, so movies replay alike
+    if let Some(path) = &options.replay {
+        handle_err!(replay_movie_headless(path, &options, config.roms));
+        return;
+    }
+    if let Some(frames) = options.headless {
+        handle_err!(run_space_invaders_headless(frames, &options, config.roms));
+        return;
+    }
+    handle_err!(run_space_invaders_machine(config, &options));
 }
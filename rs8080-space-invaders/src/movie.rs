@@ -0,0 +1,396 @@
+extern crate rs8080_emulator as emulator;
+use crate::headless::state_hash;
+use crate::invaders_machine::SpaceInvaders;
+use emulator::{DataBus, Machine, StateError};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Version of the movie file format
+const MOVIE_VERSION: u32 = 1;
+/// Input ports which are recorded
+const INPUT_PORTS: [u8; 2] = [1, 2];
+
+/// Recorded game session: ports 1 and 2 changes, stamped with frame and cycle
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct Movie {
+    pub version: u32,
+    /// Number of recorded frames
+    pub frames: u64,
+    /// Hash of the machine state after the last frame, see `headless::state_hash`
+    pub final_hash: String,
+    pub start: MovieStart,
+    pub roms: Vec<RomHash>,
+    #[serde(default)]
+    pub events: Vec<InputEvent>,
+}
+
+/// State the movie starts from
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MovieStart {
+    /// Freshly reset machine
+    PowerOn,
+    /// Hex encoded `Machine::save_state`
+    Snapshot(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub(crate) struct RomHash {
+    pub name: String,
+    /// CRC32 in hex
+    pub crc32: String,
+}
+
+impl RomHash {
+    pub fn new(name: &str, rom: &[u8]) -> RomHash {
+        RomHash {
+            name: name.to_owned(),
+            crc32: format!("{:08x}", crc32fast::hash(rom)),
+        }
+    }
+}
+
+/// New `value` of input `port`, written before `frame` started.
+/// `cycle` counts CPU cycles from the start of the movie
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
+pub(crate) struct InputEvent {
+    pub frame: u64,
+    pub cycle: u64,
+    pub port: u8,
+    pub value: u8,
+}
+
+#[derive(Debug)]
+pub(crate) enum MovieError {
+    UnsupportedVersion(u32),
+    RomMismatch {
+        expected: RomHash,
+        found: RomHash,
+    },
+    RomCount {
+        expected: usize,
+        found: usize,
+    },
+    BadSnapshot,
+    State(StateError),
+    /// Machine reached `frame` at a different cycle than when recorded
+    Desync {
+        frame: u64,
+        expected: u64,
+        found: u64,
+    },
+    /// State after the last frame does not match
+    HashMismatch {
+        expected: String,
+        found: String,
+    },
+}
+
+impl std::fmt::Display for MovieError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MovieError::UnsupportedVersion(v) => write!(f, "unsupported movie version {}", v),
+            MovieError::RomMismatch { expected, found } => write!(
+                f,
+                "movie was recorded with {} (crc32 {}), loaded {} (crc32 {})",
+                expected.name, expected.crc32, found.name, found.crc32
+            ),
+            MovieError::RomCount { expected, found } => write!(
+                f,
+                "movie was recorded with {} ROMs, loaded {}",
+                expected, found
+            ),
+            MovieError::BadSnapshot => write!(f, "movie snapshot is not a valid hex string"),
+            MovieError::State(err) => write!(f, "cannot load movie snapshot: {}", err),
+            MovieError::Desync {
+                frame,
+                expected,
+                found,
+            } => write!(
+                f,
+                "desync at frame {}: expected cycle {}, found {}",
+                frame, expected, found
+            ),
+            MovieError::HashMismatch { expected, found } => write!(
+                f,
+                "final state hash mismatch: expected {}, found {}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(err: StateError) -> Self {
+        MovieError::State(err)
+    }
+}
+
+impl Movie {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Movie, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+/// Records inputs of a running machine
+pub(crate) struct Recorder {
+    movie: Movie,
+    ports: [u8; 2],
+    start_cycles: u64,
+}
+
+impl Recorder {
+    /// Starts recording from the current state of `machine`.
+    /// Without `snapshot` the machine is reset first
+    pub fn new(machine: &mut SpaceInvaders, roms: Vec<RomHash>, snapshot: bool) -> Recorder {
+        let start = if snapshot {
+            MovieStart::Snapshot(to_hex(&machine.save_state()))
+        } else {
+            machine.reset();
+            MovieStart::PowerOn
+        };
+        Recorder {
+            movie: Movie {
+                version: MOVIE_VERSION,
+                frames: 0,
+                final_hash: String::new(),
+                start,
+                roms,
+                events: Vec::new(),
+            },
+            ports: input_ports(machine),
+            start_cycles: machine.cycles(),
+        }
+    }
+
+    /// Stores port changes made since the previous frame, call before every frame
+    pub fn record(&mut self, machine: &mut SpaceInvaders) {
+        let ports = input_ports(machine);
+        for ((port, old), new) in INPUT_PORTS.iter().zip(self.ports).zip(ports) {
+            if old != new {
+                self.movie.events.push(InputEvent {
+                    frame: self.movie.frames,
+                    cycle: machine.cycles() - self.start_cycles,
+                    port: *port,
+                    value: new,
+                });
+            }
+        }
+        self.ports = ports;
+        self.movie.frames += 1;
+    }
+
+    /// Stops recording, the movie ends with the current state of `machine`
+    pub fn finish(mut self, machine: &SpaceInvaders) -> Movie {
+        self.movie.final_hash = format!("{:016x}", state_hash(&machine.save_state()));
+        self.movie
+    }
+}
+
+/// Feeds recorded inputs back to a machine
+pub(crate) struct Player {
+    movie: Movie,
+    frame: u64,
+    next_event: usize,
+    start_cycles: u64,
+}
+
+impl Player {
+    /// Checks ROM hashes and brings `machine` to the start of the movie
+    pub fn new(
+        movie: Movie,
+        machine: &mut SpaceInvaders,
+        roms: &[RomHash],
+    ) -> Result<Player, MovieError> {
+        if movie.version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(movie.version));
+        }
+        if movie.roms.len() != roms.len() {
+            return Err(MovieError::RomCount {
+                expected: movie.roms.len(),
+                found: roms.len(),
+            });
+        }
+        if let Some((expected, found)) = movie.roms.iter().zip(roms).find(|(a, b)| a != b) {
+            return Err(MovieError::RomMismatch {
+                expected: expected.clone(),
+                found: found.clone(),
+            });
+        }
+        match &movie.start {
+            MovieStart::PowerOn => machine.reset(),
+            MovieStart::Snapshot(hex) => {
+                machine.load_state(&from_hex(hex).ok_or(MovieError::BadSnapshot)?)?
+            }
+        }
+        Ok(Player {
+            movie,
+            frame: 0,
+            next_event: 0,
+            start_cycles: machine.cycles(),
+        })
+    }
+
+    /// Applies inputs of the next frame, call before every frame.
+    /// Returns `false` when all frames were played
+    pub fn play(&mut self, machine: &mut SpaceInvaders) -> Result<bool, MovieError> {
+        if self.finished() {
+            return Ok(false);
+        }
+        while let Some(event) = self.movie.events.get(self.next_event) {
+            if event.frame != self.frame {
+                break;
+            }
+            let cycle = machine.cycles() - self.start_cycles;
+            if cycle != event.cycle {
+                return Err(MovieError::Desync {
+                    frame: self.frame,
+                    expected: event.cycle,
+                    found: cycle,
+                });
+            }
+            *machine.cpu_mut().get_io_mut().port(event.port as usize) = event.value;
+            self.next_event += 1;
+        }
+        self.frame += 1;
+        Ok(true)
+    }
+
+    /// Compares state of `machine` with the one recorded at the end of the movie
+    pub fn verify(&self, machine: &SpaceInvaders) -> Result<(), MovieError> {
+        let found = format!("{:016x}", state_hash(&machine.save_state()));
+        if found != self.movie.final_hash {
+            return Err(MovieError::HashMismatch {
+                expected: self.movie.final_hash.clone(),
+                found,
+            });
+        }
+        Ok(())
+    }
+
+    /// All frames of the movie were played
+    pub fn finished(&self) -> bool {
+        self.frame >= self.movie.frames
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.movie.frames
+    }
+}
+
+fn input_ports(machine: &mut SpaceInvaders) -> [u8; 2] {
+    let io = machine.cpu_mut().get_io_mut();
+    INPUT_PORTS.map(|port| *io.port(port as usize))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    // only hex digits, `from_str_radix` would take "+1"
+    let digit = |d: u8| (d as char).to_digit(16);
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| Some((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::invaders_machine::InvadersInput;
+
+    fn test_machine() -> SpaceInvaders {
+        let mut machine = SpaceInvaders::new();
+        let cpu = machine.cpu_mut();
+        cpu.load_to_mem(&[0xC3, 0x20, 0x00], 0);
+        cpu.load_to_mem(&[0xFB, 0xC9], 0x08);
+        cpu.load_to_mem(&[0xFB, 0xC9], 0x10);
+        // LXI SP,$2400; LXI H,$2400; EI
+        // loop: IN 1; MOV M,A; INX H; MOV A,H; ANI $27; ORI $24; MOV H,A; JMP loop
+        cpu.load_to_mem(
+            &[
+                0x31, 0x00, 0x24, 0x21, 0x00, 0x24, 0xFB, 0xDB, 0x01, 0x77, 0x23, 0x7C, 0xE6,
+                0x27, 0xF6, 0x24, 0x67, 0xC3, 0x27, 0x00,
+            ],
+            0x20,
+        );
+        machine
+    }
+
+    fn record(snapshot: bool) -> Movie {
+        let mut machine = test_machine();
+        machine.run_frame();
+        let roms = vec![RomHash::new("test", &machine.cpu_mut().get_mem()[..0x10])];
+        let mut recorder = Recorder::new(&mut machine, roms, snapshot);
+        for frame in 0..10 {
+            machine.set_input(InvadersInput::Left1P, frame % 3 == 0);
+            machine.set_input(InvadersInput::Shot2P, frame == 5);
+            recorder.record(&mut machine);
+            machine.run_frame();
+        }
+        recorder.finish(&machine)
+    }
+
+    fn replay(movie: Movie) -> Result<(), MovieError> {
+        let mut machine = test_machine();
+        let roms = vec![RomHash::new("test", &machine.cpu_mut().get_mem()[..0x10])];
+        let mut player = Player::new(movie, &mut machine, &roms)?;
+        while player.play(&mut machine)? {
+            machine.run_frame();
+        }
+        player.verify(&machine)
+    }
+
+    #[test]
+    fn movie_replay() {
+        for snapshot in [false, true] {
+            let movie = record(snapshot);
+            assert_eq!(snapshot, matches!(movie.start, MovieStart::Snapshot(_)));
+            assert_eq!(10, movie.frames);
+            assert_eq!(9, movie.events.len());
+            let text = toml::to_string(&movie).unwrap();
+            assert_eq!(movie, toml::from_str(&text).unwrap());
+            replay(movie).unwrap();
+        }
+    }
+
+    #[test]
+    fn movie_detects_mismatch() {
+        let mut movie = record(false);
+        movie.events.last_mut().unwrap().value ^= 0x80;
+        assert!(matches!(
+            replay(movie),
+            Err(MovieError::HashMismatch { .. })
+        ));
+
+        let mut movie = record(false);
+        movie.events[1].cycle += 1;
+        assert!(matches!(replay(movie), Err(MovieError::Desync { .. })));
+
+        let mut movie = record(false);
+        movie.roms[0].crc32 = "00000000".into();
+        assert!(matches!(replay(movie), Err(MovieError::RomMismatch { .. })));
+    }
+
+    #[test]
+    fn movie_hex() {
+        assert_eq!("00ff1a", to_hex(&[0, 0xFF, 0x1A]));
+        assert_eq!(Some(vec![0, 0xFF, 0x1A]), from_hex("00ff1a"));
+        assert_eq!(None, from_hex("0f1"));
+        assert_eq!(None, from_hex("zz"));
+        assert_eq!(None, from_hex("+1"));
+    }
+}
@@ -0,0 +1,629 @@
+extern crate rs8080_emulator as emulator;
+use crate::movie::RomHash;
+use emulator::loader::{LoadError, Patch};
+use emulator::{DataBus, MemLimiter, RS8080};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Built-in ROM set manifests
+const MANIFESTS: [&str; 1] = [include_str!("../manifests/invaders.toml")];
+
+/// ROM set: files and where they are loaded
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct Manifest {
+    pub name: String,
+    pub description: String,
+    pub files: Vec<RomFile>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct RomFile {
+    pub name: String,
+    pub address: u16,
+    pub size: usize,
+    /// CRC32 in hex
+    pub crc32: String,
+    /// SHA-1 in hex
+    pub sha1: String,
+}
+
+impl RomFile {
+    fn crc32(&self) -> Option<u32> {
+        u32::from_str_radix(&self.crc32, 16).ok()
+    }
+
+    /// Describes the first difference between `data` and this file
+    fn verify(&self, data: &[u8]) -> Option<RomProblem> {
+        let name = self.name.clone();
+        if data.len() != self.size {
+            return Some(RomProblem::Size {
+                name,
+                found: data.len(),
+                expected: self.size,
+            });
+        }
+        let crc32 = format!("{:08x}", crc32fast::hash(data));
+        if !crc32.eq_ignore_ascii_case(&self.crc32) {
+            return Some(RomProblem::Hash {
+                name,
+                kind: "CRC32",
+                found: crc32,
+                expected: self.crc32.clone(),
+            });
+        }
+        let sha1 = sha1_smol::Sha1::from(data).digest().to_string();
+        if !sha1.eq_ignore_ascii_case(&self.sha1) {
+            return Some(RomProblem::Hash {
+                name,
+                kind: "SHA-1",
+                found: sha1,
+                expected: self.sha1.clone(),
+            });
+        }
+        None
+    }
+}
+
+/// Parses built-in manifests
+pub(crate) fn manifests() -> Vec<Manifest> {
+    MANIFESTS
+        .iter()
+        .map(|text| toml::from_str(text).expect("built-in manifest is valid"))
+        .collect()
+}
+
+/// Place ROM files are read from
+pub(crate) trait RomSource {
+    /// Location shown in messages
+    fn location(&self) -> String;
+    /// Reads `file` of `set`, `Ok(None)` if there is no such file
+    fn read(&mut self, set: &Manifest, file: &RomFile) -> io::Result<Option<Vec<u8>>>;
+    /// Files that are not part of `set`, shown in messages
+    fn unknown(&mut self, _set: &Manifest) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Directory with ROM files named as in the manifest,
+/// or with a zip named after the set, e.g. `invaders.zip`
+pub(crate) struct DirSource {
+    dir: PathBuf,
+    /// Opened zips by set name, `None` if there is no zip
+    zips: HashMap<String, Option<ZipSource>>,
+}
+
+impl DirSource {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> DirSource {
+        DirSource {
+            dir: dir.into(),
+            zips: HashMap::new(),
+        }
+    }
+
+    fn zip(&mut self, set: &Manifest) -> io::Result<Option<&mut ZipSource>> {
+        if !self.zips.contains_key(&set.name) {
+            let path = self.dir.join(format!("{}.zip", set.name));
+            let zip = match File::open(&path) {
+                Ok(file) => Some(ZipSource::new(path.display().to_string(), file)?),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err),
+            };
+            self.zips.insert(set.name.clone(), zip);
+        }
+        Ok(self.zips.get_mut(&set.name).and_then(Option::as_mut))
+    }
+}
+
+impl RomSource for DirSource {
+    fn location(&self) -> String {
+        format!("'{}'", self.dir.display())
+    }
+
+    fn read(&mut self, set: &Manifest, file: &RomFile) -> io::Result<Option<Vec<u8>>> {
+        match std::fs::read(self.dir.join(&file.name)) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => match self.zip(set)? {
+                Some(zip) => zip.read(set, file),
+                None => Ok(None),
+            },
+            Err(err) => Err(err),
+        }
+    }
+
+    fn unknown(&mut self, set: &Manifest) -> Vec<String> {
+        match self.zips.get_mut(&set.name) {
+            Some(Some(zip)) => zip.unknown(set),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// MAME-style zipped ROM set, members are matched by CRC32 first and by name
+/// otherwise, so renamed files are still found
+pub(crate) struct ZipSource<R = File> {
+    location: String,
+    archive: ZipArchive<R>,
+}
+
+impl ZipSource {
+    pub fn open(path: &Path) -> io::Result<ZipSource> {
+        ZipSource::new(path.display().to_string(), File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> ZipSource<R> {
+    pub fn new(location: String, reader: R) -> io::Result<ZipSource<R>> {
+        let archive = ZipArchive::new(reader).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("'{}' is not a valid zip: {}", location, err),
+            )
+        })?;
+        Ok(ZipSource { location, archive })
+    }
+
+    /// Base name and CRC32 of every file in the archive
+    fn members(&mut self) -> Vec<(usize, String, u32)> {
+        (0..self.archive.len())
+            .filter_map(|i| {
+                let member = self.archive.by_index_raw(i).ok()?;
+                if member.is_dir() {
+                    return None;
+                }
+                let name = member.name().rsplit('/').next().unwrap_or_default();
+                Some((i, name.to_owned(), member.crc32()))
+            })
+            .collect()
+    }
+}
+
+impl<R: Read + Seek> RomSource for ZipSource<R> {
+    fn location(&self) -> String {
+        format!("'{}'", self.location)
+    }
+
+    fn read(&mut self, _set: &Manifest, file: &RomFile) -> io::Result<Option<Vec<u8>>> {
+        let members = self.members();
+        let found = members
+            .iter()
+            .find(|(_, _, crc32)| Some(*crc32) == file.crc32())
+            .or_else(|| {
+                members
+                    .iter()
+                    .find(|(_, name, _)| name.eq_ignore_ascii_case(&file.name))
+            });
+        let Some((index, _, _)) = found else {
+            return Ok(None);
+        };
+        let mut data = Vec::new();
+        self.archive.by_index(*index)?.read_to_end(&mut data)?;
+        Ok(Some(data))
+    }
+
+    fn unknown(&mut self, set: &Manifest) -> Vec<String> {
+        self.members()
+            .into_iter()
+            .filter(|(_, name, crc32)| {
+                !set.files.iter().any(|file| {
+                    file.crc32() == Some(*crc32) || file.name.eq_ignore_ascii_case(name)
+                })
+            })
+            .map(|(_, name, crc32)| format!("{} (CRC32 {:08x})", name, crc32))
+            .collect()
+    }
+}
+
+/// ROMs included in the binary with `bundlerom` feature
+#[cfg(feature = "bundlerom")]
+pub(crate) struct BundledSource;
+
+#[cfg(feature = "bundlerom")]
+impl RomSource for BundledSource {
+    fn location(&self) -> String {
+        "bundled ROMs".to_owned()
+    }
+
+    fn read(&mut self, _set: &Manifest, file: &RomFile) -> io::Result<Option<Vec<u8>>> {
+        let bundled: [(&str, &[u8]); 4] = [
+            ("invaders.h", include_bytes!("../../roms/invaders.h")),
+            ("invaders.g", include_bytes!("../../roms/invaders.g")),
+            ("invaders.f", include_bytes!("../../roms/invaders.f")),
+            ("invaders.e", include_bytes!("../../roms/invaders.e")),
+        ];
+        Ok(bundled
+            .iter()
+            .find(|(name, _)| *name == file.name)
+            .map(|(_, data)| data.to_vec()))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum RomProblem {
+    Missing(String),
+    Size {
+        name: String,
+        found: usize,
+        expected: usize,
+    },
+    Hash {
+        name: String,
+        kind: &'static str,
+        found: String,
+        expected: String,
+    },
+}
+
+impl Display for RomProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RomProblem::Missing(name) => write!(f, "{}: missing", name),
+            RomProblem::Size {
+                name,
+                found,
+                expected,
+            } => write!(
+                f,
+                "{}: size is {} bytes, expected {}",
+                name, found, expected
+            ),
+            RomProblem::Hash {
+                name,
+                kind,
+                found,
+                expected,
+            } => write!(f, "{}: {} is {}, expected {}", name, kind, found, expected),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum RomError {
+    /// No set matched, `problems` and `unknown` files are of the closest one
+    NoMatch {
+        location: String,
+        set: String,
+        problems: Vec<RomProblem>,
+        unknown: Vec<String>,
+    },
+    Io(io::Error),
+    Load(LoadError),
+    Patch {
+        name: String,
+        reason: String,
+    },
+    /// Patched ROMs differ from the expected hash
+    Patched(RomProblem),
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::NoMatch {
+                location,
+                set,
+                problems,
+                unknown,
+            } => {
+                write!(
+                    f,
+                    "no known ROM set found in {}, the closest is {}:",
+                    location, set
+                )?;
+                for problem in problems {
+                    write!(f, "\n    {}", problem)?;
+                }
+                for name in unknown {
+                    write!(f, "\n    {}: not part of the set", name)?;
+                }
+                Ok(())
+            }
+            RomError::Io(err) => write!(f, "cannot read ROM: {}", err),
+            RomError::Load(err) => write!(f, "cannot load ROM: {}", err),
+            RomError::Patch { name, reason } => {
+                write!(f, "cannot apply patch {}: {}", name, reason)
+            }
+            RomError::Patched(problem) => write!(f, "patched ROMs do not match: {}", problem),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// Verified ROM files
+pub(crate) struct RomSet {
+    pub manifest: Manifest,
+    pub roms: Vec<(RomFile, Vec<u8>)>,
+    /// Files in the source that are not part of the set
+    pub unknown: Vec<String>,
+}
+
+impl RomSet {
+    pub fn load_into<IO: DataBus, LIM: MemLimiter>(
+        &self,
+        cpu: &mut RS8080<IO, LIM>,
+    ) -> Result<(), RomError> {
+        for (file, data) in &self.roms {
+            cpu.try_load_to_mem(data, file.address)
+                .map_err(RomError::Load)?;
+        }
+        Ok(())
+    }
+
+    /// Files joined in address order with gaps filled by zeros, and the first address
+    fn image(&self) -> (u16, Vec<u8>) {
+        let start = self.roms.iter().map(|(file, _)| file.address).min();
+        let start = start.unwrap_or_default();
+        let mut image = Vec::new();
+        for (file, data) in &self.roms {
+            let at = (file.address - start) as usize;
+            if image.len() < at + data.len() {
+                image.resize(at + data.len(), 0);
+            }
+            image[at..at + data.len()].copy_from_slice(data);
+        }
+        (start, image)
+    }
+
+    /// Applies `patch` to the joined files, the patch must keep their size
+    pub fn patch(&mut self, name: &str, patch: &[u8]) -> Result<(), RomError> {
+        let error = |reason: String| RomError::Patch {
+            name: name.to_owned(),
+            reason,
+        };
+        let (start, image) = self.image();
+        let patched = Patch::parse(patch)
+            .and_then(|patch| patch.apply(&image))
+            .map_err(|err| error(err.to_string()))?;
+        if patched.len() != image.len() {
+            return Err(error(format!(
+                "size changes from {} to {} bytes",
+                image.len(),
+                patched.len()
+            )));
+        }
+        for (file, data) in &mut self.roms {
+            let at = (file.address - start) as usize;
+            let len = data.len();
+            data.copy_from_slice(&patched[at..at + len]);
+        }
+        Ok(())
+    }
+
+    /// Checks hashes of the joined files, e.g. after patching
+    pub fn verify(&self, crc32: Option<&str>, sha1: Option<&str>) -> Result<(), RomError> {
+        let (_, image) = self.image();
+        let hashes = [
+            ("CRC32", crc32, format!("{:08x}", crc32fast::hash(&image))),
+            (
+                "SHA-1",
+                sha1,
+                sha1_smol::Sha1::from(&image).digest().to_string(),
+            ),
+        ];
+        for (kind, expected, found) in hashes {
+            match expected {
+                Some(expected) if !found.eq_ignore_ascii_case(expected) => {
+                    return Err(RomError::Patched(RomProblem::Hash {
+                        name: "image".to_owned(),
+                        kind,
+                        found,
+                        expected: expected.to_owned(),
+                    }))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub fn hashes(&self) -> Vec<RomHash> {
+        self.roms
+            .iter()
+            .map(|(file, data)| RomHash::new(&file.name, data))
+            .collect()
+    }
+}
+
+/// Finds the first of `manifests` whose files are all present in `source` and intact
+pub(crate) fn find_rom_set(
+    source: &mut dyn RomSource,
+    manifests: &[Manifest],
+) -> Result<RomSet, RomError> {
+    let mut closest: Option<(&Manifest, Vec<RomProblem>)> = None;
+    for manifest in manifests {
+        let mut roms = Vec::new();
+        let mut problems = Vec::new();
+        for file in &manifest.files {
+            match source.read(manifest, file).map_err(RomError::Io)? {
+                None => problems.push(RomProblem::Missing(file.name.clone())),
+                Some(data) => match file.verify(&data) {
+                    Some(problem) => problems.push(problem),
+                    None => roms.push((file.clone(), data)),
+                },
+            }
+        }
+        if problems.is_empty() {
+            return Ok(RomSet {
+                manifest: manifest.clone(),
+                roms,
+                unknown: source.unknown(manifest),
+            });
+        }
+        if closest
+            .as_ref()
+            .is_none_or(|(_, best)| problems.len() < best.len())
+        {
+            closest = Some((manifest, problems));
+        }
+    }
+    let (set, problems, unknown) = match closest {
+        Some((m, problems)) => (
+            format!("'{}' ({})", m.name, m.description),
+            problems,
+            source.unknown(m),
+        ),
+        None => Default::default(),
+    };
+    Err(RomError::NoMatch {
+        location: source.location(),
+        set,
+        problems,
+        unknown,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    struct MemSource(HashMap<String, Vec<u8>>);
+
+    impl RomSource for MemSource {
+        fn location(&self) -> String {
+            "memory".to_owned()
+        }
+
+        fn read(&mut self, _set: &Manifest, file: &RomFile) -> io::Result<Option<Vec<u8>>> {
+            Ok(self.0.get(&file.name).cloned())
+        }
+    }
+
+    fn manifest() -> Manifest {
+        toml::from_str(
+            r#"
+            name = "test"
+            description = "Test set"
+            [[files]]
+            name = "a.bin"
+            address = 0x10
+            size = 3
+            crc32 = "55bc801d"
+            sha1 = "7037807198c22a7d2b0807371d763779a84fdfcf"
+            [[files]]
+            name = "b.bin"
+            address = 0x20
+            size = 1
+            crc32 = "d202ef8d"
+            sha1 = "5ba93c9db0cff93f52b521d7420e43f6eda2784f"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn roms_builtin_manifests() {
+        let manifests = manifests();
+        assert_eq!("invaders", manifests[0].name);
+        assert_eq!(0x1800, manifests[0].files[3].address);
+    }
+
+    #[test]
+    fn roms_find_set() {
+        let mut files = HashMap::new();
+        files.insert("a.bin".to_owned(), vec![1, 2, 3]);
+        files.insert("b.bin".to_owned(), vec![0]);
+        let set = find_rom_set(&mut MemSource(files.clone()), &[manifest()]).unwrap();
+        assert_eq!("test", set.manifest.name);
+        assert_eq!(2, set.roms.len());
+
+        files.remove("b.bin");
+        files.insert("a.bin".to_owned(), vec![1, 2, 4]);
+        let err = find_rom_set(&mut MemSource(files.clone()), &[manifest()]);
+        match err {
+            Err(RomError::NoMatch { problems, .. }) => {
+                assert_eq!(RomProblem::Missing("b.bin".into()), problems[1]);
+                assert!(matches!(
+                    problems[0],
+                    RomProblem::Hash { kind: "CRC32", .. }
+                ));
+            }
+            _ => panic!("set should not match"),
+        }
+
+        files.insert("a.bin".to_owned(), vec![1, 2]);
+        let err = find_rom_set(&mut MemSource(files), &[manifest()])
+            .err()
+            .unwrap();
+        assert_eq!(
+            "no known ROM set found in memory, the closest is 'test' (Test set):\n    \
+             a.bin: size is 2 bytes, expected 3\n    b.bin: missing",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn roms_patch() {
+        let mut files = HashMap::new();
+        files.insert("a.bin".to_owned(), vec![1, 2, 3]);
+        files.insert("b.bin".to_owned(), vec![0]);
+        let mut set = find_rom_set(&mut MemSource(files), &[manifest()]).unwrap();
+        // b.bin is 0x10 bytes after the start of a.bin
+        set.patch("b.ips", b"PATCH\x00\x00\x10\x00\x01\x07EOF")
+            .unwrap();
+        assert_eq!(vec![7], set.roms[1].1);
+        assert!(set
+            .verify(
+                Some("46F0E047"),
+                Some("c9b81dabf6c68eb8f1ddf3bdd2b0201a95088cbd")
+            )
+            .is_ok());
+        assert_eq!(
+            "patched ROMs do not match: image: CRC32 is 46f0e047, expected 00000000",
+            set.verify(Some("00000000"), None)
+                .err()
+                .unwrap()
+                .to_string()
+        );
+        assert!(set.verify(None, Some("0")).is_err());
+
+        let err = set.patch("c.ips", b"PATCH\x00\x00\x11\x00\x01\x07EOF");
+        assert_eq!(
+            "cannot apply patch c.ips: size changes from 17 to 18 bytes",
+            err.err().unwrap().to_string()
+        );
+        assert!(set.patch("d.ips", b"PTCH").is_err());
+    }
+
+    fn zip(files: &[(&str, &[u8])]) -> ZipSource<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, data) in files {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+        ZipSource::new("test.zip".to_owned(), Cursor::new(bytes)).unwrap()
+    }
+
+    #[test]
+    fn roms_zip_source() {
+        // b.bin is renamed, but found by CRC32
+        let mut source = zip(&[
+            ("test/A.BIN", &[1, 2, 3]),
+            ("renamed.bin", &[0]),
+            ("readme.txt", b"hi"),
+        ]);
+        let set = find_rom_set(&mut source, &[manifest()]).unwrap();
+        assert_eq!(vec![0], set.roms[1].1);
+        assert_eq!(vec!["readme.txt (CRC32 d8932aac)".to_owned()], set.unknown);
+
+        let mut source = zip(&[("a.bin", &[1, 2, 4]), ("c.bin", &[0, 0])]);
+        let err = find_rom_set(&mut source, &[manifest()]).err().unwrap();
+        assert_eq!(
+            "no known ROM set found in 'test.zip', the closest is 'test' (Test set):\n    \
+             a.bin: CRC32 is cbd815be, expected 55bc801d\n    b.bin: missing\n    \
+             c.bin (CRC32 41d912ff): not part of the set",
+            err.to_string()
+        );
+        assert!(ZipSource::new("bad.zip".to_owned(), Cursor::new(vec![0; 8])).is_err());
+    }
+}